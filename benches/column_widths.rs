@@ -0,0 +1,64 @@
+use std::{fs, io::Write};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use csv_tui::{
+    controller::default_controller::calculate_current_grid_columns,
+    model::{
+        app_state_model::AppStateModel,
+        csv_model::{CsvDelimiter, CsvModel, CsvOptions}
+    }
+};
+
+const ROW_COUNTS: [usize; 3] = [1_000, 100_000, 500_000];
+
+/// Writes a file of row_count rows and loads all of it.
+fn load_rows(row_count: usize) -> CsvModel {
+    let path = std::env::temp_dir()
+        .join(format!("csv_tui_bench_{}_{}.csv", std::process::id(), row_count))
+        .to_string_lossy()
+        .to_string();
+    let mut file = std::io::BufWriter::new(fs::File::create(&path).unwrap());
+    for row in 0..row_count {
+        writeln!(file, "{},value {},{}", row, row % 97, "x".repeat(row % 13)).unwrap();
+    }
+    drop(file);
+    let options = CsvOptions {
+        delimiter: Some(CsvDelimiter::Comma),
+        ..CsvOptions::default()
+    };
+    let mut model = CsvModel::load_file(&path, &options).unwrap();
+    while model.is_loading() {
+        model.load_more_rows().unwrap();
+    }
+    fs::remove_file(&path).unwrap();
+    model
+}
+
+fn column_widths(c: &mut Criterion) {
+    let mut group = c.benchmark_group("column widths");
+    for row_count in ROW_COUNTS {
+        let mut model = load_rows(row_count);
+        let app_state = AppStateModel::default();
+        group.bench_with_input(BenchmarkId::new("get_col_max_width", row_count),
+                               &row_count,
+                               |b, _| b.iter(|| model.get_col_max_width(1)));
+        group.bench_with_input(BenchmarkId::new("calculate_current_grid_columns", row_count),
+                               &row_count,
+                               |b, _| b.iter(|| calculate_current_grid_columns(&app_state, &model, 200)));
+        // an edit to the widest value of the column, and its undo, each
+        // change the width of the column
+        group.bench_with_input(BenchmarkId::new("edit_then_get_col_max_width", row_count),
+                               &row_count,
+                               |b, _| b.iter(|| {
+                                   model.set_cell_value(row_count - 1, 2, "y".repeat(40));
+                                   let width = model.get_col_max_width(2);
+                                   model.undo();
+                                   width
+                               }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, column_widths);
+criterion_main!(benches);
@@ -1,42 +1,77 @@
-use std::io;
+use std::{
+    io,
+    ops::Range,
+    time::Duration
+};
 use tui::{
     backend::Backend,
     Terminal, Frame};
 use crossterm::event::{
         self, 
         KeyCode, 
+        KeyEvent,
+        KeyModifiers,
         Event};
 
 use crate::model::{
     app_state_model::AppStateModel,
-    csv_model::CsvModel,
+    csv_model::{
+        CsvModel,
+        EditLocation,
+        describe_byte},
+    filter_model::FilterExpression,
+    command_model::{
+        Command,
+        SetOption},
+    search_model::{
+        SearchPattern,
+        ReplaceScope},
     utils_model::{
-        RunningMode,
         InputMode,
-        Size, Position
+        Size, Position,
+        ListPanel,
+        ViewState
     },
+    problem_model::{
+        MalformedFile,
+        RejectedFile
+    }
 };
 
+/// How often the rows read by a background load are added while waiting
+/// for a key press.
+const LOAD_REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often a followed file is checked for new records.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How many items either side of the selected one are given to a list
+/// panel, which is more than fit in it.
+const PANEL_WINDOW: usize = 100;
+
+/// Signature shared by the render_ui functions of each view.
+pub type RenderFunction<B> = fn(&ViewState, &mut Frame<B>);
+
 pub fn run<B: Backend>(
             app_data: &mut CsvModel,
             app_state: &mut AppStateModel,
-            ui_render_function: fn(Vec<Vec<String>>,
-                                   &Size,
-                                   Size,
-                                   Vec<usize>,
-                                   &Position,
-                                   &Position,
-                                   &InputMode,
-                                   &RunningMode,
-                                   &str,
-                                   &Option<String>,
-                                   bool,
-                                   &mut Frame<B>),
+            ui_render_function: RenderFunction<B>,
             terminal: &mut Terminal<B>,
             ) -> io::Result<()> {
     
     loop {
 
+        if app_data.is_loading() {
+            if let Err(err) = app_data.load_more_rows() {
+                app_state.set_status_msg(Some(match MalformedFile::from_error(&err) {
+                    Some(_) => format!(
+                        "Stopped loading the file: {}. Press p to list the problems, or load it leniently with :set lenient and :e!",
+                        err),
+                    None => format!("Stopped loading the file: {}", err)
+                }));
+            }
+        }
+
         let info_row_height = 1;
         let input_box_height = 3;
         let col_width: usize = 5;
@@ -44,12 +79,10 @@ pub fn run<B: Backend>(
         let border_width = 1;
         let row_num_col_width = col_width as u16 + 1;
         let terminal_width = terminal.size()?.width;
+        // the input box has a border on either side of the input
+        app_state.scroll_input_to_cursor(terminal_width.saturating_sub(border_width * 2) as usize);
         let width_to_remove = (border_width*2) + row_num_col_width;
-        let data_width = if terminal_width > width_to_remove {
-            terminal_width - width_to_remove
-        } else { 
-            0
-        };
+        let data_width = terminal_width.saturating_sub(width_to_remove);
        
         let terminal_height = terminal.size()?.height;
         let index_row_height = 1;
@@ -57,11 +90,7 @@ pub fn run<B: Backend>(
                                 + input_box_height 
                                 + (border_width * 2)
                                 + index_row_height;
-        let data_height = if terminal_height > height_to_remove {
-            terminal_height - height_to_remove
-        } else {
-            0
-        };
+        let data_height = terminal_height.saturating_sub(height_to_remove);
 
         let grid_size = Size { 
             width: calculate_current_grid_columns(app_state, 
@@ -73,9 +102,14 @@ pub fn run<B: Backend>(
 
         let input_mode = app_state.get_input_mode();
         let corner_pos = app_state.get_corner_pos();
+        let frozen_cols = app_state.get_frozen_cols();
+        let scrolling_width = grid_size.width.saturating_sub(frozen_cols);
 
         let mut column_widths : Vec<usize> = Vec::new();
-        for col in (corner_pos.col)..(corner_pos.col + grid_size.width) {
+        for col in 0..frozen_cols {
+            column_widths.push(app_data.get_col_max_width(col));
+        }
+        for col in (corner_pos.col)..(corner_pos.col + scrolling_width) {
             column_widths.push(app_data.get_col_max_width(col));
         } 
 
@@ -83,32 +117,75 @@ pub fn run<B: Backend>(
         let app_is_saved = app_data.is_in_saved_state();
         let app_filename = app_data.get_filename();
 
-        let data_slice = app_data.get_data_segment(&corner_pos, &grid_size);
-
-        terminal.draw(|f| {
-            ui_render_function(data_slice, 
-                      &grid_size,
-                      app_data.get_data_size(),
-                      column_widths,
-                      &corner_pos,
-                      &relative_pos,
-                      input_mode,
-                      app_state.get_running_mode(),
-                      app_state.get_input(),
-                      app_filename,
-                      app_is_saved,
-                      f)
-        })?;
+        let (data_slice, header_slice) = get_grid_slices(app_state, 
+                                                         app_data, 
+                                                         &grid_size);
+        let row_numbers = app_data.get_row_numbers(corner_pos.row, data_slice.len());
+        // while normalising is previewed, the rows it changes are marked
+        let marked_width = match app_state.get_normalise_preview() {
+            Some(preview) => preview.width,
+            None => app_data.get_modal_row_len()
+        };
+        let row_marks = app_data.get_row_marks(corner_pos.row, data_slice.len(), marked_width);
+        let list_panel = match input_mode {
+            InputMode::ViewingProblems => Some(get_problem_panel(app_state, app_data)),
+            InputMode::ConfirmNormalise => Some(get_normalise_panel(app_state, app_data)),
+            _ => None
+        };
+
+        let view_state = ViewState {
+            data_slice: &data_slice,
+            header_slice: header_slice.as_deref(),
+            grid_size,
+            data_size: app_data.get_data_size(),
+            filtered_row_count: app_data.get_filtered_row_count(),
+            ragged_row_count: app_data.get_ragged_row_count(),
+            column_widths: &column_widths,
+            row_numbers: &row_numbers,
+            row_marks: &row_marks,
+            corner_pos,
+            relative_pos,
+            frozen_cols,
+            search_pattern: app_state.get_search_pattern(),
+            search_options: app_state.get_search_options(),
+            selected_range: app_state.get_selected_range(),
+            replace_scope: app_state.get_replace_scope(),
+            input_mode,
+            running_mode: app_state.get_running_mode(),
+            current_input: app_state.get_input(),
+            input_cursor: app_state.get_input_cursor(),
+            input_scroll: app_state.get_input_scroll(),
+            filename: app_filename,
+            is_saved: app_is_saved,
+            status_msg: app_state.get_status_msg(),
+            load_progress: app_data.get_load_progress(),
+            list_panel: list_panel.as_ref()
+        };
+        terminal.draw(|f| ui_render_function(&view_state, f))?;
+        // large files are read in the background, so waiting for a key press
+        // is broken off to show the rows read since
+        if app_data.is_loading() && !event::poll(LOAD_REFRESH_INTERVAL)? {
+            continue;
+        }
+        // while following a file, waiting for a key press is broken off to 
+        // check the file for new records
+        if app_state.is_following() 
+            && *app_state.get_input_mode() == InputMode::Normal
+            && !event::poll(FOLLOW_INTERVAL)? {
+            follow_file(app_state, app_data, &grid_size);
+            continue;
+        }
         if let Event::Key(key) = event::read()? {
-            match input_mode {
+            // status messages are only shown until the next key press
+            app_state.set_status_msg(None);
+            match app_state.get_input_mode() {
                 InputMode::Normal => match key.code {
                     KeyCode::Char('e') => {
                         app_state.set_input_mode(InputMode::Editing);
-                        let data_row_pos = corner_pos.row + relative_pos.row;
-                        let data_col_pos = corner_pos.col + relative_pos.col;
+                        let data_pos = app_state.get_selected_pos();
                         
                         app_state.append_str_current_input(
-                            app_data.get_cell_value(data_row_pos, data_col_pos));
+                            &app_data.get_cell_value(data_pos.row, data_pos.col));
                     },
                     KeyCode::Char('q') => {
                         app_state.set_input_mode(InputMode::Quiting);
@@ -119,15 +196,16 @@ pub fn run<B: Backend>(
                         } else {
                             match app_filename {
                                 Some(_) => {
-                                    match app_data.save_data_to_file() {
-                                        Ok(_) => {
+                                    match save_or_confirm(app_state, app_data, false) {
+                                        Some(Ok(_)) => {
                                             app_data.set_saved(true); 
                                             app_state.set_input_mode(InputMode::Saved);
                                         },
-                                        Err(_) => {
+                                        Some(Err(_)) => {
                                             app_data.set_saved(false);
                                             app_state.set_input_mode(InputMode::SavedFailed);
-                                        }
+                                        },
+                                        None => {}
                                     }
                                 },
                                 None => {
@@ -153,100 +231,414 @@ pub fn run<B: Backend>(
                     KeyCode::Down | KeyCode::Char('j') => {
                         app_state.increment_relative_pos_row();
                     },
-                    KeyCode::Char('H') => {
-                        if corner_pos.col > 0 {
-                            let prev_grid_size = Size {
-                                width: calculate_prev_grid_columns(app_state, 
-                                                                   app_data, 
-                                                                   data_width as usize),
-                                height: data_height as usize
-                            };
-                            app_state.remove_from_corner_pos_col(prev_grid_size.width);
-                        }
+                    KeyCode::Char('H') if corner_pos.col > frozen_cols => {
+                        let prev_grid_size = Size {
+                            width: calculate_prev_grid_columns(app_state, 
+                                                               app_data, 
+                                                               data_width as usize),
+                            height: data_height as usize
+                        };
+                        app_state.remove_from_corner_pos_col(prev_grid_size.width);
                     },
                     KeyCode::Char('L') => {
-                        app_state.add_to_corner_pos_col(grid_size.width);
-                    },
-                    KeyCode::Char('K') => {
-                        if corner_pos.row > 0 {
-                            let prev_grid_size = Size {
-                                width: calculate_prev_grid_columns(app_state, 
-                                                                   app_data, 
-                                                                   data_width as usize),
-                                height: data_height as usize
-                            };
-                            app_state.remove_from_corner_pos_row(prev_grid_size.height);
-                        }
+                        app_state.add_to_corner_pos_col(scrolling_width);
+                    },
+                    KeyCode::Char('K') if corner_pos.row > 0 => {
+                        let prev_grid_size = Size {
+                            width: calculate_prev_grid_columns(app_state, 
+                                                               app_data, 
+                                                               data_width as usize),
+                            height: data_height as usize
+                        };
+                        app_state.remove_from_corner_pos_row(prev_grid_size.height);
                     },
                     KeyCode::Char('J') => {
                         app_state.add_to_corner_pos_row(grid_size.height);
                     },
+                    KeyCode::Char('u') => {
+                        if let Some(location) = app_data.undo() {
+                            focus_edit_location(app_state, location, &grid_size);
+                        }
+                    },
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        if let Some(location) = app_data.redo() {
+                            focus_edit_location(app_state, location, &grid_size);
+                        }
+                    },
                     KeyCode::Char('r') => {
                         app_state.set_input_mode(InputMode::SelectingRow);
                     },
                     KeyCode::Char('c') => {
                         app_state.set_input_mode(InputMode::SelectingCol);
                     },
-                    _ => {}
-                },
-                InputMode::Editing => match key.code {
-                    KeyCode::Enter => {
-                        /*
-                         * draing the current input value from the app state, 
-                         * then set the value of the current cell to the value
-                         * of the input.
-                         */
-                        let row = app_state.get_corner_pos().row 
-                            + app_state.get_relative_pos().row;
-                        let col = app_state.get_corner_pos().col
-                            + app_state.get_relative_pos().col;
-                        app_data.set_cell_value(row, col, app_state.drain_input());
-                        app_state.set_input_mode(InputMode::Normal);
+                    KeyCode::Char('/') => {
+                        app_state.set_search_origin(app_state.get_selected_pos());
+                        app_state.clear_input();
+                        app_state.set_input_mode(InputMode::Searching);
                     },
-                    KeyCode::Char(char) => {
-                        app_state.append_char_current_input(char);
+                    KeyCode::Char('n') => {
+                        jump_to_search_match(app_state, app_data, true, &grid_size);
                     },
-                    KeyCode::Backspace => {
-                        app_state.pop_current_input();
+                    KeyCode::Char('N') => {
+                        jump_to_search_match(app_state, app_data, false, &grid_size);
+                    },
+                    KeyCode::Esc if app_data.is_loading() => {
+                        app_data.cancel_loading();
+                        app_state.set_status_msg(Some(format!(
+                                    "Stopped loading the file after {} rows, so it can only be saved as a new file.",
+                                    app_data.get_data_size().height)));
                     },
                     KeyCode::Esc => {
-                        app_state.set_input_mode(InputMode::Normal);
+                        app_state.set_search_pattern(None);
+                    },
+                    KeyCode::Char('p') => {
+                        if app_state.get_rejected_file().is_none() 
+                            && app_data.get_problems().is_empty() {
+                            app_state.set_status_msg(Some(String::from(
+                                        "No problems were found when loading the file.")));
+                        } else {
+                            app_state.set_panel_pos(0);
+                            app_state.set_input_mode(InputMode::ViewingProblems);
+                        }
+                    },
+                    KeyCode::Char(':') => {
+                        app_state.clear_input();
+                        app_state.set_input_mode(InputMode::Command);
+                    },
+                    KeyCode::Char('|') => {
+                        app_state.clear_input();
+                        if let Some(filter) = app_data.get_filter() {
+                            app_state.append_str_current_input(filter.get_text());
+                        }
+                        app_state.set_input_mode(InputMode::Filtering);
+                    },
+                    KeyCode::Char('v') => {
+                        app_state.set_selection_anchor(Some(app_state.get_selected_pos()));
+                        app_state.set_input_mode(InputMode::SelectingRange);
+                    },
+                    KeyCode::Char('R') => {
+                        app_state.set_replace_scope(ReplaceScope::Table);
+                        app_state.clear_input();
+                        app_state.set_input_mode(InputMode::ReplacingFind);
+                    },
+                    KeyCode::Char('o') | KeyCode::Char('O') => {
+                        let descending = key.code == KeyCode::Char('O');
+                        let data_pos = app_state.get_selected_pos();
+                        let new_row = app_data.sort_by_column(data_pos.col, 
+                                                              descending, 
+                                                              data_pos.row);
+                        app_state.focus_position(Position { row: new_row, col: data_pos.col }, 
+                                                 &grid_size);
+                        app_state.set_status_msg(Some(format!(
+                                    "Sorted rows by column {}, {}.",
+                                    data_pos.col,
+                                    match descending {
+                                        true => "descending",
+                                        false => "ascending"
+                                    })));
+                    },
+                    KeyCode::Char('t') => {
+                        app_data.set_has_header(!app_data.has_header());
+                    },
+                    KeyCode::Char('f') => {
+                        let data_pos = app_state.get_selected_pos();
+                        app_state.set_frozen_cols(data_pos.col + 1);
+                        app_state.focus_position(data_pos, &grid_size);
+                    },
+                    KeyCode::Char('F') => {
+                        let data_pos = app_state.get_selected_pos();
+                        app_state.set_frozen_cols(0);
+                        app_state.focus_position(data_pos, &grid_size);
                     },
                     _ => {}
                 },
+                InputMode::Editing => edit_cell(key, app_state, app_data),
                 InputMode::Saving => match key.code {
                     KeyCode::Enter => {
                         let input = app_state.drain_input();
-                        app_data.set_filename(match input.len() > 0 {
+                        app_data.set_filename(match !input.is_empty() {
                             true => Some(input),
                             false => None
                         });
-                        match app_data.save_data_to_file() {
-                            Ok(_) => {
+                        match save_or_confirm(app_state, app_data, false) {
+                            Some(Ok(_)) => {
                                 app_data.set_saved(true);
                                 app_state.set_input_mode(InputMode::Saved);
                             },
-                            Err(_) => {
+                            Some(Err(_)) => {
                                 app_data.set_saved(false);
                                 app_state.set_input_mode(InputMode::SavedFailed);
+                            },
+                            None => {}
+                        }
+                    },
+                    KeyCode::Esc => {
+                        app_state.clear_input();
+                        app_state.set_input_mode(InputMode::Normal);
+                    },
+                    _ => {
+                        edit_input(app_state, key);
+                    }
+                },
+                InputMode::Searching => match key.code {
+                    KeyCode::Enter => {
+                        app_state.clear_input();
+                        app_state.set_input_mode(InputMode::Normal);
+                    },
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::ALT) => {
+                        app_state.toggle_search_case_insensitive();
+                        update_search(app_state, app_data, &grid_size);
+                    },
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::ALT) => {
+                        app_state.toggle_search_regex();
+                        update_search(app_state, app_data, &grid_size);
+                    },
+                    KeyCode::Esc => {
+                        app_state.clear_input();
+                        app_state.set_search_pattern(None);
+                        app_state.focus_position(app_state.get_search_origin(), &grid_size);
+                        app_state.set_input_mode(InputMode::Normal);
+                    },
+                    _ => {
+                        if edit_input(app_state, key) {
+                            update_search(app_state, app_data, &grid_size);
+                        }
+                    }
+                },
+                InputMode::Filtering => match key.code {
+                    KeyCode::Enter => {
+                        let filter = match app_state.get_input().trim().is_empty() {
+                            true => Ok(None),
+                            false => FilterExpression::parse(app_state.get_input(), 
+                                                             app_data.get_header_row()
+                                                                .as_deref())
+                                .map(Some)
+                        };
+                        match filter {
+                            Ok(filter) => {
+                                app_data.set_filter(filter);
+                                app_state.clear_input();
+                                let col = app_state.get_selected_pos().col;
+                                app_state.focus_position(Position { row: 0, col }, 
+                                                         &grid_size);
+                                app_state.set_input_mode(InputMode::Normal);
+                            },
+                            Err(msg) => {
+                                app_state.set_status_msg(Some(msg));
                             }
                         }
                     },
-                    KeyCode::Char(char) => {
-                        app_state.append_char_current_input(char);
+                    KeyCode::Esc => {
+                        app_state.clear_input();
+                        app_state.set_input_mode(InputMode::Normal);
                     },
-                    KeyCode::Backspace => {
-                        app_state.pop_current_input();
+                    _ => {
+                        edit_input(app_state, key);
+                    }
+                },
+                InputMode::Command => match key.code {
+                    KeyCode::Enter => {
+                        let input = app_state.drain_input();
+                        app_state.set_input_mode(InputMode::Normal);
+                        let result = Command::parse(&input).and_then(|command| {
+                            run_command(command, app_state, app_data, &grid_size)
+                        });
+                        match result {
+                            Ok(true) => return Ok(()),
+                            Ok(false) => {},
+                            Err(msg) => app_state.set_status_msg(Some(msg))
+                        }
+                    },
+                    KeyCode::Backspace if app_state.get_input().is_empty() => {
+                        app_state.set_input_mode(InputMode::Normal);
                     },
                     KeyCode::Esc => {
                         app_state.clear_input();
                         app_state.set_input_mode(InputMode::Normal);
                     },
+                    _ => {
+                        edit_input(app_state, key);
+                    }
+                },
+                InputMode::SelectingRange => match key.code {
+                    KeyCode::Left | KeyCode::Char('h') => {
+                        app_state.decrement_relative_pos_col();
+                    },
+                    KeyCode::Right | KeyCode::Char('l') => {
+                        app_state.increment_relative_pos_col();
+                    },
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        app_state.decrement_relative_pos_row();
+                    },
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        app_state.increment_relative_pos_row();
+                    },
+                    KeyCode::Char('R') => {
+                        if let Some(range) = app_state.get_selected_range() {
+                            app_state.set_replace_scope(range);
+                        }
+                        app_state.clear_input();
+                        app_state.set_input_mode(InputMode::ReplacingFind);
+                    },
+                    KeyCode::Esc => {
+                        app_state.set_selection_anchor(None);
+                        app_state.set_input_mode(InputMode::Normal);
+                    },
                     _ => {}
                 },
+                InputMode::ReplacingFind => match key.code {
+                    KeyCode::Enter if !app_state.get_input().is_empty() => {
+                        match SearchPattern::new(app_state.get_input(), 
+                                                 app_state.get_search_options()) {
+                            Ok(pattern) => {
+                                app_state.set_replace_pattern(Some(pattern));
+                                app_state.clear_input();
+                                app_state.set_input_mode(InputMode::ReplacingWith);
+                            },
+                            Err(_) => {
+                                app_state.set_status_msg(
+                                    Some(String::from("Invalid regex, unable to replace.")));
+                            }
+                        }
+                    },
+                    KeyCode::Tab => {
+                        app_state.cycle_replace_scope();
+                    },
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::ALT) => {
+                        app_state.toggle_search_case_insensitive();
+                    },
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::ALT) => {
+                        app_state.toggle_search_regex();
+                    },
+                    KeyCode::Esc => {
+                        finish_replace(app_state);
+                    },
+                    _ => {
+                        edit_input(app_state, key);
+                    }
+                },
+                InputMode::ReplacingWith => match key.code {
+                    KeyCode::Enter => {
+                        let replacement = app_state.drain_input();
+                        let count = match app_state.get_replace_pattern() {
+                            Some(pattern) => app_data.count_replacements(
+                                pattern, 
+                                &replacement, 
+                                app_state.get_replace_scope()),
+                            None => 0
+                        };
+                        app_state.set_replacement(replacement);
+                        if count > 0 {
+                            app_state.set_status_msg(
+                                Some(format!("{} cells will be changed. ", count)));
+                            app_state.set_input_mode(InputMode::ReplaceConfirm);
+                        } else {
+                            finish_replace(app_state);
+                            app_state.set_status_msg(
+                                Some(String::from("No cells to replace.")));
+                        }
+                    },
+                    KeyCode::Esc => {
+                        finish_replace(app_state);
+                    },
+                    _ => {
+                        edit_input(app_state, key);
+                    }
+                },
+                InputMode::ReplaceConfirm => match key.code {
+                    KeyCode::Char('y') | KeyCode::Char('Y') => {
+                        let count = match app_state.get_replace_pattern() {
+                            Some(pattern) => app_data.replace_matches(
+                                pattern,
+                                app_state.get_replacement(),
+                                app_state.get_replace_scope()),
+                            None => 0
+                        };
+                        finish_replace(app_state);
+                        app_state.set_status_msg(Some(format!("Replaced {} cells.", count)));
+                    },
+                    KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                        finish_replace(app_state);
+                    },
+                    _ => {}
+                },
+                InputMode::ConfirmOverwrite => {
+                    confirm_overwrite(key.code, app_state, app_data, &grid_size);
+                },
                 InputMode::Saved | InputMode::SavedFailed => {
                     app_state.set_input_mode(InputMode::Normal);
                 },
+                InputMode::ViewingProblems => {
+                    let problem_pos = app_state.get_panel_pos();
+                    let problem_count = match app_state.get_rejected_file() {
+                        Some(rejected) => rejected.problems.len(),
+                        None => app_data.get_problems().len()
+                    };
+                    match key.code {
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            app_state.set_panel_pos(problem_pos.saturating_sub(1));
+                        },
+                        KeyCode::Down | KeyCode::Char('j') if problem_pos + 1 < problem_count => {
+                            app_state.set_panel_pos(problem_pos + 1);
+                        },
+                        // the rows of a file which wasn't loaded can't be gone to
+                        KeyCode::Enter if app_state.get_rejected_file().is_none() => {
+                            if let Some(problem) = app_data.get_problems().get(problem_pos) {
+                                let row = app_data.get_grid_row_of_data_row(problem.row);
+                                let col = app_state.get_selected_pos().col;
+                                app_state.focus_position(Position { row, col }, &grid_size);
+                            }
+                            app_state.set_input_mode(InputMode::Normal);
+                        },
+                        KeyCode::Esc => {
+                            app_state.set_rejected_file(None);
+                            app_state.set_input_mode(InputMode::Normal);
+                        },
+                        _ => {}
+                    }
+                },
+                InputMode::ConfirmNormalise => {
+                    let panel_pos = app_state.get_panel_pos();
+                    let row_count = match app_state.get_normalise_preview() {
+                        Some(preview) => preview.rows.len(),
+                        None => 0
+                    };
+                    match key.code {
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            app_state.set_panel_pos(panel_pos.saturating_sub(1));
+                        },
+                        KeyCode::Down | KeyCode::Char('j') if panel_pos + 1 < row_count => {
+                            app_state.set_panel_pos(panel_pos + 1);
+                        },
+                        // the preview stays open, so the row can be looked at
+                        // before normalising
+                        KeyCode::Enter => {
+                            let data_row = app_state.get_normalise_preview()
+                                .and_then(|preview| preview.rows.get(panel_pos).copied());
+                            if let Some(data_row) = data_row {
+                                let row = app_data.get_grid_row_of_data_row(data_row);
+                                let col = app_state.get_selected_pos().col;
+                                app_state.focus_position(Position { row, col }, &grid_size);
+                            }
+                        },
+                        KeyCode::Char('y') | KeyCode::Char('Y') => {
+                            if let Some(preview) = app_state.get_normalise_preview() {
+                                let count = app_data.normalise_rows(preview);
+                                app_state.set_status_msg(Some(format!(
+                                            "Normalised {} rows to have {} values.",
+                                            count,
+                                            preview.width)));
+                            }
+                            app_state.set_normalise_preview(None);
+                            app_state.set_input_mode(InputMode::Normal);
+                        },
+                        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                            app_state.set_normalise_preview(None);
+                            app_state.set_input_mode(InputMode::Normal);
+                        },
+                        _ => {}
+                    }
+                },
                 InputMode::Quiting => {
                    if app_is_saved {
                        return Ok(());
@@ -265,63 +657,58 @@ pub fn run<B: Backend>(
                 InputMode::QuitSaving => {
                     match app_filename {
                         Some(_) => {
-                            match app_data.save_data_to_file() {
-                                Ok(_) => {
+                            match save_or_confirm(app_state, app_data, true) {
+                                Some(Ok(_)) => {
                                     app_data.set_saved(true);
                                     app_state.set_input_mode(InputMode::Quiting);
                                 },
-                                Err(_) => {
+                                Some(Err(_)) => {
                                     app_data.set_saved(false);
                                     app_state.set_input_mode(InputMode::SavedFailed);
-                                }
+                                },
+                                None => {}
                             }
                         },
                         None => { 
                             match key.code {
                                 KeyCode::Enter => {
                                     let input = app_state.drain_input();
-                                    app_data.set_filename(match input.len() > 0 {
+                                    app_data.set_filename(match !input.is_empty() {
                                         true => Some(input),
                                         false => None
                                     });
-                                    match app_data.save_data_to_file() {
-                                        Ok(_) => {
+                                    match save_or_confirm(app_state, app_data, true) {
+                                        Some(Ok(_)) => {
                                             app_data.set_saved(true);
                                             app_state.set_input_mode(InputMode::Quiting);
                                         },
-                                        Err(_) => {
+                                        Some(Err(_)) => {
                                             app_data.set_saved(false);
                                             app_state.set_input_mode(InputMode::SavedFailed);
-                                        }
+                                        },
+                                        None => {}
                                     }
                                 },
-                                KeyCode::Char(char) => {
-                                    app_state.append_char_current_input(char);
-                                },
-                                KeyCode::Backspace => {
-                                    app_state.pop_current_input();
-                                },
                                 KeyCode::Esc => {
                                     app_state.clear_input();
                                     app_state.set_input_mode(InputMode::Normal);
                                 },
-                                _ => {}
+                                _ => {
+                                    edit_input(app_state, key);
+                                }
                             }
                         }
                     }
                 },
                 InputMode::SelectingRow => {
-                    let row = app_state.get_corner_pos().row
-                        + app_state.get_relative_pos().row;
+                    let row = app_state.get_selected_pos().row;
                     match key.code {
                         KeyCode::Char('i') => {
                             app_data.insert_row(row);
-                            app_data.set_saved(false);
                             app_state.set_input_mode(InputMode::Normal);
                         },
                         KeyCode::Char('r') => {
                             app_data.remove_row(row);
-                            app_data.set_saved(false);
                             app_state.set_input_mode(InputMode::Normal);
                         },
                         KeyCode::Esc => {
@@ -331,17 +718,14 @@ pub fn run<B: Backend>(
                     }
                 },
                 InputMode::SelectingCol => {
-                    let col = app_state.get_corner_pos().col
-                        + app_state.get_relative_pos().col;
+                    let col = app_state.get_selected_pos().col;
                     match key.code {
                         KeyCode::Char('i') => {
                             app_data.insert_col(col);
-                            app_data.set_saved(false);
                             app_state.set_input_mode(InputMode::Normal);
                         },
                         KeyCode::Char('r') => {
                             app_data.remove_col(col);
-                            app_data.set_saved(false);
                             app_state.set_input_mode(InputMode::Normal);
                         },
                         KeyCode::Esc => {
@@ -354,44 +738,734 @@ pub fn run<B: Backend>(
         }
     }
 
-    fn calculate_current_grid_columns(app_state: &AppStateModel,
-                                      app_data: &CsvModel,
-                                      area_width: usize) -> usize {
-
+    fn calculate_prev_grid_columns(app_state: &AppStateModel, 
+                                        app_data: &CsvModel, 
+                                        area_width: usize) -> usize {
         let mut num_cols = 0;
-        let mut total_widths = 0;
+        let mut total_widths = calculate_frozen_width(app_state, app_data);
         let mut current_col = app_state.get_corner_pos().col;
 
         loop {
             total_widths += app_data.get_col_max_width(current_col) + 1;
-            if total_widths < area_width {
+            if total_widths < area_width && current_col > app_state.get_frozen_cols() {
                 num_cols += 1;
-                current_col += 1;
+                current_col -= 1;
             } else {
                 break;
             }
         }
 
-        return num_cols;
+        num_cols
     }
 
-    fn calculate_prev_grid_columns(app_state: &AppStateModel, 
-                                        app_data: &CsvModel, 
-                                        area_width: usize) -> usize {
-        let mut num_cols = 0;
-        let mut total_widths = 0;
-        let mut current_col = app_state.get_corner_pos().col;
+    /// Returns the data and header slices for the grid, with the frozen 
+    /// columns placed before the scrolling columns.
+    fn get_grid_slices(app_state: &AppStateModel,
+                       app_data: &CsvModel,
+                       grid_size: &Size) -> (Vec<Vec<String>>, Option<Vec<String>>) {
+        let corner_pos = app_state.get_corner_pos();
+        let frozen_cols = app_state.get_frozen_cols();
+        let scrolling_size = Size {
+            width: grid_size.width.saturating_sub(frozen_cols),
+            height: grid_size.height
+        };
+        let mut data_slice = app_data.get_data_segment(&corner_pos, &scrolling_size);
+        let mut header_slice = app_data.get_header_segment(&corner_pos, &scrolling_size);
+        if frozen_cols == 0 {
+            return (data_slice, header_slice);
+        }
 
-        loop {
-            total_widths += app_data.get_col_max_width(current_col) + 1;
-            if total_widths < area_width && current_col > 0 {
-                num_cols += 1;
-                current_col -= 1;
-            } else {
-                break;
+        let frozen_corner = Position { row: corner_pos.row, col: 0 };
+        let frozen_size = Size { width: frozen_cols, height: grid_size.height };
+        let frozen_slice = app_data.get_data_segment(&frozen_corner, &frozen_size);
+        /*
+         * short rows are padded so that the scrolling columns always start 
+         * after the frozen ones.
+         */
+        for (row, mut frozen_row) in data_slice.iter_mut().zip(frozen_slice) {
+            frozen_row.resize(frozen_cols, String::new());
+            frozen_row.append(row);
+            *row = frozen_row;
+        }
+        if let Some(scrolling_header) = header_slice {
+            let mut header_row = app_data.get_header_segment(&frozen_corner, &frozen_size)
+                .unwrap_or_default();
+            header_row.resize(frozen_cols, String::new());
+            header_row.extend(scrolling_header);
+            header_slice = Some(header_row);
+        }
+
+        (data_slice, header_slice)
+    }
+
+    /// Rebuilds the search pattern from the current input, and moves the  
+    /// cursor to the first match at or after the position the search started
+    /// from. The cursor returns to that position if nothing matches.
+    fn update_search(app_state: &mut AppStateModel,
+                     app_data: &CsvModel,
+                     grid_size: &Size) {
+        let origin = app_state.get_search_origin();
+        let pattern = match app_state.get_input().is_empty() {
+            true => None,
+            false => SearchPattern::new(app_state.get_input(), 
+                                        app_state.get_search_options()).ok()
+        };
+        let found_pos = match &pattern {
+            Some(search_pattern) => app_data.find_match(search_pattern, origin, true, true),
+            None => None
+        };
+        app_state.set_search_pattern(pattern);
+        app_state.focus_position(found_pos.unwrap_or(origin), grid_size);
+    }
+
+    /// Moves the cursor to the next or previous match of the current search,
+    /// wrapping around the ends of the data.
+    fn jump_to_search_match(app_state: &mut AppStateModel,
+                            app_data: &CsvModel,
+                            forward: bool,
+                            grid_size: &Size) {
+        let found_pos = match app_state.get_search_pattern() {
+            Some(pattern) => app_data.find_match(pattern, 
+                                                 app_state.get_selected_pos(), 
+                                                 forward, 
+                                                 false),
+            None => None
+        };
+        if let Some(pos) = found_pos {
+            app_state.focus_position(pos, grid_size);
+        }
+    }
+
+    /// Runs a command entered on the command line, returning true if the 
+    /// application should quit. Errors are returned as a message to show.
+    fn run_command(command: Command,
+                   app_state: &mut AppStateModel,
+                   app_data: &mut CsvModel,
+                   grid_size: &Size) -> Result<bool, String> {
+        match command {
+            Command::Write(filename) => {
+                write_file(app_state, app_data, filename, false)?;
+                Ok(false)
+            },
+            Command::WriteQuit(filename) => {
+                write_file(app_state, app_data, filename, true)
+            },
+            Command::Quit { force } => {
+                if !force && !app_data.is_in_saved_state() {
+                    return Err(String::from(
+                            "No write since last change, add ! to override."));
+                }
+                Ok(true)
+            },
+            Command::Edit { filename, force } => {
+                if !force && !app_data.is_in_saved_state() {
+                    return Err(String::from(
+                            "No write since last change, add ! to override."));
+                }
+                match filename {
+                    Some(filename) => {
+                        // the file is read with the settings of the current
+                        // one, rather than detecting them
+                        let mut loaded = match CsvModel::load_file(&filename, 
+                                                                   &app_data.get_options()) {
+                            Ok(loaded) => loaded,
+                            Err(err) => {
+                                reject_file(app_state, &filename, &err);
+                                return Err(format!("Unable to open {}: {}", filename, err));
+                            }
+                        };
+                        loaded.set_has_header(app_data.has_header());
+                        loaded.set_make_backups(app_data.makes_backups());
+                        *app_data = loaded;
+                        app_state.set_frozen_cols(0);
+                    },
+                    None => {
+                        if let Err(err) = app_data.reload() {
+                            if let Some(filename) = app_data.get_filename() {
+                                reject_file(app_state, filename, &err);
+                            }
+                            return Err(format!("Unable to reload: {}", err));
+                        }
+                    }
+                }
+                app_state.set_rejected_file(None);
+                app_state.set_search_pattern(None);
+                app_state.focus_position(Position { row: 0, col: 0 }, grid_size);
+                app_state.set_status_msg(Some(format!("Loaded, {}.{}", 
+                                                      app_data.describe_dialect(),
+                                                      describe_problems(app_data))));
+                Ok(false)
+            },
+            Command::Goto { row, col } => {
+                let col = col.unwrap_or(app_state.get_selected_pos().col);
+                let row = app_data.get_grid_row(row);
+                app_state.focus_position(Position { row, col }, grid_size);
+                Ok(false)
+            },
+            Command::Set(SetOption::Delimiter(delimiter)) => {
+                app_state.set_status_msg(Some(format!(
+                            "Delimiter set to {}, reload with :e! to read the file with it.", 
+                            delimiter.get_name())));
+                app_data.set_delimiter(delimiter);
+                Ok(false)
+            },
+            Command::Set(SetOption::Quote(quote)) => {
+                app_state.set_status_msg(Some(format!(
+                            "Quote set to {}, reload with :e! to read the file with it.", 
+                            describe_byte(quote))));
+                app_data.set_quote(quote);
+                Ok(false)
+            },
+            Command::Set(SetOption::Escape(escape)) => {
+                app_state.set_status_msg(Some(format!(
+                            "Escape set to {}, reload with :e! to read the file with it.", 
+                            match escape {
+                                Some(escape_char) => describe_byte(escape_char),
+                                None => String::from("doubled quotes")
+                            })));
+                app_data.set_escape(escape);
+                Ok(false)
+            },
+            Command::Set(SetOption::Header(has_header)) => {
+                app_data.set_has_header(has_header);
+                Ok(false)
+            },
+            Command::Set(SetOption::Backup(make_backups)) => {
+                app_data.set_make_backups(make_backups);
+                app_state.set_status_msg(Some(String::from(match make_backups {
+                    true => "A .bak copy will be made before first saving over a file.",
+                    false => "No .bak copies will be made."
+                })));
+                Ok(false)
+            },
+            Command::Normalise(width) => {
+                let preview = app_data.preview_normalise(width);
+                if preview.rows.is_empty() {
+                    return Err(format!("No rows need normalising to {} values.", 
+                                       preview.width));
+                }
+                app_state.set_status_msg(Some(format!(
+                            "{} rows will be padded or cut short to {} values. ",
+                            preview.rows.len(),
+                            preview.width)));
+                app_state.set_normalise_preview(Some(preview));
+                app_state.set_panel_pos(0);
+                app_state.set_input_mode(InputMode::ConfirmNormalise);
+                Ok(false)
+            },
+            Command::Set(SetOption::Lenient(is_lenient)) => {
+                app_data.set_lenient(is_lenient);
+                app_state.set_status_msg(Some(String::from(match is_lenient {
+                    true => "Files with malformed records will be loaded as far as they can be read, reload with :e! to load the file again.",
+                    false => "Files with malformed records will fail to load."
+                })));
+                Ok(false)
+            },
+            Command::Set(SetOption::Encoding(encoding)) => {
+                app_state.set_status_msg(Some(format!(
+                            "Encoding set to {}, reload with :e! to read the file with it.", 
+                            encoding.name())));
+                app_data.set_encoding(encoding);
+                Ok(false)
             }
         }
+    }
+
+    /// Opens the problem panel to list the problems of a file which failed to
+    /// load because it has malformed records.
+    fn reject_file(app_state: &mut AppStateModel, filename: &str, err: &io::Error) {
+        if let Some(malformed) = MalformedFile::from_error(err) {
+            app_state.set_rejected_file(Some(RejectedFile {
+                filename: String::from(filename),
+                problems: malformed.problems.clone()
+            }));
+            app_state.set_panel_pos(0);
+            app_state.set_input_mode(InputMode::ViewingProblems);
+        }
+    }
+
+    /// Describes the problems found when the file was loaded, to follow the
+    /// message saying it was loaded.
+    fn describe_problems(app_data: &CsvModel) -> String {
+        match app_data.get_problems().len() {
+            0 => String::new(),
+            count => format!(" {} malformed records are marked, press p to list them.", count)
+        }
+    }
+
+    /// Lists the problems of the file which failed to load, if there is one,
+    /// otherwise those found when the current file was loaded.
+    fn get_problem_panel(app_state: &AppStateModel, app_data: &CsvModel) -> ListPanel {
+        let (title, problems) = match app_state.get_rejected_file() {
+            Some(rejected) => (
+                format!("Problems - {} wasn't loaded, load what can be read with :set lenient and :e {}",
+                        rejected.filename,
+                        rejected.filename),
+                &rejected.problems[..]
+            ),
+            None => (
+                format!("Problems - {} found when loading", app_data.get_problems().len()),
+                app_data.get_problems()
+            )
+        };
+        let window = get_panel_window(problems.len(), app_state.get_panel_pos());
+        ListPanel {
+            title,
+            selected: app_state.get_panel_pos() - window.start,
+            items: problems[window].iter().map(|problem| problem.to_string()).collect()
+        }
+    }
+
+    /// Lists the rows normalising the data would change, and how each of
+    /// them changes.
+    fn get_normalise_panel(app_state: &AppStateModel, app_data: &CsvModel) -> ListPanel {
+        let (width, rows) = match app_state.get_normalise_preview() {
+            Some(preview) => (preview.width, &preview.rows[..]),
+            None => (0, &[][..])
+        };
+        let window = get_panel_window(rows.len(), app_state.get_panel_pos());
+        ListPanel {
+            title: format!("Normalise - {} rows changed to have {} values", rows.len(), width),
+            selected: app_state.get_panel_pos() - window.start,
+            items: rows[window].iter()
+                .map(|row| app_data.describe_normalised_row(*row, width))
+                .collect()
+        }
+    }
+
+    /// Returns the range of items given to a list panel, around the selected
+    /// one.
+    fn get_panel_window(item_count: usize, selected: usize) -> Range<usize> {
+        let start = selected.saturating_sub(PANEL_WINDOW).min(item_count);
+        start..item_count.min(selected + PANEL_WINDOW + 1)
+    }
+
+    /// Saves the data, to the given file if one is named, otherwise to the
+    /// file it was loaded from. Returns false if saving is waiting for 
+    /// confirmation that a changed file can be overwritten.
+    fn write_file(app_state: &mut AppStateModel,
+                  app_data: &mut CsvModel,
+                  filename: Option<String>,
+                  quit_after_save: bool) -> Result<bool, String> {
+        if filename.is_some() {
+            app_data.set_filename(filename);
+        }
+        let name = match app_data.get_filename() {
+            Some(name) => name.clone(),
+            None => return Err(String::from("No file name, use :w <filename>."))
+        };
+        match save_or_confirm(app_state, app_data, quit_after_save) {
+            Some(Ok(_)) => {
+                app_data.set_saved(true);
+                app_state.set_status_msg(Some(format!("Written to {}.", name)));
+                Ok(true)
+            },
+            Some(Err(err)) => {
+                app_data.set_saved(false);
+                Err(format!("Unable to write {}: {}", name, err))
+            },
+            None => Ok(false)
+        }
+    }
+
+    /// Clears everything entered for a replace, including the selected range,
+    /// and returns to normal mode.
+    fn finish_replace(app_state: &mut AppStateModel) {
+        app_state.clear_input();
+        app_state.set_replace_pattern(None);
+        app_state.set_replacement(String::new());
+        app_state.set_selection_anchor(None);
+        app_state.set_input_mode(InputMode::Normal);
+    }
+
+    /// Moves the cursor to the location of an undone or redone operation.
+    /// Row and column operations keep the cursor in its current column or 
+    /// row respectively.
+    fn focus_edit_location(app_state: &mut AppStateModel,
+                           location: EditLocation,
+                           grid_size: &Size) {
+        let current_pos = app_state.get_selected_pos();
+        let pos = match location {
+            EditLocation::Cell(pos) => pos,
+            EditLocation::Row(row) => Position { row, col: current_pos.col },
+            EditLocation::Col(col) => Position { row: current_pos.row, col }
+        };
+        app_state.focus_position(pos, grid_size);
+    }
+}
+
+/// Applies a key pressed while editing the selected cell. Alt-Enter adds a
+/// line break to the value, Enter sets the cell to the value, and Esc
+/// drops it.
+fn edit_cell(key: KeyEvent, app_state: &mut AppStateModel, app_data: &mut CsvModel) {
+    match key.code {
+        // values with line breaks are edited in a larger box
+        KeyCode::Enter if key.modifiers.contains(KeyModifiers::ALT) => {
+            app_state.insert_char_current_input('\n');
+        },
+        KeyCode::Enter => {
+            /*
+             * draing the current input value from the app state, 
+             * then set the value of the current cell to the value
+             * of the input.
+             */
+            let data_pos = app_state.get_selected_pos();
+            app_data.set_cell_value(data_pos.row, 
+                                    data_pos.col, 
+                                    app_state.drain_input());
+            app_state.set_input_mode(InputMode::Normal);
+        },
+        KeyCode::Esc => {
+            app_state.clear_input();
+            app_state.set_input_mode(InputMode::Normal);
+        },
+        _ => {
+            edit_input(app_state, key);
+        }
+    }
+}
+
+/// Applies a key which edits the input, such as typing a character,
+/// moving the cursor or deleting. Returns true if the input changed.
+fn edit_input(app_state: &mut AppStateModel, key: KeyEvent) -> bool {
+    let prev_len = app_state.get_input().len();
+    match key.code {
+        KeyCode::Char('w') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app_state.delete_word_before_cursor();
+        },
+        KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app_state.delete_to_line_start();
+        },
+        KeyCode::Char(char) => {
+            app_state.insert_char_current_input(char);
+        },
+        KeyCode::Backspace => {
+            app_state.delete_char_before_cursor();
+        },
+        KeyCode::Delete => {
+            app_state.delete_char_at_cursor();
+        },
+        KeyCode::Left => {
+            app_state.move_input_cursor_left();
+        },
+        KeyCode::Right => {
+            app_state.move_input_cursor_right();
+        },
+        KeyCode::Up => {
+            app_state.move_input_cursor_up();
+        },
+        KeyCode::Down => {
+            app_state.move_input_cursor_down();
+        },
+        KeyCode::Home => {
+            app_state.move_input_cursor_to_line_start();
+        },
+        KeyCode::End => {
+            app_state.move_input_cursor_to_line_end();
+        },
+        _ => {}
+    }
+    // every edit adds or removes something
+    app_state.get_input().len() != prev_len
+}
+
+/// Returns the number of columns that fit in the area, including the
+/// frozen columns.
+pub fn calculate_current_grid_columns(app_state: &AppStateModel,
+                                      app_data: &CsvModel,
+                                      area_width: usize) -> usize {
+
+    let mut num_cols = app_state.get_frozen_cols();
+    let mut total_widths = calculate_frozen_width(app_state, app_data);
+    let mut current_col = app_state.get_corner_pos().col;
+
+    loop {
+        total_widths += app_data.get_col_max_width(current_col) + 1;
+        if total_widths < area_width {
+            num_cols += 1;
+            current_col += 1;
+        } else {
+            break;
+        }
+    }
+
+    num_cols
+}
+
+/// Returns the width taken up by the frozen columns, including the 
+/// separator between them and the scrolling columns.
+fn calculate_frozen_width(app_state: &AppStateModel,
+                          app_data: &CsvModel) -> usize {
+    let frozen_cols = app_state.get_frozen_cols();
+    if frozen_cols == 0 {
+        return 0;
+    }
+    let separator_width = 2;
+    (0..frozen_cols).map(|col| app_data.get_col_max_width(col) + 1)
+        .sum::<usize>() + separator_width
+}
+
+/// Adds any records written to the end of the followed file. The cursor 
+/// is kept on the last row, unless it has been moved away from it.
+fn follow_file(app_state: &mut AppStateModel,
+               app_data: &mut CsvModel,
+               grid_size: &Size) {
+    let selected_pos = app_state.get_selected_pos();
+    let row_count = app_data.get_filtered_row_count()
+        .unwrap_or(app_data.get_data_size().height);
+    let is_on_last_row = selected_pos.row + 1 >= row_count;
+    match app_data.read_appended_rows() {
+        Ok(0) => {},
+        Ok(_) => {
+            let row_count = app_data.get_filtered_row_count()
+                .unwrap_or(app_data.get_data_size().height);
+            if is_on_last_row {
+                app_state.focus_position_at_bottom(Position { 
+                    row: row_count.saturating_sub(1), 
+                    col: selected_pos.col 
+                }, grid_size);
+            }
+        },
+        Err(err) => {
+            app_state.set_following(false);
+            app_state.set_status_msg(Some(format!(
+                        "Stopped following the file: {}", err)));
+        }
+    }
+}
+
+/// Saves the data, unless the file has been changed by something else 
+/// since it was loaded, in which case the user is asked whether to 
+/// overwrite it. Returns None while waiting for an answer.
+fn save_or_confirm(app_state: &mut AppStateModel,
+                   app_data: &mut CsvModel,
+                   quit_after_save: bool) -> Option<io::Result<()>> {
+    if app_data.has_file_changed() {
+        app_state.set_quit_after_save(quit_after_save);
+        app_state.set_input_mode(InputMode::ConfirmOverwrite);
+        return None;
+    }
+    Some(app_data.save_data_to_file())
+}
+
+/// Handles the answer to the prompt shown when saving over a file that has
+/// been changed by something else since it was loaded or saved: overwrite
+/// it, reload it and discard the edits, or cancel the save.
+fn confirm_overwrite(key_code: KeyCode,
+                     app_state: &mut AppStateModel,
+                     app_data: &mut CsvModel,
+                     grid_size: &Size) {
+    match key_code {
+        KeyCode::Char('o') | KeyCode::Char('O') => {
+            match app_data.save_data_to_file() {
+                Ok(_) => {
+                    app_data.set_saved(true);
+                    app_state.set_input_mode(match app_state.get_quit_after_save() {
+                        true => InputMode::Quiting,
+                        false => InputMode::Saved
+                    });
+                },
+                Err(_) => {
+                    app_data.set_saved(false);
+                    app_state.set_input_mode(InputMode::SavedFailed);
+                }
+            }
+            app_state.set_quit_after_save(false);
+        },
+        KeyCode::Char('r') | KeyCode::Char('R') => {
+            app_state.set_quit_after_save(false);
+            app_state.set_input_mode(InputMode::Normal);
+            match app_data.reload() {
+                Ok(_) => {
+                    app_state.set_search_pattern(None);
+                    app_state.focus_position(Position { row: 0, col: 0 }, 
+                                             grid_size);
+                    app_state.set_status_msg(Some(String::from(
+                                "Reloaded the file, discarding edits.")));
+                },
+                Err(err) => {
+                    app_state.set_status_msg(Some(format!(
+                                "Unable to reload: {}", err)));
+                }
+            }
+        },
+        KeyCode::Char('c') | KeyCode::Char('C') | KeyCode::Esc => {
+            app_state.set_quit_after_save(false);
+            app_state.set_input_mode(InputMode::Normal);
+        },
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, OpenOptions},
+        io::Write,
+        time::SystemTime
+    };
+
+    use super::*;
+    use crate::model::csv_model::{CsvDelimiter, CsvOptions};
+
+    const GRID_SIZE: Size = Size { width: 5, height: 5 };
+
+    /// Loads a file holding `a`, edits it to `b`, and then has something else
+    /// change the file to `x`, so that saving it asks to confirm.
+    fn changed_file(name: &str) -> (String, AppStateModel, CsvModel) {
+        let path = std::env::temp_dir()
+            .join(format!("csv_tui_{}_{}.csv", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned();
+        fs::write(&path, b"a\n").unwrap();
+        let options = CsvOptions {
+            delimiter: Some(CsvDelimiter::Comma),
+            ..CsvOptions::default()
+        };
+        let mut app_data = CsvModel::load_file(&path, &options).unwrap();
+        app_data.set_cell_value(0, 0, String::from("b"));
+        fs::write(&path, b"x\n").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        OpenOptions::new().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert!(app_data.has_file_changed());
+
+        let mut app_state = AppStateModel::default();
+        assert!(save_or_confirm(&mut app_state, &mut app_data, false).is_none());
+        assert_eq!(app_state.get_input_mode(), &InputMode::ConfirmOverwrite);
+        (path, app_state, app_data)
+    }
+
+    /// Loads a file of rows numbered 0 to 9, with the cursor on the given
+    /// row, and appends more rows to it.
+    fn follow_from_row(name: &str, 
+                       row: usize, 
+                       appended: usize) -> (String, AppStateModel, CsvModel) {
+        let path = std::env::temp_dir()
+            .join(format!("csv_tui_{}_{}.csv", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned();
+        let contents: String = (0..10).map(|row| format!("{},x\n", row)).collect();
+        fs::write(&path, contents).unwrap();
+        let options = CsvOptions {
+            delimiter: Some(CsvDelimiter::Comma),
+            ..CsvOptions::default()
+        };
+        let mut app_data = CsvModel::load_file(&path, &options).unwrap();
+        let mut app_state = AppStateModel::default();
+        app_state.set_following(true);
+        app_state.focus_position(Position { row, col: 1 }, &GRID_SIZE);
+        let appended_rows: String = (10..10 + appended).map(|row| format!("{},x\n", row))
+            .collect();
+        OpenOptions::new().append(true).open(&path).unwrap()
+            .write_all(appended_rows.as_bytes()).unwrap();
+        follow_file(&mut app_state, &mut app_data, &GRID_SIZE);
+        assert_eq!(app_data.get_data_size().height, 10 + appended);
+        (path, app_state, app_data)
+    }
+
+    #[test]
+    fn cursor_on_the_last_row_stays_on_it_when_following() {
+        let (path, app_state, _) = follow_from_row("follow_last", 9, 2);
+        assert_eq!(app_state.get_selected_pos(), Position { row: 11, col: 1 });
+        // the grid isn't moved, as the new rows fit below the row the cursor
+        // was on
+        assert_eq!(app_state.get_corner_pos().row, 9);
+        assert!(app_state.is_following());
+        fs::remove_file(&path).unwrap();
+        // rows past the bottom of the grid move it down just far enough
+        let (path, app_state, _) = follow_from_row("follow_scroll", 9, 6);
+        assert_eq!(app_state.get_selected_pos(), Position { row: 15, col: 1 });
+        assert_eq!(app_state.get_corner_pos().row, 11);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn cursor_moved_away_from_the_last_row_stays_put_when_following() {
+        let (path, app_state, _) = follow_from_row("follow_moved", 3, 2);
+        assert_eq!(app_state.get_selected_pos(), Position { row: 3, col: 1 });
+        assert_eq!(app_state.get_corner_pos().row, 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn overwriting_saves_the_edits_over_the_changed_file() {
+        let (path, mut app_state, mut app_data) = changed_file("overwrite");
+        confirm_overwrite(KeyCode::Char('o'), &mut app_state, &mut app_data, &GRID_SIZE);
+        assert_eq!(fs::read(&path).unwrap(), b"b\n");
+        assert_eq!(app_state.get_input_mode(), &InputMode::Saved);
+        assert!(app_data.is_in_saved_state());
+        assert!(!app_data.has_file_changed());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn overwriting_before_quitting_quits() {
+        let (path, mut app_state, mut app_data) = changed_file("overwrite_quit");
+        app_state.set_quit_after_save(true);
+        confirm_overwrite(KeyCode::Char('O'), &mut app_state, &mut app_data, &GRID_SIZE);
+        assert_eq!(app_state.get_input_mode(), &InputMode::Quiting);
+        assert!(!app_state.get_quit_after_save());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reloading_discards_the_edits() {
+        let (path, mut app_state, mut app_data) = changed_file("reload");
+        app_state.set_quit_after_save(true);
+        confirm_overwrite(KeyCode::Char('r'), &mut app_state, &mut app_data, &GRID_SIZE);
+        assert_eq!(app_data.get_cell_value(0, 0), "x");
+        assert_eq!(fs::read(&path).unwrap(), b"x\n");
+        assert_eq!(app_state.get_input_mode(), &InputMode::Normal);
+        assert!(!app_state.get_quit_after_save());
+        assert!(app_data.is_in_saved_state());
+        assert!(app_data.undo().is_none());
+        assert!(!app_data.has_file_changed());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn cancelling_keeps_the_edits_and_the_changed_file() {
+        let (path, mut app_state, mut app_data) = changed_file("cancel");
+        app_state.set_quit_after_save(true);
+        confirm_overwrite(KeyCode::Esc, &mut app_state, &mut app_data, &GRID_SIZE);
+        assert_eq!(app_data.get_cell_value(0, 0), "b");
+        assert_eq!(fs::read(&path).unwrap(), b"x\n");
+        assert_eq!(app_state.get_input_mode(), &InputMode::Normal);
+        assert!(!app_state.get_quit_after_save());
+        assert!(!app_data.is_in_saved_state());
+        assert!(app_data.has_file_changed());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn line_breaks_typed_in_a_value_are_saved_quoted_and_read_back() {
+        let path = std::env::temp_dir()
+            .join(format!("csv_tui_{}_line_breaks.csv", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        fs::write(&path, b"a,b\n").unwrap();
+        let options = CsvOptions {
+            delimiter: Some(CsvDelimiter::Comma),
+            ..CsvOptions::default()
+        };
+        let mut app_data = CsvModel::load_file(&path, &options).unwrap();
+        let mut app_state = AppStateModel::default();
+        app_state.set_input_mode(InputMode::Editing);
+        for (code, modifiers) in [(KeyCode::Char('x'), KeyModifiers::NONE),
+                                  (KeyCode::Enter, KeyModifiers::ALT),
+                                  (KeyCode::Char('y'), KeyModifiers::NONE),
+                                  (KeyCode::Enter, KeyModifiers::NONE)] {
+            edit_cell(KeyEvent::new(code, modifiers), &mut app_state, &mut app_data);
+        }
+        assert_eq!(app_state.get_input_mode(), &InputMode::Normal);
+        assert_eq!(app_data.get_cell_value(0, 0), "x\ny");
+        app_data.set_cell_value(0, 1, String::from("c\r\nd"));
+        app_data.save_data_to_file().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"\"x\ny\",\"c\r\nd\"\n");
 
-        return num_cols;
+        let loaded = CsvModel::load_file(&path, &options).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.get_cell_value(0, 0), loaded.get_cell_value(0, 1)),
+                   ("x\ny".to_string(), "c\r\nd".to_string()));
     }
 }
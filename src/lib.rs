@@ -0,0 +1,4 @@
+pub mod view;
+pub mod model;
+pub mod controller;
+pub mod utils;
@@ -14,20 +14,15 @@ use crossterm::{
         EnableMouseCapture, 
         DisableMouseCapture}};
 
-use model::utils_model::RunningMode;
+use csv_tui::model::utils_model::RunningMode;
 
-use view::default_view::render_ui as default_render;
-use view::debug_view::render_ui as debug_render;
-use view::help_view::print_help_text;
-use controller::default_controller::run;
-use utils::handle_args::handle_input_args;
+use csv_tui::view::default_view::render_ui as default_render;
+use csv_tui::view::debug_view::render_ui as debug_render;
+use csv_tui::view::help_view::print_help_text;
+use csv_tui::controller::default_controller::run;
+use csv_tui::utils::handle_args::handle_input_args;
 //use controller::debugController::run as run_debug;
 
-mod view;
-mod model;
-mod controller;
-mod utils;
-
 fn main() -> Result<(), io::Error>{
     let args: Vec<String> = env::args().collect();
     // process args
@@ -57,8 +52,7 @@ fn main() -> Result<(), io::Error>{
                                   &mut app_state, 
                                   debug_render, 
                                   &mut terminal),
-        RunningMode::Help => Err(io::Error::new(io::ErrorKind::Other, 
-                                                "RunningMode Help not valid run option"))
+        RunningMode::Help => Err(io::Error::other("RunningMode Help not valid run option"))
     };
 
     disable_raw_mode()?;
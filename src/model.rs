@@ -1,3 +1,11 @@
 pub mod utils_model;
 pub mod app_state_model;
 pub mod csv_model;
+pub mod search_model;
+pub mod sort_model;
+pub mod filter_model;
+pub mod command_model;
+pub mod dialect_model;
+pub mod row_store;
+pub mod width_model;
+pub mod problem_model;
@@ -1,27 +1,101 @@
-use crate::model::utils_model::{
-    Position,
-    InputMode,
-    RunningMode
+use unicode_width::UnicodeWidthStr;
+
+use crate::model::{
+    utils_model::{
+        Position,
+        Size,
+        InputMode,
+        RunningMode
+    },
+    search_model::{
+        SearchPattern,
+        SearchOptions,
+        ReplaceScope
+    },
+    problem_model::{
+        RejectedFile,
+        NormalisePreview
+    },
+    width_model::display_value
 };
 
 pub struct AppStateModel {
     /// Current value of the input box
     input: String,
+    /// Where the cursor is in the input, as the byte position of the
+    /// character it is on.
+    input_cursor: usize,
+    /// The display column of the input shown at the left of the input box,
+    /// moved as the cursor leaves the box.
+    input_scroll: usize,
     /// Current input mode
     input_mode: InputMode,
     running_mode: RunningMode,
     corner_pos: Position,
-    relative_pos: Position
+    relative_pos: Position,
+    /// Number of leading columns which stay on screen while scrolling 
+    /// horizontally. The scrolling columns start at corner_pos.col, which is
+    /// never less than frozen_cols.
+    frozen_cols: usize,
+    /// The current search, matches of which are highlighted and can be 
+    /// jumped between.
+    search_pattern: Option<SearchPattern>,
+    search_options: SearchOptions,
+    /// The selected position when searching started, which incremental 
+    /// searches start from.
+    search_origin: Position,
+    /// The corner of the selected range opposite the cursor, while a range 
+    /// is being selected.
+    selection_anchor: Option<Position>,
+    replace_scope: ReplaceScope,
+    /// The pattern being replaced, set once the find part of a replace has 
+    /// been entered.
+    replace_pattern: Option<SearchPattern>,
+    /// The text the matches of replace_pattern are replaced with.
+    replacement: String,
+    /// A message shown in the header line, replacing the usual help text.
+    status_msg: Option<String>,
+    /// Set when the application should quit once a save waiting for 
+    /// confirmation has finished.
+    quit_after_save: bool,
+    /// Set when records added to the end of the file are read as they are
+    /// written.
+    is_following: bool,
+    /// The last file which failed to load because it has malformed records,
+    /// until the problem panel listing its problems is closed.
+    rejected_file: Option<RejectedFile>,
+    /// The rows normalising the data would change, while waiting for it to
+    /// be confirmed.
+    normalise_preview: Option<NormalisePreview>,
+    /// The item selected in the panel listing problems or the rows to be
+    /// normalised.
+    panel_pos: usize
 }
 
 impl Default for AppStateModel {
     fn default() -> AppStateModel {
         AppStateModel {
             input: String::new(),
+            input_cursor: 0,
+            input_scroll: 0,
             input_mode: InputMode::Normal,
             running_mode: RunningMode::Normal,
             corner_pos: Position { row: 0, col: 0 },
             relative_pos: Position { row: 0, col: 0 },
+            frozen_cols: 0,
+            search_pattern: None,
+            search_options: SearchOptions::default(),
+            search_origin: Position { row: 0, col: 0 },
+            selection_anchor: None,
+            replace_scope: ReplaceScope::Table,
+            replace_pattern: None,
+            replacement: String::new(),
+            status_msg: None,
+            quit_after_save: false,
+            is_following: false,
+            rejected_file: None,
+            normalise_preview: None,
+            panel_pos: 0
         }
     }
 }
@@ -47,6 +121,20 @@ impl AppStateModel {
         self.relative_pos
     }
 
+    /// Returns the position of the currently selected cell in the data, 
+    /// taking any frozen columns into account.
+    pub fn get_selected_pos(&self) -> Position {
+        let col = if self.relative_pos.col < self.frozen_cols {
+            self.relative_pos.col
+        } else {
+            self.corner_pos.col + self.relative_pos.col - self.frozen_cols
+        };
+        Position {
+            row: self.corner_pos.row + self.relative_pos.row,
+            col
+        }
+    }
+
     pub fn increment_relative_pos_row(&mut self) {
         self.relative_pos.row += 1;
     }
@@ -89,30 +177,356 @@ impl AppStateModel {
     }
 
     pub fn remove_from_corner_pos_col(&mut self, n: usize) {
-        if self.corner_pos.col > n {
+        if self.corner_pos.col > n + self.frozen_cols {
             self.corner_pos.col -= n;
         } else {
-            self.corner_pos.col = 0;
+            self.corner_pos.col = self.frozen_cols;
+        }
+    }
+
+    pub fn get_frozen_cols(&self) -> usize {
+        self.frozen_cols
+    }
+
+    pub fn set_frozen_cols(&mut self, n: usize) {
+        self.frozen_cols = n;
+        if self.corner_pos.col < n {
+            self.corner_pos.col = n;
+        }
+    }
+
+    /// Moves the cursor to the given position in the data. If the position is
+    /// not within the grid currently on screen, the grid is moved so that the
+    /// position is in its top left corner.
+    pub fn focus_position(&mut self, pos: Position, grid_size: &Size) {
+        let corner_pos = self.corner_pos;
+        if pos.row >= corner_pos.row && pos.row < corner_pos.row + grid_size.height {
+            self.relative_pos.row = pos.row - corner_pos.row;
+        } else {
+            self.corner_pos.row = pos.row;
+            self.relative_pos.row = 0;
+        }
+        let scrolling_width = grid_size.width.saturating_sub(self.frozen_cols);
+        if pos.col < self.frozen_cols {
+            self.relative_pos.col = pos.col;
+        } else if pos.col >= corner_pos.col && pos.col < corner_pos.col + scrolling_width {
+            self.relative_pos.col = self.frozen_cols + pos.col - corner_pos.col;
+        } else {
+            self.corner_pos.col = pos.col;
+            self.relative_pos.col = self.frozen_cols;
+        }
+    }
+
+    /// Moves the cursor to the given position in the data, as focus_position
+    /// does, except that a grid moved down puts the position in its bottom 
+    /// row, so that the rows before it stay on screen.
+    pub fn focus_position_at_bottom(&mut self, pos: Position, grid_size: &Size) {
+        if pos.row >= self.corner_pos.row + grid_size.height {
+            self.corner_pos.row = (pos.row + 1).saturating_sub(grid_size.height);
         }
+        self.focus_position(pos, grid_size);
+    }
+
+    pub fn get_search_pattern(&self) -> Option<&SearchPattern> {
+        self.search_pattern.as_ref()
+    }
+
+    pub fn set_search_pattern(&mut self, pattern: Option<SearchPattern>) {
+        self.search_pattern = pattern;
+    }
+
+    pub fn get_search_options(&self) -> &SearchOptions {
+        &self.search_options
+    }
+
+    pub fn toggle_search_case_insensitive(&mut self) {
+        self.search_options.case_insensitive = !self.search_options.case_insensitive;
+    }
+
+    pub fn toggle_search_regex(&mut self) {
+        self.search_options.use_regex = !self.search_options.use_regex;
+    }
+
+    pub fn get_search_origin(&self) -> Position {
+        self.search_origin
+    }
+
+    pub fn set_search_origin(&mut self, pos: Position) {
+        self.search_origin = pos;
+    }
+
+    pub fn set_selection_anchor(&mut self, anchor: Option<Position>) {
+        self.selection_anchor = anchor;
+    }
+
+    /// Returns the range between the selection anchor and the cursor, or None
+    /// if no range is selected.
+    pub fn get_selected_range(&self) -> Option<ReplaceScope> {
+        self.selection_anchor.map(|anchor| {
+            ReplaceScope::Range(anchor, self.get_selected_pos())
+        })
+    }
+
+    pub fn get_replace_scope(&self) -> &ReplaceScope {
+        &self.replace_scope
     }
 
+    pub fn set_replace_scope(&mut self, scope: ReplaceScope) {
+        self.replace_scope = scope;
+    }
+
+    /// Moves the replace scope on to the next kind of scope, from the whole
+    /// table, to the selected column, to the selected range if there is one.
+    pub fn cycle_replace_scope(&mut self) {
+        self.replace_scope = match self.replace_scope {
+            ReplaceScope::Table => ReplaceScope::Column(self.get_selected_pos().col),
+            ReplaceScope::Column(_) => match self.get_selected_range() {
+                Some(range) => range,
+                None => ReplaceScope::Table
+            },
+            ReplaceScope::Range(_, _) => ReplaceScope::Table
+        };
+    }
+
+    pub fn get_replace_pattern(&self) -> Option<&SearchPattern> {
+        self.replace_pattern.as_ref()
+    }
+
+    pub fn set_replace_pattern(&mut self, pattern: Option<SearchPattern>) {
+        self.replace_pattern = pattern;
+    }
+
+    pub fn get_replacement(&self) -> &str {
+        &self.replacement
+    }
+
+    pub fn set_replacement(&mut self, replacement: String) {
+        self.replacement = replacement;
+    }
+
+    pub fn get_status_msg(&self) -> &Option<String> {
+        &self.status_msg
+    }
+
+    pub fn set_status_msg(&mut self, msg: Option<String>) {
+        self.status_msg = msg;
+    }
+
+    pub fn get_quit_after_save(&self) -> bool {
+        self.quit_after_save
+    }
+
+    pub fn set_quit_after_save(&mut self, quit_after_save: bool) {
+        self.quit_after_save = quit_after_save;
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.is_following
+    }
+
+    pub fn set_following(&mut self, is_following: bool) {
+        self.is_following = is_following;
+    }
+
+    pub fn get_rejected_file(&self) -> Option<&RejectedFile> {
+        self.rejected_file.as_ref()
+    }
+
+    pub fn set_rejected_file(&mut self, rejected_file: Option<RejectedFile>) {
+        self.rejected_file = rejected_file;
+    }
+
+    pub fn get_normalise_preview(&self) -> Option<&NormalisePreview> {
+        self.normalise_preview.as_ref()
+    }
+
+    pub fn set_normalise_preview(&mut self, normalise_preview: Option<NormalisePreview>) {
+        self.normalise_preview = normalise_preview;
+    }
+
+    pub fn get_panel_pos(&self) -> usize {
+        self.panel_pos
+    }
+
+    pub fn set_panel_pos(&mut self, panel_pos: usize) {
+        self.panel_pos = panel_pos;
+    }
+
+    /// Adds the string to the end of the input, moving the cursor to the end.
     pub fn append_str_current_input(&mut self, string_value : &str) {
-        self.input.push_str(&string_value);
+        self.input.push_str(string_value);
+        self.input_cursor = self.input.len();
+    }
+
+    /// Inserts the character at the cursor, moving the cursor past it.
+    pub fn insert_char_current_input(&mut self, char_value : char) {
+        self.input.insert(self.input_cursor, char_value);
+        self.input_cursor += char_value.len_utf8();
+    }
+
+    /// Removes the character before the cursor.
+    pub fn delete_char_before_cursor(&mut self) {
+        let start = self.prev_char_pos();
+        self.input.drain(start..self.input_cursor);
+        self.input_cursor = start;
+    }
+
+    /// Removes the character the cursor is on.
+    pub fn delete_char_at_cursor(&mut self) {
+        let end = self.next_char_pos();
+        self.input.drain(self.input_cursor..end);
+    }
+
+    /// Removes the word before the cursor, and any whitespace between it and
+    /// the cursor.
+    pub fn delete_word_before_cursor(&mut self) {
+        let before_cursor = self.input[..self.input_cursor].trim_end();
+        let start = match before_cursor.char_indices().rev().find(|(_, c)| c.is_whitespace()) {
+            Some((pos, c)) => pos + c.len_utf8(),
+            None => 0
+        };
+        self.input.drain(start..self.input_cursor);
+        self.input_cursor = start;
+    }
+
+    /// Removes everything before the cursor on its line.
+    pub fn delete_to_line_start(&mut self) {
+        let start = self.line_start();
+        self.input.drain(start..self.input_cursor);
+        self.input_cursor = start;
+    }
+
+    pub fn move_input_cursor_left(&mut self) {
+        self.input_cursor = self.prev_char_pos();
+    }
+
+    pub fn move_input_cursor_right(&mut self) {
+        self.input_cursor = self.next_char_pos();
+    }
+
+    pub fn move_input_cursor_to_line_start(&mut self) {
+        self.input_cursor = self.line_start();
+    }
+
+    pub fn move_input_cursor_to_line_end(&mut self) {
+        self.input_cursor = self.line_end();
+    }
+
+    /// Moves the cursor to the line before, as near to the same column as
+    /// that line allows.
+    pub fn move_input_cursor_up(&mut self) {
+        let line_start = self.line_start();
+        if line_start == 0 {
+            return;
+        }
+        let col = display_value(&self.input[line_start..self.input_cursor]).width();
+        let prev_line_start = match self.input[..line_start - 1].rfind('\n') {
+            Some(pos) => pos + 1,
+            None => 0
+        };
+        self.input_cursor = self.pos_at_col(prev_line_start, col);
+    }
+
+    /// Moves the cursor to the line after, as near to the same column as
+    /// that line allows.
+    pub fn move_input_cursor_down(&mut self) {
+        let next_line_start = match self.input[self.input_cursor..].find('\n') {
+            Some(pos) => self.input_cursor + pos + 1,
+            None => return
+        };
+        let col = display_value(&self.input[self.line_start()..self.input_cursor]).width();
+        self.input_cursor = self.pos_at_col(next_line_start, col);
+    }
+
+    /// The position of the start of the line the cursor is on.
+    fn line_start(&self) -> usize {
+        match self.input[..self.input_cursor].rfind('\n') {
+            Some(pos) => pos + 1,
+            None => 0
+        }
+    }
+
+    /// The position of the end of the line the cursor is on, before its line
+    /// break.
+    fn line_end(&self) -> usize {
+        let end = match self.input[self.input_cursor..].find('\n') {
+            Some(pos) => self.input_cursor + pos,
+            None => self.input.len()
+        };
+        match self.input[..end].ends_with('\r') && end > self.input_cursor {
+            true => end - 1,
+            false => end
+        }
+    }
+
+    /// The position of the character at display column col of the line
+    /// starting at line_start, or of the end of the line if it is shorter.
+    /// A carriage return which doesn't end the line is shown, so it takes up
+    /// a column.
+    fn pos_at_col(&self, line_start: usize, col: usize) -> usize {
+        let line = &self.input[line_start..];
+        let line = &line[..line.find('\n').unwrap_or(line.len())];
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let mut line_width = 0;
+        for (pos, c) in line.char_indices() {
+            if line_width >= col {
+                return line_start + pos;
+            }
+            line_width += display_value(&line[pos..pos + c.len_utf8()]).width();
+        }
+        line_start + line.len()
     }
 
-    pub fn append_char_current_input(&mut self, char_value : char) {
-        self.input.push(char_value);
+    /// The position of the character before the cursor, or of the cursor if
+    /// it is at the start.
+    fn prev_char_pos(&self) -> usize {
+        match self.input[..self.input_cursor].chars().next_back() {
+            Some(c) => self.input_cursor - c.len_utf8(),
+            None => self.input_cursor
+        }
+    }
+
+    /// The position of the character after the one the cursor is on, or of
+    /// the cursor if it is at the end.
+    fn next_char_pos(&self) -> usize {
+        match self.input[self.input_cursor..].chars().next() {
+            Some(c) => self.input_cursor + c.len_utf8(),
+            None => self.input_cursor
+        }
     }
 
-    pub fn pop_current_input(&mut self) -> Option<char> {
-        self.input.pop()
+    pub fn get_input_cursor(&self) -> usize {
+        self.input_cursor
+    }
+
+    pub fn get_input_scroll(&self) -> usize {
+        self.input_scroll
+    }
+
+    /// Scrolls the input shown in an input box width columns wide just far
+    /// enough for the cursor to be in the box, without leaving the box empty
+    /// past the end of the input. The cursor takes a column of its own at the
+    /// end of the input.
+    pub fn scroll_input_to_cursor(&mut self, width: usize) {
+        let cursor_col = display_value(&self.input[..self.input_cursor]).width();
+        let end_scroll = (display_value(&self.input).width() + 1).saturating_sub(width);
+        self.input_scroll = self.input_scroll.min(end_scroll);
+        if cursor_col < self.input_scroll {
+            self.input_scroll = cursor_col;
+        } else if cursor_col >= self.input_scroll + width {
+            self.input_scroll = cursor_col + 1 - width.max(1);
+        }
     }
 
     pub fn drain_input(&mut self) -> String {
+        self.input_cursor = 0;
+        self.input_scroll = 0;
         self.input.drain(..).collect()
     }
 
     pub fn clear_input(&mut self) {
+        self.input_cursor = 0;
+        self.input_scroll = 0;
         self.input.clear();
     }
 
@@ -132,3 +546,191 @@ impl AppStateModel {
         &self.running_mode
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with_input(input: &str) -> AppStateModel {
+        let mut state = AppStateModel::default();
+        state.append_str_current_input(input);
+        state
+    }
+
+    /// Returns a state with the first two columns frozen and the scrolling
+    /// columns moved right to start at column 10.
+    fn state_scrolled_past_frozen_cols() -> AppStateModel {
+        let mut state = AppStateModel::default();
+        state.set_frozen_cols(2);
+        state.add_to_corner_pos_col(8);
+        state
+    }
+
+    #[test]
+    fn selected_pos_counts_frozen_cols_separately() {
+        let mut state = state_scrolled_past_frozen_cols();
+        assert_eq!(state.get_corner_pos().col, 10);
+        state.increment_relative_pos_col();
+        assert_eq!(state.get_selected_pos().col, 1);
+        state.increment_relative_pos_col();
+        assert_eq!(state.get_selected_pos().col, 10);
+        state.increment_relative_pos_col();
+        assert_eq!(state.get_selected_pos().col, 11);
+    }
+
+    #[test]
+    fn focusing_a_position_scrolls_past_frozen_cols() {
+        let grid_size = Size { width: 5, height: 10 };
+        let mut state = state_scrolled_past_frozen_cols();
+        state.focus_position(Position { row: 0, col: 1 }, &grid_size);
+        assert_eq!((state.get_corner_pos().col, state.get_relative_pos().col), (10, 1));
+        state.focus_position(Position { row: 0, col: 12 }, &grid_size);
+        assert_eq!((state.get_corner_pos().col, state.get_relative_pos().col), (10, 4));
+        state.focus_position(Position { row: 0, col: 20 }, &grid_size);
+        assert_eq!((state.get_corner_pos().col, state.get_relative_pos().col), (20, 2));
+        // a column hidden behind the frozen columns is scrolled back to
+        state.focus_position(Position { row: 0, col: 5 }, &grid_size);
+        assert_eq!((state.get_corner_pos().col, state.get_relative_pos().col), (5, 2));
+        assert_eq!(state.get_selected_pos().col, 5);
+    }
+
+    #[test]
+    fn freezing_and_unfreezing_keeps_the_selected_col() {
+        let grid_size = Size { width: 5, height: 10 };
+        let mut state = state_scrolled_past_frozen_cols();
+        let pos = Position { row: 0, col: 11 };
+        state.focus_position(pos, &grid_size);
+        // as f does, freezing up to the selected column
+        state.set_frozen_cols(pos.col + 1);
+        state.focus_position(pos, &grid_size);
+        assert_eq!(state.get_selected_pos().col, 11);
+        assert_eq!(state.get_corner_pos().col, 12);
+        // and as F does, unfreezing them
+        state.set_frozen_cols(0);
+        state.focus_position(pos, &grid_size);
+        assert_eq!(state.get_selected_pos().col, 11);
+    }
+
+    #[test]
+    fn cursor_moves_over_whole_characters() {
+        let mut state = state_with_input("a語😀");
+        assert_eq!(state.get_input_cursor(), 8);
+        state.move_input_cursor_left();
+        assert_eq!(state.get_input_cursor(), 4);
+        state.move_input_cursor_left();
+        assert_eq!(state.get_input_cursor(), 1);
+        state.move_input_cursor_right();
+        assert_eq!(state.get_input_cursor(), 4);
+        state.insert_char_current_input('é');
+        assert_eq!((state.get_input(), state.get_input_cursor()), ("a語é😀", 6));
+        state.delete_char_at_cursor();
+        state.delete_char_before_cursor();
+        assert_eq!((state.get_input(), state.get_input_cursor()), ("a語", 4));
+    }
+
+    #[test]
+    fn moving_between_lines_keeps_the_display_column() {
+        let mut state = state_with_input("語語x\nabcde");
+        state.move_input_cursor_left();
+        state.move_input_cursor_left();
+        // after "abc", at column 3, which is inside the second 語, so the
+        // cursor goes after it, to column 4
+        state.move_input_cursor_up();
+        assert_eq!(state.get_input_cursor(), 6);
+        state.move_input_cursor_down();
+        assert_eq!(state.get_input_cursor(), 12);
+    }
+
+    #[test]
+    fn moving_between_lines_counts_a_carriage_return_inside_a_line() {
+        // shown as "↵ab", so the end of the second line is below its end
+        let mut state = state_with_input("\rab\nxyz");
+        state.move_input_cursor_up();
+        assert_eq!(state.get_input_cursor(), 3);
+        state.move_input_cursor_down();
+        assert_eq!(state.get_input_cursor(), 7);
+    }
+
+    #[test]
+    fn delete_removes_the_character_at_the_cursor() {
+        let mut state = state_with_input("ab語c");
+        state.move_input_cursor_to_line_start();
+        state.delete_char_at_cursor();
+        assert_eq!((state.get_input(), state.get_input_cursor()), ("b語c", 0));
+        state.move_input_cursor_right();
+        state.delete_char_at_cursor();
+        assert_eq!((state.get_input(), state.get_input_cursor()), ("bc", 1));
+        state.move_input_cursor_to_line_end();
+        state.delete_char_at_cursor();
+        assert_eq!((state.get_input(), state.get_input_cursor()), ("bc", 2));
+    }
+
+    #[test]
+    fn home_and_end_stay_on_the_cursor_line() {
+        let mut state = state_with_input("ab\r\ncd\nef");
+        state.move_input_cursor_to_line_start();
+        assert_eq!(state.get_input_cursor(), 7);
+        state.move_input_cursor_to_line_end();
+        assert_eq!(state.get_input_cursor(), 9);
+        state.move_input_cursor_up();
+        state.move_input_cursor_to_line_end();
+        assert_eq!(state.get_input_cursor(), 6);
+        state.move_input_cursor_up();
+        // before the line ending
+        state.move_input_cursor_to_line_end();
+        assert_eq!(state.get_input_cursor(), 2);
+        state.move_input_cursor_to_line_start();
+        assert_eq!(state.get_input_cursor(), 0);
+    }
+
+    #[test]
+    fn deleting_a_word_takes_the_spaces_after_it() {
+        let mut state = state_with_input("one  two,three   ");
+        // punctuation is part of the word
+        state.delete_word_before_cursor();
+        assert_eq!((state.get_input(), state.get_input_cursor()), ("one  ", 5));
+        state.delete_word_before_cursor();
+        assert_eq!((state.get_input(), state.get_input_cursor()), ("", 0));
+        state.delete_word_before_cursor();
+        assert_eq!((state.get_input(), state.get_input_cursor()), ("", 0));
+
+        let mut state = state_with_input("   ");
+        state.delete_word_before_cursor();
+        assert_eq!(state.get_input(), "");
+
+        // only the text before the cursor, back to the line break
+        let mut state = state_with_input("ab\ncd ef");
+        state.move_input_cursor_left();
+        state.move_input_cursor_left();
+        state.move_input_cursor_left();
+        state.delete_word_before_cursor();
+        assert_eq!((state.get_input(), state.get_input_cursor()), ("ab\n ef", 3));
+    }
+
+    #[test]
+    fn deleting_to_the_line_start_keeps_the_lines_before() {
+        let mut state = state_with_input("ab\ncd ef");
+        state.move_input_cursor_left();
+        state.move_input_cursor_left();
+        state.move_input_cursor_left();
+        state.delete_to_line_start();
+        assert_eq!((state.get_input(), state.get_input_cursor()), ("ab\n ef", 3));
+        state.delete_to_line_start();
+        assert_eq!((state.get_input(), state.get_input_cursor()), ("ab\n ef", 3));
+    }
+
+    #[test]
+    fn input_scrolls_by_display_columns() {
+        let mut state = state_with_input("語語語語語");
+        state.scroll_input_to_cursor(6);
+        // the cursor takes the column after the ten of the input
+        assert_eq!(state.get_input_scroll(), 5);
+        state.move_input_cursor_to_line_start();
+        state.move_input_cursor_right();
+        state.scroll_input_to_cursor(6);
+        assert_eq!(state.get_input_scroll(), 2);
+        state.move_input_cursor_to_line_end();
+        state.scroll_input_to_cursor(20);
+        assert_eq!(state.get_input_scroll(), 0);
+    }
+}
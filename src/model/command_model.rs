@@ -0,0 +1,261 @@
+use encoding_rs::Encoding;
+
+use crate::model::csv_model::{
+    CsvDelimiter,
+    parse_byte
+};
+
+/// A command entered on the `:` command line.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Saves the data, to the given file if one is named.
+    Write(Option<String>),
+    /// Quits, even with unsaved changes when forced.
+    Quit {
+        force: bool
+    },
+    /// Saves the data, to the given file if one is named, and then quits.
+    WriteQuit(Option<String>),
+    /// Opens another file in place of the current one, or reloads the 
+    /// current file if none is named, discarding unsaved changes when forced.
+    /// Every `:set` value carries over to the file opened: the delimiter,
+    /// quote, escape, encoding, header, backup and lenient settings.
+    Edit {
+        filename: Option<String>,
+        force: bool
+    },
+    /// Moves the cursor to a row, and optionally a column, using the numbers
+    /// shown in the grid.
+    Goto {
+        row: usize,
+        col: Option<usize>
+    },
+    /// Pads or cuts short the rows so that each has the given number of
+    /// values, or as many as most rows have, after previewing the rows
+    /// which change.
+    Normalise(Option<usize>),
+    Set(SetOption)
+}
+
+/// A setting changed with `:set`.
+#[derive(Debug, PartialEq)]
+pub enum SetOption {
+    Delimiter(CsvDelimiter),
+    Quote(u8),
+    /// The character escaping quotes, or None to double quotes instead.
+    Escape(Option<u8>),
+    Header(bool),
+    Backup(bool),
+    /// Whether files with malformed records are loaded as far as they can be
+    /// read.
+    Lenient(bool),
+    /// The encoding files are read and saved in.
+    Encoding(&'static Encoding)
+}
+
+impl Command {
+    /// Parses the text of the command line, with or without the leading `:`.
+    pub fn parse(text: &str) -> Result<Command, String> {
+        let text = text.trim_start();
+        let text = text.strip_prefix(':').unwrap_or(text).trim_start();
+        let (name, argument) = match text.find(char::is_whitespace) {
+            Some(index) => (&text[..index], text[index..].trim()),
+            None => (text, "")
+        };
+        let filename_argument = match argument.is_empty() {
+            true => None,
+            false => Some(String::from(argument))
+        };
+
+        match name {
+            "" => Err(String::from("No command entered.")),
+            "w" | "write" => Ok(Command::Write(filename_argument)),
+            "q" | "quit" if filename_argument.is_none() => Ok(Command::Quit { force: false }),
+            "q!" | "quit!" if filename_argument.is_none() => Ok(Command::Quit { force: true }),
+            "wq" | "x" => Ok(Command::WriteQuit(filename_argument)),
+            "e" | "edit" | "e!" | "edit!" => Ok(Command::Edit {
+                filename: filename_argument,
+                force: name.ends_with('!')
+            }),
+            "goto" | "g" => parse_goto(argument),
+            "normalise" | "normalize" => parse_normalise(argument),
+            "set" => parse_set(argument).map(Command::Set),
+            _ if name.chars().all(|c| c.is_ascii_digit()) && filename_argument.is_none() => {
+                parse_goto(name)
+            },
+            _ => Err(format!("Not a command: {}", text.trim_end()))
+        }
+    }
+}
+
+fn parse_goto(argument: &str) -> Result<Command, String> {
+    let usage = String::from("Usage: :goto <row> [column]");
+    let mut numbers = argument.split_whitespace().map(|number| number.parse::<usize>());
+    let row = match numbers.next() {
+        Some(Ok(row)) => row,
+        _ => return Err(usage)
+    };
+    let col = match numbers.next() {
+        Some(Ok(col)) => Some(col),
+        Some(Err(_)) => return Err(usage),
+        None => None
+    };
+    if numbers.next().is_some() {
+        return Err(usage);
+    }
+
+    Ok(Command::Goto { row, col })
+}
+
+fn parse_normalise(argument: &str) -> Result<Command, String> {
+    match argument.trim() {
+        "" => Ok(Command::Normalise(None)),
+        width => match width.parse::<usize>() {
+            Ok(width) if width > 0 => Ok(Command::Normalise(Some(width))),
+            _ => Err(String::from("Usage: :normalise [width]"))
+        }
+    }
+}
+
+/// Parses `option=value` or a flag such as `header`. Whitespace around the
+/// option and value is ignored, so a space is given as `space` or `0x20`.
+fn parse_set(argument: &str) -> Result<SetOption, String> {
+    let (option, value) = match argument.split_once('=') {
+        Some((option, value)) => (option.trim(), value.trim()),
+        None => (argument.trim(), "")
+    };
+    match option {
+        "delimiter" | "delim" => {
+            match CsvDelimiter::parse(value) {
+                Some(delimiter) => Ok(SetOption::Delimiter(delimiter)),
+                None => Err(format!(
+                    "Delimiter must be a single ASCII byte, or a name such as tab or space: {}", 
+                    value))
+            }
+        },
+        "quote" => match parse_byte(value) {
+            Some(quote) => Ok(SetOption::Quote(quote)),
+            None => Err(format!("Quote must be a single ASCII byte: {}", value))
+        },
+        "escape" if value.is_empty() => Ok(SetOption::Escape(None)),
+        "escape" => match parse_byte(value) {
+            Some(escape) => Ok(SetOption::Escape(Some(escape))),
+            None => Err(format!("Escape must be a single ASCII byte: {}", value))
+        },
+        "header" if value.is_empty() => Ok(SetOption::Header(true)),
+        "noheader" if value.is_empty() => Ok(SetOption::Header(false)),
+        "backup" if value.is_empty() => Ok(SetOption::Backup(true)),
+        "nobackup" if value.is_empty() => Ok(SetOption::Backup(false)),
+        "lenient" if value.is_empty() => Ok(SetOption::Lenient(true)),
+        "nolenient" if value.is_empty() => Ok(SetOption::Lenient(false)),
+        "encoding" | "enc" => match Encoding::for_label(value.as_bytes()) {
+            Some(encoding) => Ok(SetOption::Encoding(encoding)),
+            None => Err(format!("Unknown encoding: {}", value))
+        },
+        "" => Err(String::from("Usage: :set <option>=<value>")),
+        _ => Err(format!("Unknown option: {}", option))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{UTF_16LE, WINDOWS_1252};
+
+    use super::*;
+
+    fn set(text: &str) -> Result<SetOption, String> {
+        match Command::parse(text)? {
+            Command::Set(option) => Ok(option),
+            command => panic!("{} parsed as {:?}", text, command)
+        }
+    }
+
+    #[test]
+    fn commands_are_parsed_with_or_without_a_colon() {
+        assert_eq!(Command::parse(":w"), Ok(Command::Write(None)));
+        assert_eq!(Command::parse("  : write  out.csv "), 
+                   Ok(Command::Write(Some(String::from("out.csv")))));
+        assert_eq!(Command::parse("q"), Ok(Command::Quit { force: false }));
+        assert_eq!(Command::parse(":quit!"), Ok(Command::Quit { force: true }));
+        assert_eq!(Command::parse(":x"), Ok(Command::WriteQuit(None)));
+        assert_eq!(Command::parse(":wq a b.csv"), 
+                   Ok(Command::WriteQuit(Some(String::from("a b.csv")))));
+        assert_eq!(Command::parse(":e"), Ok(Command::Edit { filename: None, force: false }));
+        assert_eq!(Command::parse(":edit! other.csv"), Ok(Command::Edit { 
+            filename: Some(String::from("other.csv")), 
+            force: true 
+        }));
+    }
+
+    #[test]
+    fn bad_commands_are_rejected() {
+        assert_eq!(Command::parse(":"), Err(String::from("No command entered.")));
+        assert_eq!(Command::parse("  "), Err(String::from("No command entered.")));
+        assert_eq!(Command::parse(":q now "), Err(String::from("Not a command: q now")));
+        assert_eq!(Command::parse(":frobnicate"), Err(String::from("Not a command: frobnicate")));
+        assert_eq!(Command::parse(":12 x"), Err(String::from("Not a command: 12 x")));
+    }
+
+    #[test]
+    fn goto_takes_a_row_and_an_optional_column() {
+        assert_eq!(Command::parse(":goto 5"), Ok(Command::Goto { row: 5, col: None }));
+        assert_eq!(Command::parse(":g 5  3 "), Ok(Command::Goto { row: 5, col: Some(3) }));
+        assert_eq!(Command::parse(":12"), Ok(Command::Goto { row: 12, col: None }));
+        let usage = Err(String::from("Usage: :goto <row> [column]"));
+        for text in [":goto", ":goto a", ":goto -1", ":goto 1 x", ":goto 1 2 3"] {
+            assert_eq!(Command::parse(text), usage, "{}", text);
+        }
+    }
+
+    #[test]
+    fn normalise_takes_an_optional_width() {
+        assert_eq!(Command::parse(":normalise"), Ok(Command::Normalise(None)));
+        assert_eq!(Command::parse(":normalize 4"), Ok(Command::Normalise(Some(4))));
+        let usage = Err(String::from("Usage: :normalise [width]"));
+        assert_eq!(Command::parse(":normalise 0"), usage);
+        assert_eq!(Command::parse(":normalise wide"), usage);
+    }
+
+    #[test]
+    fn each_option_is_set() {
+        assert_eq!(set(":set delimiter=;"), Ok(SetOption::Delimiter(CsvDelimiter::Semicolon)));
+        assert_eq!(set(":set delim=tab"), Ok(SetOption::Delimiter(CsvDelimiter::Tab)));
+        assert_eq!(set(":set delimiter=|"), Ok(SetOption::Delimiter(CsvDelimiter::Other(b'|'))));
+        assert_eq!(set(":set quote='"), Ok(SetOption::Quote(b'\'')));
+        assert_eq!(set(":set escape=\\\\"), Ok(SetOption::Escape(Some(b'\\'))));
+        assert_eq!(set(":set escape="), Ok(SetOption::Escape(None)));
+        assert_eq!(set(":set header"), Ok(SetOption::Header(true)));
+        assert_eq!(set(":set noheader"), Ok(SetOption::Header(false)));
+        assert_eq!(set(":set backup"), Ok(SetOption::Backup(true)));
+        assert_eq!(set(":set nobackup"), Ok(SetOption::Backup(false)));
+        assert_eq!(set(":set lenient"), Ok(SetOption::Lenient(true)));
+        assert_eq!(set(":set nolenient"), Ok(SetOption::Lenient(false)));
+        assert_eq!(set(":set encoding=utf-16le"), Ok(SetOption::Encoding(UTF_16LE)));
+        assert_eq!(set(":set enc=latin1"), Ok(SetOption::Encoding(WINDOWS_1252)));
+    }
+
+    #[test]
+    fn whitespace_around_set_values_is_ignored() {
+        assert_eq!(set(":set delimiter = ; "), Ok(SetOption::Delimiter(CsvDelimiter::Semicolon)));
+        assert_eq!(set(":set quote = '"), Ok(SetOption::Quote(b'\'')));
+        assert_eq!(set(":set escape = "), Ok(SetOption::Escape(None)));
+        assert_eq!(set(":set header "), Ok(SetOption::Header(true)));
+        assert_eq!(set(":set encoding = utf-16le "), Ok(SetOption::Encoding(UTF_16LE)));
+        // so a space is given by name or value
+        assert_eq!(set(":set delimiter=space"), Ok(SetOption::Delimiter(CsvDelimiter::Space)));
+        assert_eq!(set(":set delimiter=0x20"), Ok(SetOption::Delimiter(CsvDelimiter::Space)));
+        assert!(set(":set delimiter= ").is_err());
+    }
+
+    #[test]
+    fn bad_set_values_are_rejected() {
+        assert!(set(":set delimiter=ab").is_err());
+        assert!(set(":set delimiter=é").is_err());
+        assert!(set(":set quote=").is_err());
+        assert!(set(":set escape=0xff").is_err());
+        assert!(set(":set encoding=klingon").is_err());
+        assert_eq!(set(":set header=yes"), Err(String::from("Unknown option: header")));
+        assert_eq!(set(":set colour=red"), Err(String::from("Unknown option: colour")));
+        assert_eq!(set(":set"), Err(String::from("Usage: :set <option>=<value>")));
+    }
+}
@@ -1,15 +1,71 @@
-use std::{fs, io::{self, Error as IO_Error, ErrorKind}, vec };
+use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    fs, 
+    io::{self, Read, Seek, SeekFrom, Write, Error as IO_Error},  
+    path::PathBuf,
+    vec 
+};
+
+use encoding_rs::{Encoding, UTF_8};
 
-use crate::model::utils_model::{
-    Size,
-    Position
+use crate::model::{
+    utils_model::{
+        Size,
+        Position,
+        LoadProgress,
+        RowMark
+    },
+    search_model::{
+        SearchPattern,
+        ReplaceScope
+    },
+    sort_model::{
+        ColumnType,
+        compare_cells
+    },
+    filter_model::FilterExpression,
+    dialect_model::{
+        Dialect,
+        FileFormat,
+        SAMPLE_SIZE,
+        UTF8_BOM,
+        find_records_end,
+        ends_in_quotes,
+        detect_encoding,
+        FilePositions,
+        decode_contents,
+        encode_text
+    },
+    row_store::{RowStore, RecordInfo, RowOrigin},
+    width_model::{ColumnWidths, WidthCounts},
+    problem_model::{
+        LoadProblem,
+        ProblemKind,
+        MalformedFile,
+        NormalisePreview,
+        record_start
+    }
+};
+use crate::utils::save_file::{
+    resolve_symlinks,
+    get_backup_path,
+    write_atomically,
+    FileStamp
 };
 
+/// Files larger than this are read lazily, as their rows are needed, and are
+/// indexed in the background.
+const LAZY_LOAD_SIZE: u64 = 8 * 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
 pub enum CsvDelimiter {
     Comma,
     Tab,
     Semicolon,
-    Space
+    Space,
+    /// Any other single ASCII byte, such as `|` or `0x1f`.
+    Other(u8)
 }
 
 impl Copy for CsvDelimiter {}
@@ -20,88 +76,750 @@ impl Clone for CsvDelimiter {
 }
 
 impl CsvDelimiter {
-    fn as_u8(&self) -> u8 {
+    /// Parses a delimiter given by name, such as `tab`, as a single 
+    /// character, such as `|`, or as a byte value, such as `0x1f`.
+    pub fn parse(value: &str) -> Option<CsvDelimiter> {
+        let delimiter = match value.to_ascii_lowercase().as_str() {
+            "comma" => CsvDelimiter::Comma,
+            "tab" => CsvDelimiter::Tab,
+            "semicolon" => CsvDelimiter::Semicolon,
+            "space" => CsvDelimiter::Space,
+            "pipe" => CsvDelimiter::Other(b'|'),
+            _ => CsvDelimiter::from_u8(parse_byte(value)?)
+        };
+        Some(delimiter)
+    }
+
+    pub fn from_u8(byte: u8) -> CsvDelimiter {
+        match byte {
+            b'\t' => CsvDelimiter::Tab,
+            b',' => CsvDelimiter::Comma,
+            b' ' => CsvDelimiter::Space,
+            b';' => CsvDelimiter::Semicolon,
+            _ => CsvDelimiter::Other(byte)
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
         match self {
             CsvDelimiter::Tab => b'\t',
             CsvDelimiter::Comma => b',',
             CsvDelimiter::Space => b' ',
-            CsvDelimiter::Semicolon => b';'
+            CsvDelimiter::Semicolon => b';',
+            CsvDelimiter::Other(byte) => *byte
         }
     }
 
-    fn as_char(&self) -> char {
+    pub fn as_char(&self) -> char {
         match self {
             CsvDelimiter::Tab => '\t',
             CsvDelimiter::Comma => ',',
             CsvDelimiter::Space => ' ',
-            CsvDelimiter::Semicolon => ';'
+            CsvDelimiter::Semicolon => ';',
+            CsvDelimiter::Other(byte) => *byte as char
+        }
+    }
+
+    /// Returns the name of the delimiter for showing to the user.
+    pub fn get_name(&self) -> String {
+        match self {
+            CsvDelimiter::Tab => String::from("Tab"),
+            CsvDelimiter::Comma => String::from("Comma"),
+            CsvDelimiter::Space => String::from("Space"),
+            CsvDelimiter::Semicolon => String::from("Semicolon"),
+            CsvDelimiter::Other(byte) => describe_byte(*byte)
+        }
+    }
+}
+
+/// Parses a single byte given as a character, such as `|`, an escape 
+/// sequence, such as `\t`, or a byte value, such as `0x1f`. Bytes outside of
+/// ASCII are not accepted, as in UTF-8 they are only ever part of a longer
+/// character, so a file split on one couldn't be written back the same.
+pub fn parse_byte(value: &str) -> Option<u8> {
+    if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        return u8::from_str_radix(hex, 16).ok().filter(u8::is_ascii);
+    }
+    match value {
+        "\\t" => Some(b'\t'),
+        "\\\\" => Some(b'\\'),
+        _ => match value.as_bytes() {
+            [byte] if byte.is_ascii() => Some(*byte),
+            _ => None
         }
     }
 }
 
-trait CSVPreparation {
-    fn escape_double_quote(&mut self);
-    fn wrap_double_quotes(&mut self);
+/// Shows a byte as its character when printable, otherwise as its value.
+pub fn describe_byte(byte: u8) -> String {
+    match byte.is_ascii_graphic() {
+        true => format!("'{}'", byte as char),
+        false => format!("0x{:02x}", byte)
+    }
+}
+
+/// The delimiter, quote and escape characters used to read and write a file.
+/// Unset delimiter and quote characters are detected when loading.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CsvOptions {
+    pub delimiter: Option<CsvDelimiter>,
+    pub quote: Option<u8>,
+    /// The character escaping quotes inside quoted values. When unset, quotes
+    /// are escaped by doubling them.
+    pub escape: Option<u8>,
+    /// The encoding of the file. When unset, it is detected when loading.
+    pub encoding: Option<&'static Encoding>,
+    /// When set, files with malformed records are loaded as far as they can
+    /// be read, instead of failing to load.
+    pub lenient: bool
+}
+
+/// Serializes rows following RFC 4180, generalised to any delimiter, quote 
+/// and escape character. Values are quoted when they contain the delimiter, 
+/// the quote or escape character, or a line break, or start with a BOM,
+/// which would be taken for that of the file at its start, so that reading
+/// the output gives back the same values.
+struct CsvWriter {
+    delimiter: char,
+    quote: char,
+    /// Escapes quotes inside quoted values, which are doubled when unset.
+    escape: Option<char>
 }
 
-impl CSVPreparation for String {
-    fn escape_double_quote(&mut self) {
-        let double_quote = '"';
-        if !self.contains(double_quote) {
+impl CsvWriter {
+    /// Writes the values of the row, without a line ending, so that rows
+    /// shorter than others are left short rather than padded. A row with no
+    /// values is written as an empty quoted value, so reads back as a row
+    /// with one empty value. Values in columns where force_quotes is true are
+    /// always quoted.
+    fn write_row(&self, 
+                 output: &mut String, 
+                 row: &[String], 
+                 force_quotes: impl Fn(usize) -> bool) {
+        let row_start = output.len();
+        for (col, value) in row.iter().enumerate() {
+            if col > 0 {
+                output.push(self.delimiter);
+            }
+            self.write_field(output, value, force_quotes(col));
+        }
+        if output.len() == row_start {
+            // an empty line would be skipped when reading, losing the row
+            output.push(self.quote);
+            output.push(self.quote);
+        }
+    }
+
+    fn write_field(&self, output: &mut String, value: &str, force_quotes: bool) {
+        let needs_quotes = force_quotes 
+            || value.starts_with('\u{FEFF}')
+            || value.chars().any(|c| {
+                c == self.delimiter 
+                    || c == self.quote 
+                    || Some(c) == self.escape 
+                    || c == '\n' 
+                    || c == '\r'
+            });
+        if !needs_quotes {
+            output.push_str(value);
             return;
-        } 
+        }
 
-        *self = self.replace("\"", "\"\"");
+        output.push(self.quote);
+        for c in value.chars() {
+            match self.escape {
+                Some(escape_char) if c == self.quote || c == escape_char => {
+                    output.push(escape_char);
+                },
+                None if c == self.quote => {
+                    output.push(self.quote);
+                },
+                _ => {}
+            }
+            output.push(c);
+        }
+        output.push(self.quote);
     }
+}
+
+/// A single reversible change made to the data through one of the public
+/// mutation functions. Each variant holds enough information to both undo 
+/// and redo the change.
+enum CsvOperation {
+    SetCell {
+        pos: Position,
+        old_value: String,
+        new_value: String,
+        /// The number of rows before the change, if it added or trimmed rows,
+        /// and the position and previous length of each row whose length
+        /// changed, in the order they changed. Setting a cell can pad and trim
+        /// empty cells, so this is needed to restore the exact shape of the
+        /// data. The row count is only kept when it changed, as rows indexed
+        /// since the change mustn't be dropped.
+        prev_row_count: Option<usize>,
+        prev_row_lengths: Vec<(usize, usize)>
+    },
+    InsertRow {
+        row: usize
+    },
+    RemoveRow {
+        row: usize,
+        values: Vec<String>,
+        /// Where the row was read from, if it was, so that the restored row
+        /// is written as it was read.
+        origin: Option<RowOrigin>
+    },
+    InsertCol {
+        col: usize
+    },
+    RemoveCol {
+        col: usize,
+        /// The values removed from the rows in memory, with their rows. The
+        /// values of rows still in their records are read from them again.
+        values: Vec<(usize, String)>,
+        /// The widths counted for the column.
+        widths: WidthCounts
+    },
+    /// Several existing cells changed at once, holding the position, old 
+    /// value and new value of each.
+    SetCells {
+        cells: Vec<(Position, String, String)>
+    },
+    /// The rows from first_row onwards were reordered, with order holding 
+    /// the previous position of each row, relative to first_row.
+    SortRows {
+        col: usize,
+        first_row: usize,
+        order: Vec<usize>
+    },
+    /// Rows were padded with empty values or cut short to have width values,
+    /// holding the position, previous length and cut values of each.
+    NormaliseRows {
+        width: usize,
+        rows: Vec<(usize, usize, Vec<String>)>
+    }
+}
 
-    fn wrap_double_quotes(&mut self) {
-        *self = format!("\"{}\"", self);
+impl CsvOperation {
+    fn location(&self) -> EditLocation {
+        match self {
+            CsvOperation::SetCell { pos, .. } => EditLocation::Cell(*pos),
+            CsvOperation::InsertRow { row } |
+                CsvOperation::RemoveRow { row, .. } => EditLocation::Row(*row),
+            CsvOperation::InsertCol { col } |
+                CsvOperation::RemoveCol { col, .. } |
+                CsvOperation::SortRows { col, .. } => EditLocation::Col(*col),
+            CsvOperation::SetCells { cells } => match cells.first() {
+                Some((pos, _, _)) => EditLocation::Cell(*pos),
+                None => EditLocation::Row(0)
+            },
+            CsvOperation::NormaliseRows { rows, .. } => match rows.first() {
+                Some((row, _, _)) => EditLocation::Row(*row),
+                None => EditLocation::Row(0)
+            }
+        }
     }
 }
 
+/// Describes where in the data an undone or redone operation took place, so 
+/// that the cursor can be moved back to it.
+#[derive(Clone, Copy, Debug)]
+pub enum EditLocation {
+    Cell(Position),
+    Row(usize),
+    Col(usize)
+}
+
 pub struct CsvModel {
-    data: Vec<Vec<String>>,
-    saved: bool,
+    data: RowStore,
+    /// The widths of the values in each column, kept up to date as the data
+    /// changes so that the grid doesn't need to scan every row to lay out
+    /// the columns.
+    col_widths: ColumnWidths,
     filename: Option<String>,
-    delimiter: CsvDelimiter
+    delimiter: CsvDelimiter,
+    /// The character used to quote values containing the delimiter.
+    quote: u8,
+    /// The character escaping quotes inside quoted values, or None if quotes
+    /// are doubled instead.
+    escape: Option<u8>,
+    /// The encoding files are read and saved in.
+    encoding: &'static Encoding,
+    /// How the loaded file was written, reproduced when saving.
+    format: FileFormat,
+    /// When set, a copy of each file is made before it is first overwritten.
+    make_backups: bool,
+    /// The files a backup has been made of, so that later saves don't 
+    /// replace the backup of the original file.
+    backed_up_files: HashSet<PathBuf>,
+    /// The state of the file when it was last loaded or saved, used to 
+    /// detect changes made to it by something else.
+    file_stamp: Option<FileStamp>,
+    /// The length of the file up to the end of the last complete record read
+    /// from it, where reading continues when following a growing file.
+    read_offset: u64,
+    /// The length of the file when it was last read.
+    read_len: u64,
+    /// The values of the last row when it was read from a record that hadn't
+    /// been finished yet, so that it can be replaced once it is.
+    partial_row: Option<Vec<String>>,
+    /// Set when loading the file was cancelled or stopped at a record that
+    /// couldn't be read, so the rest of the file is missing from data.
+    is_partly_loaded: bool,
+    /// When set, files with malformed records are loaded as far as they can
+    /// be read, with the problems found kept in problems.
+    is_lenient: bool,
+    /// The problems found in the records of the file when it was loaded, in
+    /// order of the rows they were found in.
+    problems: Vec<LoadProblem>,
+    /// Every operation applied to the data. Operations before history_pos 
+    /// are applied, the ones from history_pos onwards have been undone and 
+    /// can be redone.
+    history: Vec<CsvOperation>,
+    history_pos: usize,
+    /// The history_pos at the time the data was last saved, None if that 
+    /// state can no longer be reached.
+    saved_pos: Option<usize>,
+    /// When set, the first row of data holds the column names. It stays in 
+    /// data so that it is saved unchanged, but all public row positions 
+    /// refer to the rows after it.
+    has_header: bool,
+    /// When set, only the rows matching the filter are shown in the grid.
+    filter: Option<FilterExpression>,
+    /// The data rows shown in the grid while a filter is set, in order. Grid
+    /// rows past the end of these map to new rows after the end of data.
+    filtered_rows: Vec<usize>
 }
 
 impl Default for CsvModel {
     fn default() -> Self {
         CsvModel {
-            data: Vec::new(),
-            saved: true,
+            data: RowStore::default(),
+            col_widths: ColumnWidths::default(),
             filename: None,
-            delimiter: CsvDelimiter::Comma
+            delimiter: CsvDelimiter::Comma,
+            quote: b'"',
+            escape: None,
+            encoding: UTF_8,
+            format: FileFormat::default(),
+            make_backups: false,
+            backed_up_files: HashSet::new(),
+            file_stamp: None,
+            read_offset: 0,
+            read_len: 0,
+            partial_row: None,
+            is_partly_loaded: false,
+            is_lenient: false,
+            problems: Vec::new(),
+            history: Vec::new(),
+            history_pos: 0,
+            saved_pos: Some(0),
+            has_header: false,
+            filter: None,
+            filtered_rows: Vec::new()
         }
     }
 }
 
 impl CsvModel {
-    pub fn load_file(filename: &String, delimiter: &CsvDelimiter) -> Result<CsvModel, io::Error> {
-        let mut csv_model = CsvModel::default();
-        csv_model.delimiter = *delimiter; 
+    /// Loads the file using the given options. The encoding, if not given,
+    /// is detected from the start of the file. Without a delimiter, the 
+    /// delimiter, quote character if not given, and whether there is a header
+    /// row are detected from it too.
+    pub fn load_file(filename: &String, 
+                     options: &CsvOptions) -> Result<CsvModel, io::Error> {
+        let mut sample = Vec::new();
+        fs::File::open(filename)?
+            .take(SAMPLE_SIZE as u64)
+            .read_to_end(&mut sample)?;
+        let encoding = options.encoding.unwrap_or_else(|| detect_encoding(&sample));
+        let mut csv_model = match options.delimiter {
+            Some(_) => CsvModel::default_with_options(options),
+            None => {
+                let dialect = Dialect::detect(decode_contents(encoding, &sample).as_bytes(), 
+                                              sample.len() < SAMPLE_SIZE,
+                                              options.quote);
+                CsvModel {
+                    delimiter: dialect.delimiter,
+                    quote: dialect.quote,
+                    escape: options.escape,
+                    is_lenient: options.lenient,
+                    has_header: dialect.has_header,
+                    ..CsvModel::default()
+                }
+            }
+        };
+        csv_model.encoding = encoding;
 
-        let mut reader = csv::ReaderBuilder::new()
-            .delimiter(delimiter.as_u8())
-            .has_headers(false)
-            .from_path(&filename)?;
         csv_model.filename = Some(filename.to_string()); 
+        csv_model.read_file(filename)?;
+
+        Ok(csv_model)
+    }
+
+    /// Loads the file again using the current delimiter and quote character,
+    /// discarding all changes and history.
+    pub fn reload(&mut self) -> Result<(), io::Error> {
+        let filename = match &self.filename {
+            Some(name) => name.clone(),
+            None => return Err(IO_Error::other("filename not set"))
+        };
+        self.read_file(&filename)?;
+        self.history.clear();
+        self.history_pos = 0;
+        self.saved_pos = Some(0);
+        self.refresh_filter();
+        Ok(())
+    }
 
-        for row in reader.records() {
-            csv_model.data.push(row.unwrap().iter().map(|cell_value| {
-                String::from(cell_value)
-            }).collect());
+    /// Reads the data from the file, recording its format. Large files are
+    /// opened to be read lazily instead, unless their encoding changes how
+    /// the delimiters and line endings are written, as UTF-16 does. A file
+    /// with malformed records fails to load, leaving the data as it was,
+    /// unless loading leniently.
+    fn read_file(&mut self, filename: &String) -> Result<(), io::Error> {
+        if self.encoding.is_ascii_compatible() 
+            && fs::metadata(filename)?.len() > LAZY_LOAD_SIZE {
+            return self.open_file(filename);
         }
+        let raw_contents = fs::read(filename)?;
+        let file_stamp = FileStamp::new(&resolve_symlinks(filename.as_ref())?, &raw_contents);
+        let file_len = raw_contents.len();
+        // positions in the file are only found in encodings where the
+        // delimiters and line endings are written as in ASCII
+        let (records_end, is_partial) = match self.encoding.is_ascii_compatible() {
+            true => self.find_read_position(&raw_contents),
+            false => (file_len, false)
+        };
+        // records are read from the bytes of the file where the encoding
+        // allows it, so that problems are found where they are in the file,
+        // and from the text decoded from it otherwise
+        let (contents, record_encoding) = match self.encoding.is_ascii_compatible() {
+            true => (raw_contents, self.encoding),
+            false => (decode_contents(self.encoding, &raw_contents).into_owned().into_bytes(), 
+                      UTF_8)
+        };
+        let offset = match contents.starts_with(UTF8_BOM) {
+            true => UTF8_BOM.len(),
+            false => 0
+        };
+        let mut reader = self.reader_builder().from_reader(&contents[offset..]);
+        let mut record = csv::ByteRecord::new();
+        let mut problems = Vec::new();
+        let mut records = Vec::new();
+        let mut col_widths = ColumnWidths::default();
+        let (mut last_start, mut last_line) = (0, 1);
+        let mut file_positions = FilePositions::new(self.encoding, &contents);
 
-        Ok(csv_model)
+        while reader.read_byte_record(&mut record)? {
+            let (record_pos, line) = match record.position() {
+                Some(position) => (offset + position.byte() as usize, position.line()),
+                None => (offset, 1)
+            };
+            let (start, line) = record_start(record_pos as u64,
+                                             line,
+                                             contents.get(record_pos).copied());
+            let start = start as usize;
+            let mut has_invalid_bytes = false;
+            for (col, field) in record.iter().enumerate() {
+                let (value, had_errors) = record_encoding.decode_without_bom_handling(field);
+                // text decoded from other encodings already has the invalid
+                // bytes replaced
+                has_invalid_bytes |= had_errors || (record_encoding != self.encoding 
+                                                     && value.contains('\u{FFFD}'));
+                col_widths.add_value(col, &value);
+            }
+            if has_invalid_bytes {
+                problems.push(LoadProblem {
+                    row: records.len(),
+                    line,
+                    byte: file_positions.get(start),
+                    kind: ProblemKind::InvalidBytes
+                });
+            }
+            records.push(RecordInfo {
+                start: record_pos as u64,
+                len: (offset + reader.position().byte() as usize - record_pos) as u64,
+                field_count: record.len() as u32
+            });
+            (last_start, last_line) = (start, line);
+        }
+        if !records.is_empty() && ends_in_quotes(&contents[last_start..],
+                                                 self.delimiter.as_u8(),
+                                                 self.quote,
+                                                 self.escape) {
+            problems.push(LoadProblem {
+                row: records.len() - 1,
+                line: last_line,
+                byte: file_positions.get(last_start),
+                kind: ProblemKind::UnclosedQuote
+            });
+        }
+        if !problems.is_empty() && !self.is_lenient {
+            return Err(MalformedFile { problems }.into_error());
+        }
+        self.problems = problems;
+        self.is_partly_loaded = false;
+
+        self.format = FileFormat::from_contents(&contents, 
+                                                self.delimiter.as_u8(), 
+                                                self.quote, 
+                                                self.escape);
+        self.format.encoding = self.encoding;
+        self.col_widths = col_widths;
+        // the rows are read from the contents as they are needed, so that
+        // the text of each record is kept once, and untouched rows can be
+        // written back exactly
+        self.data = RowStore::from_records(contents, records, self.reader_builder(), record_encoding);
+        self.file_stamp = Some(file_stamp);
+        self.set_read_position(records_end as u64, file_len as u64, is_partial);
+        Ok(())
+    }
+
+    /// Opens a large file so that its rows are read as they are needed. The
+    /// format is recorded from the start of the file, and the rows are
+    /// indexed in the background, so that the first ones can be shown
+    /// straight away.
+    fn open_file(&mut self, filename: &String) -> Result<(), io::Error> {
+        let mut file = fs::File::open(filename)?;
+        let file_len = file.metadata()?.len();
+        let mut sample = Vec::new();
+        (&mut file).take(SAMPLE_SIZE as u64).read_to_end(&mut sample)?;
+        // the last record in the sample may be cut short
+        let (records_end, _) = self.find_read_position(&sample);
+        let mut format = FileFormat::from_contents(&sample[..records_end],
+                                                   self.delimiter.as_u8(),
+                                                   self.quote,
+                                                   self.escape);
+        format.encoding = self.encoding;
+        let mut last_byte = [b'\n'];
+        if file_len > 0 {
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last_byte)?;
+        }
+        format.has_trailing_newline = last_byte[0] == b'\n' || last_byte[0] == b'\r';
+        let offset = match format.has_bom {
+            true => UTF8_BOM.len() as u64,
+            false => 0
+        };
+
+        let (delimiter, quote, escape) = (self.delimiter.as_u8(), self.quote, self.escape);
+        self.data = RowStore::open(filename.as_ref(),
+                                   self.reader_builder(),
+                                   offset,
+                                   self.encoding,
+                                   move |raw| ends_in_quotes(raw, delimiter, quote, escape))?;
+        self.col_widths = ColumnWidths::default();
+        self.problems = Vec::new();
+        self.is_partly_loaded = false;
+        self.format = format;
+        self.file_stamp = Some(FileStamp::from_metadata(&resolve_symlinks(filename.as_ref())?)?);
+        self.set_read_position(file_len, file_len, false);
+        Ok(())
     }
 
-    pub fn default_with_delimiter(delimiter: &CsvDelimiter) -> Result<CsvModel, io::Error> {
-        let mut csv_model = CsvModel::default();
-        csv_model.delimiter = delimiter.clone();
+    /// Returns true while a large file is still being read.
+    pub fn is_loading(&self) -> bool {
+        self.data.is_indexing()
+    }
 
-        Ok(csv_model)
+    /// Returns how much of a large file has been read, while it is being
+    /// read.
+    pub fn get_load_progress(&self) -> Option<LoadProgress> {
+        let (bytes_read, file_len) = self.data.get_index_progress()?;
+        Some(LoadProgress {
+            bytes_read,
+            file_len,
+            rows_read: self.data.len()
+        })
+    }
+
+    /// Adds the rows of a large file read in the background since the last
+    /// call. The rows read so far can be viewed, but changes that need every
+    /// row wait for the rest of the file first.
+    pub fn load_more_rows(&mut self) -> Result<(), io::Error> {
+        let result = self.data.receive_records(&mut self.col_widths, &mut self.problems)
+            .and_then(|_| self.stop_at_problems());
+        self.finish_loading(&result);
+        result
+    }
+
+    /// Waits for the rest of a large file to be read, stopping at any record
+    /// which can't be read.
+    fn load_all_rows(&mut self) {
+        if self.data.is_indexing() {
+            let result = self.data.receive_all_records(&mut self.col_widths, &mut self.problems)
+                .and_then(|_| self.stop_at_problems());
+            self.finish_loading(&result);
+        }
+    }
+
+    /// Stops reading a large file once a malformed record has been read,
+    /// unless loading leniently, returning the problems found as the error.
+    fn stop_at_problems(&mut self) -> Result<(), io::Error> {
+        if self.is_lenient || self.problems.is_empty() {
+            return Ok(());
+        }
+        self.cancel_loading();
+        Err(MalformedFile { problems: self.problems.clone() }.into_error())
+    }
+
+    /// Stops reading a large file, keeping the rows read so far. The file
+    /// can't be saved over afterwards, as that would lose the rest of it.
+    pub fn cancel_loading(&mut self) {
+        if self.data.is_indexing() {
+            self.data.cancel_indexing();
+            self.is_partly_loaded = true;
+        }
+    }
+
+    /// Records where following the file continues from, once all of it has
+    /// been read, or that part of it is missing if reading it failed.
+    fn finish_loading<T>(&mut self, result: &Result<T, io::Error>) {
+        // stopping at a malformed record has already been recorded
+        if result.as_ref().is_err_and(|err| MalformedFile::from_error(err).is_none()) {
+            self.is_partly_loaded = true;
+        }
+        if self.data.is_indexing() || self.format.has_trailing_newline {
+            return;
+        }
+        let last_start = self.data.len().checked_sub(1)
+            .and_then(|last_row| self.data.get_record_start(last_row));
+        if let Some(last_start) = last_start {
+            self.set_read_position(last_start, self.read_len, true);
+        }
+    }
+
+    fn reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder.delimiter(self.delimiter.as_u8())
+            .quote(self.quote)
+            .escape(self.escape)
+            .double_quote(self.escape.is_none())
+            .has_headers(false)
+            .flexible(true);
+        builder
+    }
+
+    /// Finds where the complete records in the contents end, and whether
+    /// they are followed by a record without a line ending, which may still
+    /// be being written.
+    fn find_read_position(&self, contents: &[u8]) -> (usize, bool) {
+        let records_end = find_records_end(contents,
+                                           self.delimiter.as_u8(),
+                                           self.quote,
+                                           self.escape);
+        let is_partial = contents[records_end..].iter()
+            .any(|byte| *byte != b'\n' && *byte != b'\r');
+        (records_end, is_partial)
+    }
+
+    /// Records how much of the file has been read. When the last row was
+    /// read from a record that may still be being written, it is noted so
+    /// that it can be replaced.
+    fn set_read_position(&mut self, read_offset: u64, read_len: u64, is_partial: bool) {
+        self.read_offset = read_offset;
+        self.read_len = read_len;
+        self.partial_row = match is_partial {
+            true => self.data.last().map(|row| row.to_vec()),
+            false => None
+        };
+    }
+
+    /// Reads the records added to the end of the file since it was last read,
+    /// adding them to the end of the data. A row read from a record which was
+    /// still being written is replaced by the finished record, unless it has 
+    /// been edited. A file which has shrunk is loaded again, if there are no 
+    /// unsaved changes. Returns the number of rows added.
+    pub fn read_appended_rows(&mut self) -> Result<usize, io::Error> {
+        let filename = match &self.filename {
+            Some(name) => name.clone(),
+            None => return Err(IO_Error::other("filename not set"))
+        };
+        if self.data.is_indexing() {
+            return Ok(0);
+        }
+        if self.is_partly_loaded {
+            return Err(IO_Error::other("only part of the file was loaded"));
+        }
+        if !self.encoding.is_ascii_compatible() {
+            return Err(IO_Error::other(format!("files encoded as {} can't be followed",
+                                               self.encoding.name())));
+        }
+        let mut file = fs::File::open(&filename)?;
+        let file_len = file.metadata()?.len();
+        if file_len == self.read_len {
+            return Ok(0);
+        }
+        let previous_count = self.grid_row_count();
+        if file_len < self.read_len {
+            if !self.is_in_saved_state() {
+                return Err(IO_Error::other("the file shrank and there are unsaved changes"));
+            }
+            self.reload()?;
+            return Ok(self.grid_row_count().saturating_sub(previous_count));
+        }
+
+        let mut appended = Vec::new();
+        file.seek(SeekFrom::Start(self.read_offset))?;
+        file.read_to_end(&mut appended)?;
+        let mut rows = Vec::new();
+        let appended_text = decode_contents(self.encoding, &appended);
+        for row in self.reader_builder().from_reader(appended_text.as_bytes()).records() {
+            rows.push(row?.iter().map(String::from).collect::<Vec<String>>());
+        }
+
+        // the stamp is kept up to date so that saving doesn't report the 
+        // appended records as a change made by something else
+        let path = resolve_symlinks(filename.as_ref())?;
+        let unstamped_start = (self.read_len - self.read_offset) as usize;
+        if let Some(stamp) = &mut self.file_stamp {
+            if stamp.get_path() == path 
+                && stamp.get_size() == self.read_len 
+                && unstamped_start <= appended.len() {
+                stamp.append(&appended[unstamped_start..]);
+            }
+        }
+
+        if let Some(partial_row) = self.partial_row.take() {
+            if self.data.last().is_some_and(|row| row[..] == partial_row[..]) {
+                self.col_widths.remove_row(&partial_row);
+                self.data.pop();
+                let data_len = self.data.len();
+                self.move_problems(|row| (row < data_len).then_some(row));
+                if self.filtered_rows.last() == Some(&self.data.len()) {
+                    self.filtered_rows.pop();
+                }
+            }
+        }
+        for row in rows {
+            if self.filter.as_ref().is_some_and(|filter| filter.matches(&row)) {
+                self.filtered_rows.push(self.data.len());
+            }
+            self.col_widths.add_row(&row);
+            self.data.push(row);
+        }
+        let (records_end, is_partial) = self.find_read_position(&appended);
+        self.set_read_position(self.read_offset + records_end as u64,
+                               self.read_offset + appended.len() as u64,
+                               is_partial);
+        self.format.has_trailing_newline = self.partial_row.is_none();
+
+        Ok(self.grid_row_count().saturating_sub(previous_count))
+    }
+
+    /// Creates an empty model, using the given options when saving. Unset 
+    /// options use their defaults.
+    pub fn default_with_options(options: &CsvOptions) -> CsvModel {
+        let default = CsvModel::default();
+        CsvModel {
+            delimiter: options.delimiter.unwrap_or(default.delimiter),
+            quote: options.quote.unwrap_or(default.quote),
+            escape: options.escape,
+            encoding: options.encoding.unwrap_or(default.encoding),
+            is_lenient: options.lenient,
+            ..default
+        }
     }
 
     pub fn get_filename(&self) -> &Option<String> {
@@ -112,286 +830,1885 @@ impl CsvModel {
         self.filename = filename;
     }
 
-    pub fn _get_data(&self) -> &Vec<Vec<String>> {
-        &self.data
+    /// Sets the delimiter used when the data is next saved or reloaded.
+    pub fn set_delimiter(&mut self, delimiter: CsvDelimiter) {
+        if delimiter != self.delimiter {
+            self.delimiter = delimiter;
+            self.set_saved(false);
+        }
     }
 
-    pub fn is_in_saved_state(&self) -> bool {
-        self.saved 
+    pub fn get_quote(&self) -> char {
+        self.quote as char
     }
 
-    pub fn set_saved(&mut self, is_saved: bool) {
-        self.saved = is_saved;
+    /// Sets the quote character used when the data is next saved or reloaded.
+    pub fn set_quote(&mut self, quote: u8) {
+        if quote != self.quote {
+            self.quote = quote;
+            self.set_saved(false);
+        }
     }
- 
-    pub fn get_data_size(&self) -> Size {
-        let width = self.data.len();
-        let height = match self.data.get(0) {
-            Some(row) => row.len(),
-            None => 0
-        };
 
-        Size {
-            width,
-            height
+    pub fn get_escape(&self) -> Option<char> {
+        self.escape.map(|escape| escape as char)
+    }
+
+    /// Sets the character escaping quotes, or None to double them, used when
+    /// the data is next saved or reloaded.
+    pub fn set_escape(&mut self, escape: Option<u8>) {
+        if escape != self.escape {
+            self.escape = escape;
+            self.set_saved(false);
         }
     }
 
-    pub fn insert_row(&mut self, row_pos: usize) {
-        if row_pos < self.data.len() {
-            self.data.insert(row_pos, (0..self.get_max_row_length())
-                                        .into_iter()
-                                        .map(|_| String::from(""))
-                                        .collect());
-        } 
+    pub fn get_encoding(&self) -> &'static Encoding {
+        self.encoding
     }
 
+    /// Sets the encoding used when the data is next saved or reloaded.
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        if encoding != self.encoding {
+            self.encoding = encoding;
+            self.set_saved(false);
+        }
+    }
 
-    pub fn remove_row(&mut self, row_pos: usize) {
-        if row_pos < self.data.len() {
-            self.data.remove(row_pos);
+    /// Describes the delimiter, quote character, header row and encoding in
+    /// use, such as `Semicolon delimited, quoted with '"', with header row`.
+    /// The encoding is only mentioned when it isn't UTF-8.
+    pub fn describe_dialect(&self) -> String {
+        format!("{} delimited, quoted with {}{}, {}{}",  
+                self.delimiter.get_name(),
+                describe_byte(self.quote),
+                match self.escape {
+                    Some(escape) => format!(" escaped with {}", describe_byte(escape)),
+                    None => String::new()
+                },
+                match self.has_header {
+                    true => "with header row",
+                    false => "no header row"
+                },
+                match self.encoding == UTF_8 {
+                    true => String::new(),
+                    false => format!(", encoded as {}", self.encoding.name())
+                })
+    }
+
+    /// Returns the options the data is read and saved with, so that another
+    /// file can be loaded the same way.
+    pub fn get_options(&self) -> CsvOptions {
+        CsvOptions {
+            delimiter: Some(self.delimiter),
+            quote: Some(self.quote),
+            escape: self.escape,
+            encoding: Some(self.encoding),
+            lenient: self.is_lenient
         }
     }
 
-    pub fn insert_col(&mut self, col_pos: usize) {
-        for row in self.data.iter_mut() {
-            if col_pos < row.len() {
-                row.insert(col_pos, String::from(""));
-            }
+    /// Sets whether files with malformed records are loaded as far as they
+    /// can be read, used when the file is next loaded.
+    pub fn set_lenient(&mut self, is_lenient: bool) {
+        self.is_lenient = is_lenient;
+    }
+
+    pub fn get_problems(&self) -> &[LoadProblem] {
+        &self.problems
+    }
+
+    /// Returns the number of values most rows have. Rows with another number
+    /// of values are ragged, apart from empty rows.
+    pub fn get_modal_row_len(&self) -> usize {
+        self.data.modal_row_len()
+    }
+
+    /// Returns the number of ragged rows, including the header row.
+    pub fn get_ragged_row_count(&self) -> usize {
+        match self.data.modal_row_len() {
+            0 => 0,
+            modal_len => self.data.len() 
+                - self.data.count_rows_of_len(0) 
+                - self.data.count_rows_of_len(modal_len)
         }
     }
-   
-    pub fn remove_col(&mut self, col_pos: usize) {
-        for row in self.data.iter_mut() {
-            if col_pos < row.len() {
-                row.remove(col_pos);
+
+    /// Returns how the row shown in each grid row is marked, with a problem
+    /// found when loading it, and as ragged when it doesn't have width
+    /// values.
+    pub fn get_row_marks(&self, 
+                         first_row: usize, 
+                         row_count: usize, 
+                         width: usize) -> Vec<RowMark> {
+        (first_row..first_row + row_count).map(|grid_row| {
+            let data_row = self.to_data_row(grid_row);
+            let index = self.problems.partition_point(|problem| problem.row < data_row);
+            let row_len = self.data.row_len(data_row);
+            RowMark {
+                is_ragged: row_len > 0 && row_len != width,
+                has_problem: self.problems.get(index).is_some_and(|problem| problem.row == data_row)
             }
-        }
+        }).collect()
     }
 
-    pub fn get_data_segment(&self, 
-                            corner_pos: &Position, 
-                            grid_size: &Size) -> Vec<Vec<String>> {
+    /// Returns the grid row showing the data row, such as the row a problem
+    /// was found in, or the next shown row if a filter hides it.
+    pub fn get_grid_row_of_data_row(&self, data_row: usize) -> usize {
+        self.to_grid_row(data_row)
+    }
 
-        let current_data_height = self.data.len();
-        let current_data_width = match self.data.get(0) {
-            Some(row) => row.len(),
-            None => 0
-        };
+    pub fn has_header(&self) -> bool {
+        self.has_header
+    }
 
-        let high_row = match (corner_pos.row + grid_size.height) < current_data_height {
-            true => corner_pos.row + grid_size.height,
-            false => current_data_height
-        };
-        let high_col = match (corner_pos.col + grid_size.width) < current_data_width {
-            true => corner_pos.col + grid_size.width,
-            false => current_data_width
-        };
+    pub fn set_has_header(&mut self, has_header: bool) {
+        self.has_header = has_header;
+        self.refresh_filter();
+    }
 
-        let mut data_segment : Vec<Vec<String>> = Vec::new();
-        for row_i in corner_pos.row..high_row {
-            let mut new_row = Vec::new();
-            match self.data.get(row_i) {
-                Some(row) => {
-                    for col_i in corner_pos.col..high_col {
-                        match row.get(col_i) {
-                            Some(cell) => {
-                                new_row.push(cell.clone());
-                            },
-                            None => {}
-                        }
-                    } 
-                },
-                None => {}
-            }
-            data_segment.push(new_row);
+    /// Returns the header row, or None if the data has no header row.
+    pub fn get_header_row(&self) -> Option<Vec<String>> {
+        match self.has_header {
+            true => self.data.first().map(|row| row.to_vec()),
+            false => None
         }
+    }
 
-        data_segment
+    pub fn get_filter(&self) -> Option<&FilterExpression> {
+        self.filter.as_ref()
     }
 
-    fn get_max_row_length(&self) -> usize {
-        let mut max_length = 0;
-        for row in self.data.iter() {
-            if max_length < row.len() {
-                max_length = row.len();
-            }
-        };
-        max_length
+    /// Sets the filter deciding which rows are shown in the grid. The data 
+    /// itself is not changed, so hidden rows are still saved.
+    pub fn set_filter(&mut self, filter: Option<FilterExpression>) {
+        self.filter = filter;
+        self.refresh_filter();
     }
 
-    /// This function sets the cell value to the value of input, at the position
-    /// defined by the row and column parameters. 
-    ///
-    /// Mutates the CsvModel by changing the specified cell, as well as setting 
-    /// the saved flag to false.
-    pub fn set_cell_value(&mut self, row: usize, col: usize, input: String) {
-        /*
-         * get the row to be edited. if the row does not exist, all rows up 
-         * to and including the row number needs to be populated with empty 
-         * rows.
-         */
-        for pos in 0..=row {
-            match self.data.get_mut(pos) {
-                Some(data_prev_row) => {
-                    if data_prev_row.len() < col + 1 {
-                        let diff = col - data_prev_row.len() + 1;
-                        for _ in 0..diff {
-                            data_prev_row.push(String::new());
-                        }
-                    }
-                },
-                None => {
-                    self.data.push(vec![String::new();col + 1]);
-                }
-            };
+    /// Returns the number of rows shown in the grid, or None if no filter is
+    /// set and every row is shown.
+    pub fn get_filtered_row_count(&self) -> Option<usize> {
+        self.filter.as_ref().map(|_| self.filtered_rows.len())
+    }
+
+    /// Reapplies the filter to every row. Used when rows have been changed in
+    /// ways that can't be tracked row by row.
+    fn refresh_filter(&mut self) {
+        if self.filter.is_some() {
+            self.load_all_rows();
         }
-        let row: &mut Vec<String> = match self.data.get_mut(row) {
-            Some(data_row) => data_row,
-            None => {
-                for _ in 0..=row {
-                    self.data.push(vec![String::new();col + 1]);
-                }
-                self.data.get_mut(row).unwrap()
-            }
-        };
-        /*
-         * same as above, need to retrieve the cell to be edited, and if the 
-         * cell does not exist, populate the row so that all cells up to the 
-         * required one now exist. 
-         */
-        let cell = match row.get_mut(col) {
-            Some(cell_data) => cell_data,
-            None => {
-                let diff = col - row.len() + 1;
-                for _ in 0..diff {
-                    row.push(String::new());
-                }
-                row.get_mut(col).unwrap()
-            }
+        self.filtered_rows = match &self.filter {
+            Some(filter) => (self.header_offset()..self.data.len())
+                .filter(|row| self.data.get(*row).is_some_and(|values| filter.matches(&values)))
+                .collect(),
+            None => Vec::new()
         };
-        *cell = input;
-        self.remove_unneeded_rows();
-        self.saved = false;
     }
 
-    pub fn get_cell_value(&self, row: usize, col: usize) -> &str {
-        match self.data.get(row) {
-            Some(row_val) => {
-                match row_val.get(col) {
-                    Some(cell_val) => &cell_val[..],
-                    None => ""
-                }
+    /// Converts a grid row into the data row shown there.
+    fn to_data_row(&self, grid_row: usize) -> usize {
+        match self.filter {
+            Some(_) => match self.filtered_rows.get(grid_row) {
+                Some(data_row) => *data_row,
+                None => self.data.len().max(self.header_offset()) 
+                    + grid_row - self.filtered_rows.len()
             },
-            None => ""
+            None => grid_row + self.header_offset()
         }
     }
 
-    pub fn get_col_max_width(&self, col: usize) -> usize {
-        let mut max_width = 5;
-
-        for row in self.data.iter() {
-            match row.get(col) {
-                Some(cell_value) => {
-                    if cell_value.len() > max_width {
-                        max_width = cell_value.len();
-                    }
-                },
-                None => {}
-            }
+    /// Converts a data row into the grid row showing it. Rows hidden by the
+    /// filter give the grid row of the next shown row.
+    fn to_grid_row(&self, data_row: usize) -> usize {
+        match self.filter {
+            Some(_) => self.filtered_rows.partition_point(|row| *row < data_row),
+            None => data_row.saturating_sub(self.header_offset())
         }
-       max_width 
     }
-    
-    /// This function finds the furthest position in the data that contains a 
-    /// value, and then removes all rows and columns greater than this position.
-    fn remove_unneeded_rows(&mut self) {
-        let mut largest_row_col = (0,0);
-        for (row_pos,row) in self.data.iter().enumerate() {
-            let mut has_data = false;
-            for (col_pos,col) in row.iter().enumerate() {
-                if col.len() > 0 {
-                    largest_row_col.1 = if col_pos > largest_row_col.1 {
-                        col_pos
-                    } else {
-                        largest_row_col.1
-                    };
-                    has_data = true;
-                }
-            }
-            if has_data {
-                largest_row_col.0 = if row_pos > largest_row_col.0 {
-                    row_pos
-                } else {
-                    largest_row_col.0
-                };
-            }
-        }
-        for pos in ((largest_row_col.0 + 1)..self.data.len()).rev() {
-            if pos < self.data.len() {
-                self.data.remove(pos);
-            }
-        }
-        for row in self.data.iter_mut() {
-            for pos in ((largest_row_col.1 + 1)..row.len()).rev() {
-                if pos < row.len() {
-                    row.remove(pos);
-                }
-            }
+
+    /// The number of grid rows that show data.
+    fn grid_row_count(&self) -> usize {
+        match self.filter {
+            Some(_) => self.filtered_rows.len(),
+            None => self.data.len().saturating_sub(self.header_offset())
         }
     }
 
-    pub fn save_data_to_file(&self) -> std::io::Result<()>  {
-        match &self.filename {
-            Some(name) => {
-                if name.ends_with(".csv") {
-                    fs::write(name, self.create_csv_string())?;
-                } else {
-                    let new_name = format!("{}{}", name, ".csv");
-                    fs::write(new_name, self.create_csv_string())?;
-                }
-            },
-            None => {
-                return Err(IO_Error::new(ErrorKind::Other, "filename not set"));
-            }
+    /// Returns the number shown beside each grid row, which is the row's 
+    /// position in the unfiltered grid.
+    pub fn get_row_numbers(&self, first_row: usize, row_count: usize) -> Vec<usize> {
+        (first_row..first_row + row_count)
+            .map(|grid_row| self.to_data_row(grid_row) - self.header_offset())
+            .collect()
+    }
+
+    /// Returns the grid row showing the row with the given row number, or
+    /// the next shown row if a filter hides it.
+    pub fn get_grid_row(&self, row_number: usize) -> usize {
+        self.to_grid_row(row_number + self.header_offset())
+    }
+
+    /// The number of rows at the start of data which are not part of the 
+    /// grid, used to convert between grid rows and data rows.
+    fn header_offset(&self) -> usize {
+        match self.has_header {
+            true => 1,
+            false => 0
         }
-        Ok(())
     }
 
-    fn create_csv_string(&self) -> String {
-        let lengths: Vec<usize> = self.data.iter().map(|row| row.len()).collect();
-        let num_cols = match lengths.iter().max() {
-            Some(value) => *value,
-            None => 0
+    pub fn is_in_saved_state(&self) -> bool {
+        self.saved_pos == Some(self.history_pos)
+    }
+
+    pub fn set_saved(&mut self, is_saved: bool) {
+        self.saved_pos = match is_saved {
+            true => Some(self.history_pos),
+            false => None
         };
-        let delim_char = self.delimiter.as_char();
-        let double_quote = '"';
-        let output = self.data.iter().fold(String::new(), |mut sum, row| {
-            let mut row_value = row.iter().fold(
-                String::new(), 
-                |mut row_sum, cell| {
-                    let mut cell_val = cell.to_owned();
-                    if cell.contains(double_quote) {
-                        cell_val.escape_double_quote(); 
-                    } 
-                    if cell.contains(delim_char) || cell.contains(double_quote) {
-                        cell_val.wrap_double_quotes();
+    }
+ 
+    pub fn get_data_size(&self) -> Size {
+        let height = self.data.len().saturating_sub(self.header_offset());
+        let width = self.get_max_row_length();
+
+        Size {
+            width,
+            height
+        }
+    }
+
+    pub fn insert_row(&mut self, row_pos: usize) {
+        let grid_row = row_pos;
+        let row_pos = self.to_data_row(grid_row);
+        if row_pos < self.data.len() {
+            self.apply_insert_row(row_pos);
+            self.record_operation(CsvOperation::InsertRow { row: row_pos });
+            if self.filter.is_some() {
+                // the new row is shown, even though it may not match
+                for data_row in self.filtered_rows.iter_mut() {
+                    if *data_row >= row_pos {
+                        *data_row += 1;
                     }
-                    row_sum.push_str(&cell_val);
-                    row_sum.push(delim_char);
-                    row_sum
+                }
+                self.filtered_rows.insert(grid_row, row_pos);
+            }
+        } 
+    }
+
+    pub fn remove_row(&mut self, row_pos: usize) {
+        let grid_row = row_pos;
+        let row_pos = self.to_data_row(grid_row);
+        if row_pos < self.data.len() {
+            let (values, origin) = self.apply_remove_row(row_pos);
+            self.record_operation(CsvOperation::RemoveRow { 
+                row: row_pos, 
+                values,
+                origin
+            });
+            if self.filter.is_some() {
+                self.filtered_rows.remove(grid_row);
+                for data_row in self.filtered_rows.iter_mut() {
+                    if *data_row > row_pos {
+                        *data_row -= 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Inserts an empty column, once the whole file has been indexed, so that
+    /// the widths of the columns of records indexed later aren't shifted.
+    pub fn insert_col(&mut self, col_pos: usize) {
+        self.load_all_rows();
+        if col_pos < self.get_max_row_length() {
+            self.apply_insert_col(col_pos);
+            self.record_operation(CsvOperation::InsertCol { col: col_pos });
+        }
+    }
+   
+    /// Removes the column, once the whole file has been indexed, so that the
+    /// widths of the columns of records indexed later aren't shifted.
+    pub fn remove_col(&mut self, col_pos: usize) {
+        self.load_all_rows();
+        if col_pos < self.get_max_row_length() {
+            let (values, widths) = self.apply_remove_col(col_pos);
+            self.record_operation(CsvOperation::RemoveCol { 
+                col: col_pos, 
+                values,
+                widths
+            });
+        }
+    }
+
+    /// Reverts the most recent operation that has not already been undone.
+    ///
+    /// Returns the location of the reverted operation, or None if there is 
+    /// nothing left to undo.
+    pub fn undo(&mut self) -> Option<EditLocation> {
+        if self.history_pos == 0 {
+            return None;
+        }
+        self.history_pos -= 1;
+        // history is taken out while applying so the data can be mutated
+        let history = std::mem::take(&mut self.history);
+        let operation = &history[self.history_pos];
+        match operation {
+            CsvOperation::SetCell { pos, old_value, prev_row_count, prev_row_lengths, .. } => {
+                // only empty cells are ever padded or trimmed, so resizing
+                // with empty cells restores the previous data exactly. The
+                // shape is restored first, as the cell may have been trimmed
+                if let Some(prev_row_count) = prev_row_count {
+                    self.data.resize(*prev_row_count);
+                }
+                for (row, length) in prev_row_lengths.iter().rev() {
+                    if let Some(mut values) = self.data.get_mut(*row) {
+                        values.resize(*length, String::new());
+                    }
+                }
+                if let Some(mut row) = self.data.get_mut(pos.row) {
+                    if let Some(cell) = row.get_mut(pos.col) {
+                        self.col_widths.remove_value(pos.col, cell);
+                        self.col_widths.add_value(pos.col, old_value);
+                        *cell = old_value.clone();
+                    }
+                }
+            },
+            CsvOperation::InsertRow { row } => {
+                self.apply_remove_row(*row);
+            },
+            CsvOperation::RemoveRow { row, values, origin } => {
+                self.col_widths.add_row(values);
+                self.data.insert(*row, values.clone(), *origin);
+                self.move_problems(|problem_row| match problem_row >= *row {
+                    true => Some(problem_row + 1),
+                    false => Some(problem_row)
                 });
-            if row.len() < num_cols {
-                for _ in row.len()..num_cols {
-                    row_value.push(',');
+            },
+            CsvOperation::InsertCol { col } => {
+                self.col_widths.remove_col(*col);
+                self.data.revert_insert_col(*col);
+            },
+            CsvOperation::RemoveCol { col, values, widths } => {
+                self.col_widths.insert_col(*col, widths.clone());
+                self.data.revert_remove_col(*col, values);
+            },
+            CsvOperation::SetCells { cells } => {
+                for (pos, old_value, _) in cells {
+                    self.set_existing_cell(*pos, old_value.clone());
+                }
+            },
+            CsvOperation::SortRows { first_row, order, .. } => {
+                self.apply_order(*first_row, order, true);
+            },
+            CsvOperation::NormaliseRows { rows, .. } => {
+                for (row, len, cut_values) in rows {
+                    if let Some(mut values) = self.data.get_mut(*row) {
+                        values.truncate(*len);
+                        for value in cut_values {
+                            self.col_widths.add_value(values.len(), value);
+                            values.push(value.clone());
+                        }
+                    }
+                }
+            }
+        }
+        let location = operation.location();
+        self.history = history;
+        self.refresh_filter();
+        Some(self.to_grid_location(location))
+    }
+
+    /// Reapplies the most recently undone operation.
+    ///
+    /// Returns the location of the reapplied operation, or None if there is
+    /// nothing to redo.
+    pub fn redo(&mut self) -> Option<EditLocation> {
+        if self.history_pos >= self.history.len() {
+            return None;
+        }
+        let mut history = std::mem::take(&mut self.history);
+        let operation = &mut history[self.history_pos];
+        match operation {
+            CsvOperation::SetCell { pos, new_value, .. } => {
+                self.apply_cell_value(pos.row, pos.col, new_value.clone());
+            },
+            CsvOperation::InsertRow { row } => {
+                self.apply_insert_row(*row);
+            },
+            CsvOperation::RemoveRow { row, .. } => {
+                self.apply_remove_row(*row);
+            },
+            CsvOperation::InsertCol { col } => {
+                self.apply_insert_col(*col);
+            },
+            CsvOperation::RemoveCol { col, values, widths } => {
+                // rows moved into memory since are now among the rows whose
+                // values are kept
+                (*values, *widths) = self.apply_remove_col(*col);
+            },
+            CsvOperation::SetCells { cells } => {
+                for (pos, _, new_value) in cells {
+                    self.set_existing_cell(*pos, new_value.clone());
+                }
+            },
+            CsvOperation::SortRows { first_row, order, .. } => {
+                self.apply_order(*first_row, order, false);
+            },
+            CsvOperation::NormaliseRows { width, rows } => {
+                for (row, _, _) in rows {
+                    self.apply_row_len(*row, *width);
+                }
+            }
+        }
+        let location = operation.location();
+        self.history = history;
+        self.history_pos += 1;
+        self.refresh_filter();
+        Some(self.to_grid_location(location))
+    }
+
+    /// Converts a location in data rows to one in grid rows. Operations on 
+    /// the header row are located at the first grid row.
+    fn to_grid_location(&self, location: EditLocation) -> EditLocation {
+        match location {
+            EditLocation::Cell(pos) => EditLocation::Cell(Position { 
+                row: self.to_grid_row(pos.row), 
+                col: pos.col 
+            }),
+            EditLocation::Row(row) => EditLocation::Row(self.to_grid_row(row)),
+            EditLocation::Col(col) => EditLocation::Col(col)
+        }
+    }
+
+    /// Adds an operation that has just been applied to the history, 
+    /// discarding any operations that had been undone.
+    fn record_operation(&mut self, operation: CsvOperation) {
+        self.history.truncate(self.history_pos);
+        if let Some(saved_pos) = self.saved_pos {
+            if saved_pos > self.history_pos {
+                self.saved_pos = None;
+            }
+        }
+        self.history.push(operation);
+        self.history_pos += 1;
+    }
+
+    /// Sets the value of a cell which is known to exist.
+    fn set_existing_cell(&mut self, pos: Position, value: String) {
+        if let Some(mut row) = self.data.get_mut(pos.row) {
+            if let Some(cell) = row.get_mut(pos.col) {
+                self.col_widths.remove_value(pos.col, cell);
+                self.col_widths.add_value(pos.col, &value);
+                *cell = value;
+            }
+        }
+    }
+
+    fn apply_insert_row(&mut self, row_pos: usize) {
+        self.data.insert(row_pos, vec![String::new(); self.get_max_row_length()], None);
+        self.move_problems(|row| match row >= row_pos {
+            true => Some(row + 1),
+            false => Some(row)
+        });
+    }
+
+    /// Removes the row, returning its values and where it was read from, if
+    /// it was.
+    fn apply_remove_row(&mut self, row_pos: usize) -> (Vec<String>, Option<RowOrigin>) {
+        let (values, origin) = self.data.remove(row_pos);
+        self.col_widths.remove_row(&values);
+        self.move_problems(|row| match row.cmp(&row_pos) {
+            Ordering::Less => Some(row),
+            Ordering::Equal => None,
+            Ordering::Greater => Some(row - 1)
+        });
+        (values, origin)
+    }
+
+    /// Reorders the rows from first_row onwards, so that the row at position
+    /// i is the one which was at order[i], or reverts that order.
+    fn apply_order(&mut self, first_row: usize, order: &[usize], revert: bool) {
+        let mut new_positions = vec![0; order.len()];
+        match revert {
+            true => {
+                self.data.revert_order(first_row, order);
+                new_positions.copy_from_slice(order);
+            },
+            false => {
+                self.data.apply_order(first_row, order);
+                for (new_pos, old_pos) in order.iter().enumerate() {
+                    new_positions[*old_pos] = new_pos;
+                }
+            }
+        }
+        self.move_problems(|row| match row.checked_sub(first_row) {
+            Some(pos) => Some(new_positions.get(pos).map_or(row, |new_pos| first_row + new_pos)),
+            None => Some(row)
+        });
+    }
+
+    /// Moves the problems found when loading along with the rows they were
+    /// found in. new_row gives the new position of a row, or None if it has
+    /// been removed, along with its problems.
+    fn move_problems(&mut self, new_row: impl Fn(usize) -> Option<usize>) {
+        if self.problems.is_empty() {
+            return;
+        }
+        self.problems.retain_mut(|problem| match new_row(problem.row) {
+            Some(row) => {
+                problem.row = row;
+                true
+            },
+            None => false
+        });
+        self.problems.sort_by_key(|problem| problem.row);
+    }
+
+    fn apply_insert_col(&mut self, col_pos: usize) {
+        self.col_widths.insert_col(col_pos, WidthCounts::new());
+        self.data.insert_col(col_pos);
+    }
+
+    /// Removes the column from every row long enough to contain it, returning
+    /// the values removed from the rows in memory and the widths counted for
+    /// the column.
+    fn apply_remove_col(&mut self, col_pos: usize) -> (Vec<(usize, String)>, WidthCounts) {
+        let widths = self.col_widths.remove_col(col_pos);
+        (self.data.remove_col(col_pos), widths)
+    }
+
+    pub fn get_data_segment(&self, 
+                            corner_pos: &Position, 
+                            grid_size: &Size) -> Vec<Vec<String>> {
+
+        let current_data_width = self.get_max_row_length();
+
+        let high_row = match (corner_pos.row + grid_size.height) < self.grid_row_count() {
+            true => corner_pos.row + grid_size.height,
+            false => self.grid_row_count()
+        };
+        let high_col = match (corner_pos.col + grid_size.width) < current_data_width {
+            true => corner_pos.col + grid_size.width,
+            false => current_data_width
+        };
+
+        let mut data_segment : Vec<Vec<String>> = Vec::new();
+        for row_i in corner_pos.row..high_row {
+            let mut new_row = Vec::new();
+            if let Some(row) = self.data.get(self.to_data_row(row_i)) {
+                for col_i in corner_pos.col..high_col {
+                    if let Some(cell) = row.get(col_i) {
+                        new_row.push(cell.clone());
+                    }
+                } 
+            }
+            data_segment.push(new_row);
+        }
+
+        data_segment
+    }
+
+    /// Returns the column names for the columns in the grid, or None if the 
+    /// data has no header row.
+    pub fn get_header_segment(&self, 
+                              corner_pos: &Position, 
+                              grid_size: &Size) -> Option<Vec<String>> {
+        if !self.has_header {
+            return None;
+        }
+        let header_row = match self.data.first() {
+            Some(row) => row,
+            None => return Some(Vec::new())
+        };
+        Some(header_row.iter()
+             .skip(corner_pos.col)
+             .take(grid_size.width)
+             .cloned()
+             .collect())
+    }
+
+    fn get_max_row_length(&self) -> usize {
+        self.data.max_row_len()
+    }
+
+    /// This function sets the cell value to the value of input, at the position
+    /// defined by the row and column parameters. 
+    ///
+    /// Mutates the CsvModel by changing the specified cell, and records the 
+    /// change in the undo history. Setting a cell to the value it already 
+    /// holds is not recorded.
+    pub fn set_cell_value(&mut self, row: usize, col: usize, input: String) {
+        // the rest of the file is only waited for when the cell is past the
+        // rows indexed so far, as it may be in a row still to be indexed
+        if self.to_data_row(row) >= self.data.len() {
+            self.load_all_rows();
+        }
+        let old_value = self.get_cell_value(row, col);
+        let row = self.to_data_row(row);
+        if old_value == input {
+            return;
+        }
+        let prev_row_count = self.data.len();
+        let prev_row_lengths = self.apply_cell_value(row, col, input.clone()).into_iter()
+            .filter(|(changed_row, _)| *changed_row < prev_row_count)
+            .collect();
+        let prev_row_count = (self.data.len() != prev_row_count).then_some(prev_row_count);
+        self.record_operation(CsvOperation::SetCell {
+            pos: Position { row, col },
+            old_value,
+            new_value: input,
+            prev_row_count,
+            prev_row_lengths
+        });
+        if self.filter.is_some() {
+            // rows created past the end of the data are shown once edited, 
+            // and rows trimmed from the end are no longer shown
+            if self.filtered_rows.last().is_none_or(|last_row| *last_row < row) {
+                self.filtered_rows.push(row);
+            }
+            let data_len = self.data.len();
+            self.filtered_rows.retain(|data_row| *data_row < data_len);
+        }
+    }
+
+    /// Sets the cell value without recording the change, adding rows and
+    /// cells to fit the cell and trimming any trailing empty rows and
+    /// columns. Returns the position and previous length of each row whose
+    /// length changed, in the order they were changed.
+    fn apply_cell_value(&mut self, row: usize, col: usize, input: String) -> Vec<(usize, usize)> {
+        let mut changed_rows = Vec::new();
+        if row >= self.data.len() {
+            self.data.resize(row + 1);
+        }
+        if let Some(mut row_values) = self.data.get_mut(row) {
+            if row_values.len() < col + 1 {
+                changed_rows.push((row, row_values.len()));
+                row_values.resize(col + 1, String::new());
+            }
+            self.col_widths.remove_value(col, &row_values[col]);
+            self.col_widths.add_value(col, &input);
+            row_values[col] = input;
+        }
+        changed_rows.extend(self.data.trim());
+        let data_len = self.data.len();
+        self.move_problems(|row| (row < data_len).then_some(row));
+        changed_rows
+    }
+
+    pub fn get_cell_value(&self, row: usize, col: usize) -> String {
+        match self.data.get(self.to_data_row(row)) {
+            Some(row_val) => {
+                match row_val.get(col) {
+                    Some(cell_val) => cell_val.clone(),
+                    None => String::new()
+                }
+            },
+            None => String::new()
+        }
+    }
+
+    /// Finds the next cell matching the pattern, searching row by row from 
+    /// the from position and wrapping around the ends of the data. The header
+    /// row is not searched.
+    ///
+    /// The cell at from is checked first if include_from is set, otherwise 
+    /// it is checked last, once the search has wrapped around.
+    pub fn find_match(&self, 
+                      pattern: &SearchPattern, 
+                      from: Position, 
+                      forward: bool,
+                      include_from: bool) -> Option<Position> {
+        let row_count = self.grid_row_count();
+        let from_key = (from.row, from.col);
+        // cells searched before wrapping around
+        let in_first_pass = |pos: &Position| {
+            let key = (pos.row, pos.col);
+            (forward && key > from_key) 
+                || (!forward && key < from_key)
+                || (include_from && key == from_key)
+        };
+        let row_positions = |row: usize| {
+            (0..self.data.row_len(self.to_data_row(row))).map(move |col| Position { row, col })
+        };
+        let is_match = |pos: &Position| {
+            self.data.get(self.to_data_row(pos.row))
+                .is_some_and(|row| row.get(pos.col).is_some_and(|value| pattern.is_match(value)))
+        };
+        let from_row = from.row.min(row_count);
+
+        if forward {
+            (from_row..row_count).flat_map(row_positions)
+                .filter(in_first_pass)
+                .chain((0..(from_row + 1).min(row_count)).flat_map(row_positions)
+                       .filter(|pos| !in_first_pass(pos)))
+                .find(is_match)
+        } else {
+            (0..(from_row + 1).min(row_count)).rev()
+                .flat_map(|row| row_positions(row).rev())
+                .filter(in_first_pass)
+                .chain((from_row..row_count).rev()
+                       .flat_map(|row| row_positions(row).rev())
+                       .filter(|pos| !in_first_pass(pos)))
+                .find(is_match)
+        }
+    }
+
+    /// Sorts the rows by the values in the column, comparing them as numbers,
+    /// dates or text depending on what the column holds. The sort is stable,
+    /// so sorting by one column and then another orders rows by both. The 
+    /// header row is not moved.
+    ///
+    /// Returns the new grid row of the row which was at follow_row.
+    pub fn sort_by_column(&mut self, 
+                          col: usize, 
+                          descending: bool, 
+                          follow_row: usize) -> usize {
+        self.load_all_rows();
+        let first_row = self.header_offset().min(self.data.len());
+        let follow_row = self.to_data_row(follow_row);
+        let values: Vec<String> = self.data.iter().skip(first_row).map(|row| {
+            match row.get(col) {
+                Some(value) => value.clone(),
+                None => String::new()
+            }
+        }).collect();
+        let column_type = ColumnType::detect(values.iter().map(|value| value.as_str()));
+        let mut order: Vec<usize> = (0..values.len()).collect();
+        order.sort_by(|a, b| compare_cells(&values[*a],
+                                           &values[*b],
+                                           column_type,
+                                           descending));
+
+        let new_follow_row = match order.iter()
+            .position(|old_pos| first_row + *old_pos == follow_row) {
+            Some(new_pos) => first_row + new_pos,
+            None => follow_row
+        };
+        if order.iter().enumerate().any(|(new_pos, old_pos)| new_pos != *old_pos) {
+            self.apply_order(first_row, &order, false);
+            self.record_operation(CsvOperation::SortRows { col, first_row, order });
+            self.refresh_filter();
+        }
+        self.to_grid_row(new_follow_row)
+    }
+
+    /// Returns the number of cells within the scope that would be changed by
+    /// replacing matches of the pattern.
+    pub fn count_replacements(&self, 
+                              pattern: &SearchPattern, 
+                              replacement: &str,
+                              scope: &ReplaceScope) -> usize {
+        self.collect_replacements(pattern, replacement, scope).len()
+    }
+
+    /// Replaces every match of the pattern within the scope, recording all 
+    /// the changed cells as a single operation. Only existing cells are 
+    /// changed, so the shape of the data stays the same.
+    ///
+    /// Returns the number of cells changed.
+    pub fn replace_matches(&mut self,
+                           pattern: &SearchPattern,
+                           replacement: &str,
+                           scope: &ReplaceScope) -> usize {
+        self.load_all_rows();
+        let cells = self.collect_replacements(pattern, replacement, scope);
+        let count = cells.len();
+        if count > 0 {
+            for (pos, _, new_value) in cells.iter() {
+                self.set_existing_cell(*pos, new_value.clone());
+            }
+            self.record_operation(CsvOperation::SetCells { cells });
+        }
+        count
+    }
+
+    /// Lists the rows which normalising the data pads with empty values or
+    /// cuts short, so that every row has width values, or as many as most
+    /// rows have if no width is given. Empty rows are left as they are.
+    pub fn preview_normalise(&mut self, width: Option<usize>) -> NormalisePreview {
+        self.load_all_rows();
+        let width = width.unwrap_or_else(|| self.data.modal_row_len());
+        let rows = (0..self.data.len()).filter(|row| {
+            let row_len = self.data.row_len(*row);
+            row_len > 0 && row_len != width
+        }).collect();
+        NormalisePreview { width, rows }
+    }
+
+    /// Describes how normalising changes the data row, to list it in the 
+    /// preview.
+    pub fn describe_normalised_row(&self, row: usize, width: usize) -> String {
+        let name = match self.has_header && row == 0 {
+            true => String::from("Header"),
+            false => format!("Row {}", row - self.header_offset())
+        };
+        let row_len = self.data.row_len(row);
+        if row_len <= width {
+            return format!("{} - {} values, padded to {}", name, row_len, width);
+        }
+        let cut_values: Vec<String> = match self.data.get(row) {
+            Some(values) => values[width..].iter().map(|value| format!("{:?}", value)).collect(),
+            None => Vec::new()
+        };
+        format!("{} - {} values, cutting {}", name, row_len, cut_values.join(", "))
+    }
+
+    /// Pads or cuts short the rows listed in the preview, recording them all
+    /// as a single operation.
+    ///
+    /// Returns the number of rows changed.
+    pub fn normalise_rows(&mut self, preview: &NormalisePreview) -> usize {
+        let rows: Vec<(usize, usize, Vec<String>)> = preview.rows.iter().map(|row| {
+            let (len, cut_values) = self.apply_row_len(*row, preview.width);
+            (*row, len, cut_values)
+        }).collect();
+        let count = rows.len();
+        if count > 0 {
+            self.record_operation(CsvOperation::NormaliseRows { 
+                width: preview.width, 
+                rows 
+            });
+        }
+        count
+    }
+
+    /// Pads the row with empty values or cuts it short so that it has len
+    /// values, returning its previous length and the values cut from it.
+    fn apply_row_len(&mut self, row: usize, len: usize) -> (usize, Vec<String>) {
+        match self.data.get_mut(row) {
+            Some(mut values) => {
+                let prev_len = values.len();
+                let cut_values = match prev_len > len {
+                    true => values.split_off(len),
+                    false => {
+                        values.resize(len, String::new());
+                        Vec::new()
+                    }
+                };
+                for (offset, value) in cut_values.iter().enumerate() {
+                    self.col_widths.remove_value(len + offset, value);
+                }
+                (prev_len, cut_values)
+            },
+            None => (0, Vec::new())
+        }
+    }
+
+    /// Finds every cell within the scope that changes when matches are 
+    /// replaced, returning the data position, old value and new value of 
+    /// each.
+    fn collect_replacements(&self,
+                            pattern: &SearchPattern,
+                            replacement: &str,
+                            scope: &ReplaceScope) -> Vec<(Position, String, String)> {
+        let mut cells = Vec::new();
+        for grid_row in 0..self.grid_row_count() {
+            let row_pos = self.to_data_row(grid_row);
+            let row = match self.data.get(row_pos) {
+                Some(row) => row,
+                None => continue
+            };
+            for (col_pos, cell_value) in row.iter().enumerate() {
+                let grid_pos = Position { row: grid_row, col: col_pos };
+                if !scope.contains(&grid_pos) {
+                    continue;
+                }
+                let new_value = pattern.replace_all(cell_value, replacement);
+                if new_value != *cell_value {
+                    cells.push((Position { row: row_pos, col: col_pos },
+                                cell_value.clone(),
+                                new_value.into_owned()));
+                }
+            }
+        }
+        cells
+    }
+
+    pub fn get_col_max_width(&self, col: usize) -> usize {
+        self.col_widths.get(col).max(5)
+    }
+    
+    /// Sets whether a `.bak` copy of each file is made before it is first 
+    /// overwritten.
+    pub fn makes_backups(&self) -> bool {
+        self.make_backups
+    }
+
+    pub fn set_make_backups(&mut self, make_backups: bool) {
+        self.make_backups = make_backups;
+    }
+
+    /// Saves the data to the file, replacing it atomically so that it is 
+    /// never left part written. Symlinks are followed, so that the file they
+    /// point to is replaced.
+    pub fn save_data_to_file(&mut self) -> std::io::Result<()>  {
+        self.load_all_rows();
+        let path = self.get_save_path()?;
+        let filename = self.filename.clone().unwrap_or_default();
+        let is_read_path = self.file_stamp.as_ref()
+            .is_some_and(|stamp| stamp.get_path() == path);
+        if self.is_partly_loaded && is_read_path {
+            return Err(IO_Error::other(
+                    "only part of the file was loaded, so saving over it would lose the rest"));
+        }
+
+        if self.make_backups && !self.backed_up_files.contains(&path) && path.exists() {
+            fs::copy(&path, get_backup_path(&path))?;
+            self.backed_up_files.insert(path.clone());
+        }
+        let mut read_position = (0, 0, false);
+        self.file_stamp = Some(write_atomically(&path, |output| {
+            read_position = self.write_csv(output)?;
+            Ok(())
+        })?);
+        if resolve_symlinks(filename.as_ref()).is_ok_and(|read_path| read_path == path) {
+            let (records_end, written_len, is_partial) = read_position;
+            self.set_read_position(records_end, written_len, is_partial);
+        }
+        Ok(())
+    }
+
+    /// Checks whether the file that saving would overwrite has been changed
+    /// by something else since it was loaded or last saved.
+    pub fn has_file_changed(&self) -> bool {
+        let path = match self.get_save_path() {
+            Ok(path) => path,
+            Err(_) => return false
+        };
+        match &self.file_stamp {
+            Some(stamp) if stamp.get_path() == path => stamp.has_changed(),
+            _ => false
+        }
+    }
+
+    /// Returns the path of the file the data is saved to, following symlinks.
+    fn get_save_path(&self) -> std::io::Result<PathBuf> {
+        let path = match &self.filename {
+            Some(name) => {
+                if name.ends_with(".csv") {
+                    PathBuf::from(name)
+                } else {
+                    PathBuf::from(format!("{}{}", name, ".csv"))
                 }
+            },
+            None => {
+                return Err(IO_Error::other("filename not set"));
+            }
+        };
+        resolve_symlinks(&path)
+    }
+
+    /// Writes the data as CSV in the encoding set. Rows which haven't changed
+    /// are written exactly as they were read. Returns where the complete records end,
+    /// the length written, and whether the last record was left without a
+    /// line ending.
+    fn write_csv(&self, output: &mut dyn Write) -> io::Result<(u64, u64, bool)> {
+        let writer = CsvWriter {
+            delimiter: self.delimiter.as_char(),
+            quote: self.get_quote(),
+            escape: self.get_escape()
+        };
+        let line_ending = encode_text(self.encoding, self.format.line_ending.as_str())?;
+        let is_format_kept = self.format.is_written_with(self.delimiter.as_u8(),
+                                                         self.quote,
+                                                         self.escape);
+        let mut written_len = 0;
+        let mut last_row_start = 0;
+        let mut line = String::new();
+        // UTF-16 needs a BOM to be read back, a UTF-8 BOM is only kept, and
+        // other encodings have no way of writing one
+        let has_bom = match self.encoding == UTF_8 {
+            true => self.format.has_bom && self.format.encoding == UTF_8,
+            false => !self.encoding.is_ascii_compatible()
+        };
+        if has_bom {
+            let bom = encode_text(self.encoding, "\u{FEFF}")?;
+            output.write_all(&bom)?;
+            written_len += bom.len();
+        }
+        for row_pos in 0..self.data.len() {
+            if row_pos > 0 {
+                output.write_all(&line_ending)?;
+                written_len += line_ending.len();
+            }
+            last_row_start = written_len;
+            let raw = match is_format_kept {
+                true => self.data.get_raw_record(row_pos, self.encoding),
+                false => None
+            };
+            if let Some(raw) = raw {
+                output.write_all(&raw)?;
+                written_len += raw.len();
+                continue;
+            }
+            let row = match self.data.get(row_pos) {
+                Some(row) => row,
+                None => continue
+            };
+            line.clear();
+            writer.write_row(&mut line, &row, |col| self.format.is_quoted_col(col));
+            let encoded_line = encode_text(self.encoding, &line)?;
+            output.write_all(&encoded_line)?;
+            written_len += encoded_line.len();
+        }
+
+        if self.data.is_empty() {
+            return Ok((0, written_len as u64, false));
+        }
+        match self.format.has_trailing_newline {
+            true => {
+                output.write_all(&line_ending)?;
+                written_len += line_ending.len();
+                Ok((written_len as u64, written_len as u64, false))
+            },
+            false => Ok((last_row_start as u64, written_len as u64, true))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{search_model::SearchOptions, row_store::rows_looked_at};
+
+    /// Returns a path in the temporary directory for the test to write to.
+    pub(super) fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("csv_tui_{}_{}.csv", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn comma_options() -> CsvOptions {
+        CsvOptions {
+            delimiter: Some(CsvDelimiter::Comma),
+            ..CsvOptions::default()
+        }
+    }
+
+    /// Writes the contents to a file, loads it and saves it after making the
+    /// edits, returning what was saved.
+    fn save_after_edits(name: &str, contents: &[u8], edit: impl Fn(&mut CsvModel)) -> Vec<u8> {
+        let path = temp_path(name);
+        fs::write(&path, contents).unwrap();
+        let mut model = CsvModel::load_file(&path, &comma_options()).unwrap();
+        edit(&mut model);
+        model.save_data_to_file().unwrap();
+        let saved = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        saved
+    }
+
+    #[test]
+    fn backup_is_made_once_per_session() {
+        let path = temp_path("backup");
+        let backup_path = get_backup_path(std::path::Path::new(&path));
+        let _ = fs::remove_file(&backup_path);
+        fs::write(&path, b"a\n").unwrap();
+        let mut model = CsvModel::load_file(&path, &comma_options()).unwrap();
+        model.save_data_to_file().unwrap();
+        assert!(!backup_path.exists());
+        model.set_make_backups(true);
+        model.set_cell_value(0, 0, String::from("b"));
+        model.save_data_to_file().unwrap();
+        model.set_cell_value(0, 0, String::from("c"));
+        model.save_data_to_file().unwrap();
+        // the backup still holds the file as it was before this session
+        assert_eq!(fs::read(&backup_path).unwrap(), b"a\n");
+        assert_eq!(fs::read(&path).unwrap(), b"c\n");
+        fs::remove_file(&backup_path).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn append_to_file(path: &str, contents: &[u8]) {
+        fs::OpenOptions::new().append(true).open(path).unwrap().write_all(contents).unwrap();
+    }
+
+    #[test]
+    fn appended_records_are_added_to_the_end() {
+        let path = temp_path("follow_append");
+        fs::write(&path, b"a,b\n1,2\n").unwrap();
+        let mut model = CsvModel::load_file(&path, &comma_options()).unwrap();
+        assert_eq!(model.read_appended_rows().unwrap(), 0);
+        append_to_file(&path, b"3,4\n5,6\n");
+        assert_eq!(model.read_appended_rows().unwrap(), 2);
+        assert_eq!(model.get_cell_value(3, 1), "6");
+        // the appended records aren't a change made by something else
+        assert!(!model.has_file_changed());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn partial_last_line_is_completed_by_a_later_append() {
+        let path = temp_path("follow_partial");
+        fs::write(&path, b"a,b\n1,").unwrap();
+        let mut model = CsvModel::load_file(&path, &comma_options()).unwrap();
+        assert_eq!(model.data.len(), 2);
+        assert_eq!(model.get_cell_value(1, 0), "1");
+        append_to_file(&path, b"2");
+        // the row is replaced rather than added again
+        assert_eq!(model.read_appended_rows().unwrap(), 0);
+        assert_eq!(model.data.get(1).unwrap()[..], ["1", "2"]);
+        append_to_file(&path, b"3\n4,5\n");
+        assert_eq!(model.read_appended_rows().unwrap(), 1);
+        assert_eq!(model.data.get(1).unwrap()[..], ["1", "23"]);
+        assert_eq!(model.data.get(2).unwrap()[..], ["4", "5"]);
+        assert_eq!(model.data.len(), 3);
+        assert!(!model.has_file_changed());
+        model.save_data_to_file().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"a,b\n1,23\n4,5\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn edited_partial_row_is_kept() {
+        let path = temp_path("follow_edited");
+        fs::write(&path, b"a,b\n1,").unwrap();
+        let mut model = CsvModel::load_file(&path, &comma_options()).unwrap();
+        model.set_cell_value(1, 0, String::from("x"));
+        append_to_file(&path, b"2\n");
+        assert_eq!(model.read_appended_rows().unwrap(), 1);
+        assert_eq!(model.data.get(1).unwrap()[..], ["x", ""]);
+        assert_eq!(model.data.get(2).unwrap()[..], ["1", "2"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncated_or_rotated_file_is_loaded_again() {
+        let path = temp_path("follow_truncate");
+        fs::write(&path, b"a,b\n1,2\n3,4\n").unwrap();
+        let mut model = CsvModel::load_file(&path, &comma_options()).unwrap();
+        fs::write(&path, b"x,y\n").unwrap();
+        model.read_appended_rows().unwrap();
+        assert_eq!(model.data.len(), 1);
+        assert_eq!(model.get_cell_value(0, 0), "x");
+        // a rotated file is replaced by a new one, which starts out shorter
+        fs::rename(&path, format!("{}.1", path)).unwrap();
+        fs::write(&path, b"z\n").unwrap();
+        model.read_appended_rows().unwrap();
+        assert_eq!(model.get_cell_value(0, 0), "z");
+        // but not while there are edits which would be lost
+        model.set_cell_value(0, 0, String::from("edited"));
+        fs::write(&path, b"").unwrap();
+        assert!(model.read_appended_rows().is_err());
+        assert_eq!(model.get_cell_value(0, 0), "edited");
+        fs::remove_file(format!("{}.1", path)).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn undo_and_redo_return_where_the_edit_was() {
+        let mut model = CsvModel::default();
+        model.data.push(vec![String::from("a"), String::from("b")]);
+        model.data.push(vec![String::from("c"), String::from("d")]);
+        model.set_cell_value(1, 1, String::from("x"));
+        model.insert_row(1);
+        model.remove_col(0);
+        assert!(matches!(model.undo(), Some(EditLocation::Col(0))));
+        assert!(matches!(model.undo(), Some(EditLocation::Row(1))));
+        assert!(matches!(model.undo(), 
+                         Some(EditLocation::Cell(Position { row: 1, col: 1 }))));
+        assert!(model.undo().is_none());
+        assert!(matches!(model.redo(), 
+                         Some(EditLocation::Cell(Position { row: 1, col: 1 }))));
+        assert!(matches!(model.redo(), Some(EditLocation::Row(1))));
+        assert!(matches!(model.redo(), Some(EditLocation::Col(0))));
+        assert!(model.redo().is_none());
+    }
+
+    #[test]
+    fn saved_state_follows_undo_and_redo() {
+        let mut model = CsvModel::default();
+        model.data.push(vec![String::from("a")]);
+        assert!(model.is_in_saved_state());
+        model.set_cell_value(0, 0, String::from("b"));
+        assert!(!model.is_in_saved_state());
+        model.set_saved(true);
+        model.set_cell_value(0, 0, String::from("c"));
+        assert!(!model.is_in_saved_state());
+        model.undo();
+        assert!(model.is_in_saved_state());
+        model.undo();
+        assert!(!model.is_in_saved_state());
+        model.redo();
+        assert!(model.is_in_saved_state());
+        // a new edit after undoing past the save can't get back to it
+        model.undo();
+        model.set_cell_value(0, 0, String::from("d"));
+        model.undo();
+        model.redo();
+        assert!(!model.is_in_saved_state());
+        model.undo();
+        assert!(!model.is_in_saved_state());
+        model.set_saved(false);
+        assert!(!model.is_in_saved_state());
+    }
+
+    /// Returns a model of a single column holding the values, whose first 
+    /// value is the header row.
+    fn model_with_header(values: &[&str]) -> CsvModel {
+        let mut model = CsvModel::default();
+        for value in values {
+            model.data.push(vec![String::from(*value)]);
+        }
+        model.set_has_header(true);
+        model
+    }
+
+    fn first_col(model: &CsvModel) -> Vec<String> {
+        model.data.iter().map(|row| row[0].clone()).collect()
+    }
+
+    #[test]
+    fn edit_locations_skip_the_header_row() {
+        let mut model = model_with_header(&["name", "a"]);
+        model.set_cell_value(0, 0, String::from("b"));
+        assert_eq!(model.data.get(1).unwrap()[0], "b");
+        assert!(matches!(model.undo(), 
+                         Some(EditLocation::Cell(Position { row: 0, col: 0 }))));
+        assert!(matches!(model.redo(), 
+                         Some(EditLocation::Cell(Position { row: 0, col: 0 }))));
+    }
+
+    #[test]
+    fn toggling_the_header_keeps_undo_on_the_edited_row() {
+        let mut model = model_with_header(&["name", "a"]);
+        model.set_cell_value(0, 0, String::from("b"));
+        model.set_has_header(false);
+        assert_eq!(model.get_cell_value(1, 0), "b");
+        // the location is now a grid row further down, as the header is shown
+        assert!(matches!(model.undo(), 
+                         Some(EditLocation::Cell(Position { row: 1, col: 0 }))));
+        assert_eq!(first_col(&model), ["name", "a"]);
+        model.set_has_header(true);
+        assert!(matches!(model.redo(), 
+                         Some(EditLocation::Cell(Position { row: 0, col: 0 }))));
+        assert_eq!(first_col(&model), ["name", "b"]);
+    }
+
+    fn find(model: &CsvModel, 
+            query: &str, 
+            options: SearchOptions, 
+            from: (usize, usize), 
+            forward: bool) -> Option<(usize, usize)> {
+        let pattern = SearchPattern::new(query, &options).unwrap();
+        let from = Position { row: from.0, col: from.1 };
+        model.find_match(&pattern, from, forward, false).map(|pos| (pos.row, pos.col))
+    }
+
+    fn search_model() -> CsvModel {
+        model_of_rows([
+            vec![String::from("apple"), String::from("Pear")],
+            vec![String::from("plum"), String::from("apple")],
+            vec![String::from("pear"), String::from("fig")]
+        ])
+    }
+
+    #[test]
+    fn search_finds_matches_forward_and_backward() {
+        let model = search_model();
+        let options = SearchOptions::default();
+        assert_eq!(find(&model, "apple", options, (0, 0), true), Some((1, 1)));
+        assert_eq!(find(&model, "p", options, (1, 0), true), Some((1, 1)));
+        assert_eq!(find(&model, "apple", options, (1, 1), false), Some((0, 0)));
+        assert_eq!(find(&model, "pl", options, (2, 1), false), Some((1, 1)));
+        assert_eq!(find(&model, "kiwi", options, (0, 0), true), None);
+    }
+
+    #[test]
+    fn search_wraps_around_the_ends() {
+        let model = search_model();
+        let options = SearchOptions::default();
+        // from the last cell forward to the first, and the first back to
+        // the last
+        assert_eq!(find(&model, "apple", options, (2, 1), true), Some((0, 0)));
+        assert_eq!(find(&model, "pear", options, (0, 0), false), Some((2, 0)));
+        // the only match is found again after going all the way around
+        assert_eq!(find(&model, "fig", options, (2, 1), true), Some((2, 1)));
+        assert_eq!(find(&model, "fig", options, (2, 1), false), Some((2, 1)));
+    }
+
+    #[test]
+    fn search_options_are_used() {
+        let model = search_model();
+        let case_insensitive = SearchOptions { case_insensitive: true, ..Default::default() };
+        let regex = SearchOptions { use_regex: true, ..Default::default() };
+        assert_eq!(find(&model, "Pear", SearchOptions::default(), (0, 1), true), 
+                   Some((0, 1)));
+        assert_eq!(find(&model, "Pear", case_insensitive, (0, 1), true), Some((2, 0)));
+        assert_eq!(find(&model, "^p.*m$", regex, (0, 0), true), Some((1, 0)));
+        // without the regex option the query is matched literally
+        assert_eq!(find(&model, "^p.*m$", SearchOptions::default(), (0, 0), true), None);
+    }
+
+    #[test]
+    fn header_row_is_not_searched() {
+        let mut model = model_with_header(&["name", "a", "name"]);
+        let pattern = SearchPattern::new("name", &SearchOptions::default()).unwrap();
+        let origin = Position { row: 0, col: 0 };
+        let found = model.find_match(&pattern, origin, true, true);
+        assert!(matches!(found, Some(Position { row: 1, col: 0 })));
+        let found = model.find_match(&pattern, Position { row: 1, col: 0 }, true, false);
+        assert!(matches!(found, Some(Position { row: 1, col: 0 })));
+        model.set_has_header(false);
+        let found = model.find_match(&pattern, origin, true, true);
+        assert!(matches!(found, Some(Position { row: 0, col: 0 })));
+    }
+
+    fn replace_in(scope: ReplaceScope) -> (usize, Vec<Vec<String>>) {
+        let mut model = model_of_rows((0..3).map(|_| vec![String::from("ab"); 3]));
+        let options = SearchOptions { use_regex: true, ..Default::default() };
+        let pattern = SearchPattern::new("(a)(b)", &options).unwrap();
+        let count = model.count_replacements(&pattern, "$2$1", &scope);
+        assert_eq!(model.replace_matches(&pattern, "$2$1", &scope), count);
+        (count, model.data.iter().map(|row| row.to_vec()).collect())
+    }
+
+    #[test]
+    fn replacing_changes_only_cells_in_scope() {
+        let pos = |row, col| Position { row, col };
+        let (count, rows) = replace_in(ReplaceScope::Table);
+        assert_eq!(count, 9);
+        assert!(rows.iter().flatten().all(|value| value == "ba"));
+        let (count, rows) = replace_in(ReplaceScope::Column(1));
+        assert_eq!(count, 3);
+        assert!(rows.iter().all(|row| row == &["ab", "ba", "ab"]));
+        let (count, rows) = replace_in(ReplaceScope::Range(pos(2, 2), pos(1, 1)));
+        assert_eq!(count, 4);
+        assert_eq!(rows, [["ab", "ab", "ab"], ["ab", "ba", "ba"], ["ab", "ba", "ba"]]);
+        let (count, rows) = replace_in(ReplaceScope::Range(pos(0, 2), pos(0, 2)));
+        assert_eq!(count, 1);
+        assert_eq!(rows[0], ["ab", "ab", "ba"]);
+    }
+
+    #[test]
+    fn replacing_everything_is_undone_at_once() {
+        let mut model = model_of_rows((0..3).map(|_| vec![String::from("a$b")]));
+        let pattern = SearchPattern::new("$", &SearchOptions::default()).unwrap();
+        assert_eq!(model.replace_matches(&pattern, "$1", &ReplaceScope::Table), 3);
+        assert!(model.data.iter().all(|row| row[..] == ["a$1b"]));
+        assert!(!model.is_in_saved_state());
+        model.undo();
+        assert!(model.data.iter().all(|row| row[..] == ["a$b"]));
+        assert!(model.is_in_saved_state());
+        model.redo();
+        assert!(model.data.iter().all(|row| row[..] == ["a$1b"]));
+    }
+
+    #[test]
+    fn sorting_is_stable_and_follows_the_cursor_row() {
+        let rows = [["b", "1"], ["a", "2"], ["b", "3"], ["a", "4"]];
+        let mut model = model_of_rows(rows.map(|row| row.map(String::from).to_vec()));
+        let second_col = |model: &CsvModel| -> Vec<String> {
+            model.data.iter().map(|row| row[1].clone()).collect()
+        };
+        // the row of "b", "3" is followed from grid row 2
+        assert_eq!(model.sort_by_column(0, false, 2), 3);
+        assert_eq!(second_col(&model), ["2", "4", "1", "3"]);
+        assert_eq!(model.sort_by_column(0, true, 3), 1);
+        assert_eq!(second_col(&model), ["1", "3", "2", "4"]);
+        // sorting an already sorted column isn't recorded
+        model.sort_by_column(0, true, 0);
+        model.undo();
+        assert_eq!(second_col(&model), ["2", "4", "1", "3"]);
+    }
+
+    #[test]
+    fn header_row_is_not_sorted() {
+        let mut model = model_with_header(&["size", "10", "9", "100"]);
+        // the header doesn't stop the column being sorted as numbers
+        assert_eq!(model.sort_by_column(0, false, 0), 1);
+        assert_eq!(first_col(&model), ["size", "9", "10", "100"]);
+        model.sort_by_column(0, true, 0);
+        assert_eq!(first_col(&model), ["size", "100", "10", "9"]);
+        model.undo();
+        model.undo();
+        assert_eq!(first_col(&model), ["size", "10", "9", "100"]);
+    }
+
+    /// Returns a model of rows of a letter and the row's number, filtered to
+    /// the rows with an a, which are the even ones.
+    fn filtered_model() -> CsvModel {
+        let mut model = model_of_rows((0..5).map(|row| {
+            let letter = if row % 2 == 0 { "a" } else { "b" };
+            vec![String::from(letter), row.to_string()]
+        }));
+        model.set_filter(Some(FilterExpression::parse("col0 = a", None).unwrap()));
+        model
+    }
+
+    fn shown_rows(model: &CsvModel) -> Vec<usize> {
+        model.get_row_numbers(0, model.get_filtered_row_count().unwrap())
+    }
+
+    #[test]
+    fn edits_while_filtered_change_the_shown_rows() {
+        let mut model = filtered_model();
+        assert_eq!(shown_rows(&model), [0, 2, 4]);
+        model.set_cell_value(1, 1, String::from("x"));
+        assert_eq!(model.data.get(2).unwrap()[1], "x");
+        // a row edited so that it no longer matches stays shown
+        model.set_cell_value(1, 0, String::from("b"));
+        assert_eq!(shown_rows(&model), [0, 2, 4]);
+        assert_eq!(model.get_cell_value(1, 0), "b");
+        // until the filter is applied again, such as by undo
+        model.undo();
+        assert_eq!(model.get_cell_value(1, 0), "a");
+        model.undo();
+        assert_eq!(model.get_cell_value(1, 1), "2");
+        assert_eq!(shown_rows(&model), [0, 2, 4]);
+    }
+
+    #[test]
+    fn rows_inserted_and_removed_while_filtered_keep_the_others_shown() {
+        let mut model = filtered_model();
+        model.insert_row(1);
+        // the new row is shown before the row the cursor was on
+        assert_eq!(shown_rows(&model), [0, 2, 3, 5]);
+        assert!(model.data.get(2).unwrap().iter().all(|value| value.is_empty()));
+        model.remove_row(0);
+        assert_eq!(shown_rows(&model), [1, 2, 4]);
+        assert_eq!(model.get_cell_value(2, 1), "4");
+        // the empty inserted row is hidden once undo applies the filter again
+        assert!(matches!(model.undo(), Some(EditLocation::Row(0))));
+        assert_eq!(shown_rows(&model), [0, 3, 5]);
+        assert!(matches!(model.undo(), Some(EditLocation::Row(1))));
+        assert_eq!(shown_rows(&model), [0, 2, 4]);
+        model.redo();
+        assert_eq!(shown_rows(&model), [0, 3, 5]);
+        assert_eq!(model.data.len(), 6);
+    }
+
+    #[test]
+    fn header_row_is_not_filtered() {
+        let mut model = model_with_header(&["name", "a", "name", "b"]);
+        let header = model.get_header_row();
+        let filter = FilterExpression::parse("name != a", header.as_deref()).unwrap();
+        model.set_filter(Some(filter));
+        assert_eq!(model.get_filtered_row_count(), Some(2));
+        assert_eq!(model.get_row_numbers(0, 2), [1, 2]);
+        assert_eq!(model.get_header_row(), Some(vec![String::from("name")]));
+    }
+
+    #[test]
+    fn parse_byte_accepts_ascii_bytes() {
+        assert_eq!(parse_byte("|"), Some(b'|'));
+        assert_eq!(parse_byte("\\t"), Some(b'\t'));
+        assert_eq!(parse_byte("0x1f"), Some(0x1f));
+        assert_eq!(parse_byte("0X7F"), Some(0x7f));
+    }
+
+    #[test]
+    fn parse_byte_rejects_bytes_outside_ascii() {
+        assert_eq!(parse_byte("0xa7"), None);
+        assert_eq!(parse_byte("0x80"), None);
+        assert_eq!(parse_byte("§"), None);
+        assert_eq!(CsvDelimiter::parse("0xa7"), None);
+    }
+
+    /// Writes the contents to a file and loads it without --lenient.
+    fn load_contents(name: &str, contents: &[u8]) -> io::Result<CsvModel> {
+        let path = temp_path(name);
+        fs::write(&path, contents).unwrap();
+        let model = CsvModel::load_file(&path, &comma_options());
+        fs::remove_file(&path).unwrap();
+        model
+    }
+
+    #[test]
+    fn ragged_records_are_loaded_without_problems() {
+        let model = load_contents("ragged", b"a,b,c\n1\n2,3,4,5\n").unwrap();
+        assert!(model.get_problems().is_empty());
+        assert_eq!(model.data.row_len(1), 1);
+        assert_eq!(model.data.row_len(2), 4);
+    }
+
+    #[test]
+    fn unclosed_quote_is_a_problem() {
+        let err = load_contents("unclosed", b"a,b\n\"1,2\n3,4\n").err().unwrap();
+        let problems = &MalformedFile::from_error(&err).unwrap().problems;
+        assert_eq!(problems.len(), 1);
+        assert_eq!((problems[0].line, problems[0].byte), (2, 4));
+        assert_eq!(problems[0].kind, ProblemKind::UnclosedQuote);
+    }
+
+    #[test]
+    fn invalid_bytes_are_a_problem() {
+        let mut options = comma_options();
+        options.encoding = Some(UTF_8);
+        let path = temp_path("invalid_bytes");
+        fs::write(&path, b"a,b\n1,\xff\n").unwrap();
+        let err = CsvModel::load_file(&path, &options).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        let problems = &MalformedFile::from_error(&err).unwrap().problems;
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].kind, ProblemKind::InvalidBytes);
+    }
+
+    /// Saves the rows to a new file with commas, returning what was saved.
+    fn save_rows(name: &str, rows: &[&[&str]]) -> Vec<u8> {
+        let path = temp_path(name);
+        let mut model = CsvModel::default_with_options(&comma_options());
+        for row in rows {
+            model.data.push(row.iter().map(|value| value.to_string()).collect());
+        }
+        model.set_filename(Some(path.clone()));
+        model.save_data_to_file().unwrap();
+        let saved = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        saved
+    }
+
+    #[test]
+    fn short_rows_are_saved_without_padding() {
+        assert_eq!(save_rows("short_rows", &[&["a"], &["b", "c"]]), b"a\nb,c\n");
+    }
+
+    #[test]
+    fn row_without_values_is_saved_as_an_empty_value() {
+        let saved = save_rows("empty_row", &[&["a", "b"], &[], &["c"]]);
+        assert_eq!(saved, b"a,b\n\"\"\nc\n");
+        let model = load_contents("empty_row_loaded", &saved).unwrap();
+        assert_eq!(model.data.get(1).unwrap().to_vec(), vec![String::new()]);
+    }
+
+    #[test]
+    fn undoing_clearing_a_cell_restores_its_value() {
+        let mut model = CsvModel::default();
+        model.data.push(vec![String::from("a"), String::from("b")]);
+        model.set_cell_value(4, 3, String::from("x"));
+        model.set_cell_value(4, 3, String::new());
+        assert_eq!(model.data.len(), 1);
+        model.undo();
+        assert_eq!(model.data.len(), 5);
+        assert_eq!(model.get_cell_value(4, 3), "x");
+        assert_eq!(model.col_widths.get(3), 1);
+        model.undo();
+        assert_eq!(model.data.len(), 1);
+        assert_eq!(model.get_cell_value(0, 1), "b");
+    }
+
+    /// Returns a model holding the rows, with the widths of their values.
+    fn model_of_rows(rows: impl IntoIterator<Item = Vec<String>>) -> CsvModel {
+        let mut model = CsvModel::default();
+        for row in rows {
+            model.col_widths.add_row(&row);
+            model.data.push(row);
+        }
+        model
+    }
+
+    fn col_widths(model: &CsvModel) -> Vec<usize> {
+        (0..model.get_max_row_length()).map(|col| model.col_widths.get(col)).collect()
+    }
+
+    #[test]
+    fn column_widths_follow_edits() {
+        let rows = [["a", "bbb"], ["cc", "d"]];
+        let mut model = model_of_rows(rows.map(|row| row.map(String::from).to_vec()));
+        model.set_cell_value(1, 1, String::from("eeeee"));
+        assert_eq!(col_widths(&model), [2, 5]);
+        model.set_cell_value(1, 1, String::new());
+        assert_eq!(col_widths(&model), [2, 3]);
+        model.remove_row(0);
+        assert_eq!(col_widths(&model), [2, 0]);
+        model.insert_row(0);
+        model.set_cell_value(0, 2, String::from("ffff"));
+        assert_eq!(col_widths(&model), [2, 0, 4]);
+        model.insert_col(1);
+        assert_eq!(col_widths(&model), [2, 0, 0, 4]);
+        model.remove_col(0);
+        assert_eq!(col_widths(&model), [0, 0, 4]);
+        for _ in 0..6 {
+            model.undo();
+        }
+        assert_eq!(col_widths(&model), [2, 5]);
+        for _ in 0..6 {
+            model.redo();
+        }
+        assert_eq!(col_widths(&model), [0, 0, 4]);
+    }
+
+    /// Returns the number of rows looked at while editing cells and finding
+    /// the widest value of their columns in a model of row_count rows.
+    fn rows_looked_at_by_width_updates(row_count: usize) -> usize {
+        let rows = (0..row_count).map(|row| vec![row.to_string(), String::from("value")]);
+        let mut model = model_of_rows(rows);
+        let looked_at = rows_looked_at();
+        for edit in 0..200 {
+            let row = row_count - 1 - edit % 100;
+            model.set_cell_value(row, edit % 2, "x".repeat(edit % 20));
+            model.get_col_max_width(edit % 2);
+        }
+        for _ in 0..200 {
+            model.undo();
+        }
+        rows_looked_at() - looked_at
+    }
+
+    #[test]
+    fn width_updates_do_not_scan_the_rows() {
+        let few_rows = rows_looked_at_by_width_updates(1_000);
+        assert!(few_rows < 1_000);
+        assert_eq!(rows_looked_at_by_width_updates(100_000), few_rows);
+    }
+
+    #[test]
+    fn another_file_is_loaded_with_the_same_options() {
+        let mut model = CsvModel::default();
+        model.set_delimiter(CsvDelimiter::Semicolon);
+        model.set_quote(b'\'');
+        model.set_escape(Some(b'\\'));
+        model.set_encoding(encoding_rs::WINDOWS_1252);
+        model.set_lenient(true);
+        let path = temp_path("same_options");
+        fs::write(&path, b"'a;\\'b';\xe9\n'c\n").unwrap();
+        let loaded = CsvModel::load_file(&path, &model.get_options());
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.describe_dialect(), model.describe_dialect());
+        assert_eq!(loaded.get_problems().len(), 1);
+        assert_eq!(loaded.data.get(0).unwrap().to_vec(), ["a;'b", "é"]);
+    }
+
+    fn marks_of(model: &CsvModel) -> Vec<(bool, bool)> {
+        model.get_row_marks(0, model.grid_row_count(), model.get_modal_row_len()).iter()
+            .map(|mark| (mark.is_ragged, mark.has_problem))
+            .collect()
+    }
+
+    #[test]
+    fn ragged_rows_are_marked_against_the_modal_width() {
+        let mut model = load_contents("ragged_marks", b"a,b,c\n1,2,3\n4\n5,6,7,8\n").unwrap();
+        assert_eq!(model.get_modal_row_len(), 3);
+        assert_eq!(marks_of(&model),
+                   [(false, false), (false, false), (true, false), (true, false)]);
+        model.set_has_header(true);
+        assert_eq!(marks_of(&model), [(false, false), (true, false), (true, false)]);
+        assert_eq!(model.get_ragged_row_count(), 2);
+    }
+
+    #[test]
+    fn ragged_rows_with_problems_have_both_marks() {
+        let mut options = comma_options();
+        options.lenient = true;
+        let path = temp_path("both_marks");
+        fs::write(&path, b"a,b,c\n1,2,3\n4,\"5\n").unwrap();
+        let model = CsvModel::load_file(&path, &options);
+        fs::remove_file(&path).unwrap();
+        let model = model.unwrap();
+        assert_eq!(marks_of(&model), [(false, false), (false, false), (true, true)]);
+    }
+
+    #[test]
+    fn normalise_preview_lists_the_rows_which_change() {
+        let contents = b"a,b,c\n1\n2,3,4,5\n6,7,8\n";
+        let mut model = load_contents("normalise_preview", contents).unwrap();
+        let preview = model.preview_normalise(None);
+        assert_eq!((preview.width, &preview.rows[..]), (3, &[1, 2][..]));
+        assert_eq!(model.describe_normalised_row(1, 3), "Row 1 - 1 values, padded to 3");
+        assert_eq!(model.describe_normalised_row(2, 3), "Row 2 - 4 values, cutting \"5\"");
+        let wider = model.preview_normalise(Some(4));
+        assert_eq!(wider.rows, [0, 1, 3]);
+        model.set_has_header(true);
+        assert_eq!(model.describe_normalised_row(0, 4), "Header - 3 values, padded to 4");
+    }
+
+    #[test]
+    fn normalising_pads_and_cuts_the_previewed_rows() {
+        let contents = b"a,b,c\n1\n2,3,4,5\n6,7,8\n";
+        let mut model = load_contents("normalise", contents).unwrap();
+        let preview = model.preview_normalise(None);
+        assert_eq!(model.normalise_rows(&preview), 2);
+        assert_eq!(model.data.get(1).unwrap().to_vec(), ["1", "", ""]);
+        assert_eq!(model.data.get(2).unwrap().to_vec(), ["2", "3", "4"]);
+        assert_eq!(model.get_ragged_row_count(), 0);
+        assert!(marks_of(&model).iter().all(|mark| *mark == (false, false)));
+        model.undo();
+        assert_eq!(model.data.get(2).unwrap().to_vec(), ["2", "3", "4", "5"]);
+        assert_eq!(marks_of(&model)[1..3], [(true, false), (true, false)]);
+    }
+
+    #[test]
+    fn untouched_file_is_saved_as_read() {
+        let contents = b"\"a\",b,\"c d\"\n1,\"2\",3\n'x',\"\"\"q\"\"\",\n";
+        assert_eq!(save_after_edits("untouched", contents, |_| {}), contents);
+    }
+
+    #[test]
+    fn editing_a_cell_keeps_trailing_delimiters_of_other_rows() {
+        let saved = save_after_edits("trailing_delimiters",
+                                     b"\"a\",b,\r\n1,2,\r\n3,4,\r\n",
+                                     |model| model.set_cell_value(0, 0, String::from("9")));
+        assert_eq!(saved, b"9,b,\r\n1,2,\r\n3,4,\r\n");
+    }
+
+    #[test]
+    fn editing_a_cell_keeps_quoting_of_other_rows() {
+        let saved = save_after_edits("quoting",
+                                     b"\"a\",\"b\"\n\"c\",d\ne,\"f\"\n",
+                                     |model| model.set_cell_value(1, 1, String::from("x,y")));
+        assert_eq!(saved, b"\"a\",\"b\"\n\"c\",\"x,y\"\ne,\"f\"\n");
+    }
+
+    #[test]
+    fn rows_with_the_same_values_keep_their_own_quoting() {
+        let contents = b"\"x\",1\nx,1\nx,\"1\"\n";
+        let saved = save_after_edits("same_values", contents, |model| {
+            model.set_cell_value(2, 0, String::from("y"));
+            model.set_cell_value(2, 0, String::from("x"));
+        });
+        assert_eq!(saved, contents);
+    }
+
+    #[test]
+    fn editing_a_cell_keeps_line_endings_and_bom() {
+        let saved = save_after_edits("crlf_bom",
+                                     b"\xef\xbb\xbfname,value\r\n\"x, y\",1\r\nz,2",
+                                     |model| model.set_cell_value(2, 1, String::from("3")));
+        assert_eq!(saved, b"\xef\xbb\xbfname,value\r\n\"x, y\",1\r\nz,3");
+    }
+
+    #[test]
+    fn undoing_an_edit_restores_the_row_as_read() {
+        let contents = b"'a',\"b\"\r\n\"c\" ,d\r\n";
+        let saved = save_after_edits("undo", contents, |model| {
+            model.set_cell_value(0, 1, String::from("e"));
+            model.remove_row(1);
+            model.undo();
+            model.undo();
+        });
+        assert_eq!(saved, contents);
+    }
+
+    #[test]
+    fn undoing_column_operations_restores_the_rows_as_read() {
+        let contents = b"\"a\",b,c\r\n1,\"2\"\r\n3\r\n";
+        let saved = save_after_edits("undo_cols", contents, |model| {
+            model.remove_col(1);
+            model.set_cell_value(1, 1, String::from("x"));
+            model.insert_col(0);
+            for _ in 0..3 {
+                model.undo();
             }
-            row_value.pop();
-            row_value.push('\n');
-            sum.push_str(&row_value);
-            sum
         });
-        
-        output
+        assert_eq!(saved, contents);
+    }
+
+    #[test]
+    fn redoing_a_removed_column_removes_it_from_rows_edited_since() {
+        let saved = save_after_edits("redo_cols", b"a,b,c\n1,2\n3\n", |model| {
+            model.remove_col(1);
+            model.set_cell_value(0, 1, String::from("x"));
+            model.undo();
+            model.undo();
+            model.redo();
+        });
+        assert_eq!(saved, b"a,c\n1\n3\n");
+    }
+
+    #[test]
+    fn editing_a_lazily_loaded_file_does_not_wait_for_indexing() {
+        let path = temp_path("lazy_edit");
+        let mut contents = Vec::new();
+        while contents.len() as u64 <= LAZY_LOAD_SIZE {
+            contents.extend_from_slice(b"a,b,cccccccccccccccccccccccccccccc\n");
+        }
+        fs::write(&path, &contents).unwrap();
+        let mut model = CsvModel::load_file(&path, &comma_options()).unwrap();
+        fs::remove_file(&path).unwrap();
+        // waits for the first batch of records, which is far from the last
+        while model.data.is_empty() {
+            model.data.receive_records(&mut model.col_widths, &mut model.problems).unwrap();
+        }
+        model.set_cell_value(0, 1, String::from("x"));
+        assert!(model.data.is_indexing());
+        model.load_all_rows();
+        let row_count = model.data.len();
+        assert_eq!(row_count, contents.len() / 35);
+        model.undo();
+        assert_eq!(model.data.len(), row_count);
+        assert_eq!(model.get_cell_value(0, 1), "b");
+    }
+
+    #[test]
+    fn editing_a_lazily_loaded_file_only_changes_the_edited_row() {
+        let mut contents = Vec::new();
+        let mut row = 0;
+        while contents.len() as u64 <= LAZY_LOAD_SIZE {
+            contents.extend_from_slice(format!("\"{}\",value {},\r\n", row, row).as_bytes());
+            row += 1;
+        }
+        let saved = save_after_edits("lazy", &contents, |model| {
+            model.set_cell_value(1, 1, String::from("edited"));
+        });
+        let mut expected = contents.clone();
+        expected.splice(14..28, b"\"1\",edited,\r\n".iter().copied());
+        assert_eq!(&expected[..27], b"\"0\",value 0,\r\n\"1\",edited,\r\n");
+        assert!(saved == expected);
+    }
+}
+
+#[cfg(test)]
+mod round_trip {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn delimiters() -> impl Strategy<Value = CsvDelimiter> {
+        prop_oneof![
+            Just(CsvDelimiter::Comma),
+            Just(CsvDelimiter::Tab),
+            Just(CsvDelimiter::Semicolon),
+            Just(CsvDelimiter::Space),
+            Just(CsvDelimiter::Other(b'|')),
+            Just(CsvDelimiter::Other(0x1f))
+        ]
+    }
+
+    /// Values made of the characters which need quoting with any of the
+    /// delimiters, quotes and escapes, along with some outside of ASCII.
+    fn values() -> impl Strategy<Value = String> {
+        "[ab ,;|\t\u{1f}\"'\\\\\r\n#é語\u{FEFF}]{0,5}"
+    }
+
+    /// Grids of rows of any length, except that every row has at least one
+    /// value, as a row with none is written as an empty value.
+    fn grids() -> impl Strategy<Value = Vec<Vec<String>>> {
+        prop::collection::vec(prop::collection::vec(values(), 1..5), 0..6)
+    }
+
+    fn options() -> impl Strategy<Value = CsvOptions> {
+        (delimiters(), prop_oneof![Just(b'"'), Just(b'\'')], prop::option::of(Just(b'\\')))
+            .prop_map(|(delimiter, quote, escape)| CsvOptions {
+                delimiter: Some(delimiter),
+                quote: Some(quote),
+                escape,
+                ..CsvOptions::default()
+            })
+    }
+
+    fn rows(model: &CsvModel) -> Vec<Vec<String>> {
+        (0..model.data.len())
+            .map(|row| model.data.get(row).unwrap().to_vec())
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn saved_grid_loads_back_the_same(grid in grids(), options in options()) {
+            let path = super::tests::temp_path("round_trip");
+            let mut model = CsvModel::default_with_options(&options);
+            for row in &grid {
+                model.data.push(row.clone());
+            }
+            model.set_filename(Some(path.clone()));
+            model.save_data_to_file().unwrap();
+            let loaded = CsvModel::load_file(&path, &options);
+            fs::remove_file(&path).unwrap();
+            prop_assert_eq!(rows(&loaded.unwrap()), grid);
+        }
     }
 }
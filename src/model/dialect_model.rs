@@ -0,0 +1,601 @@
+use std::{
+    borrow::Cow,
+    io
+};
+
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+use crate::model::{
+    csv_model::CsvDelimiter,
+    sort_model::ColumnType
+};
+
+/// The number of bytes at the start of a file used to detect its dialect.
+pub const SAMPLE_SIZE: usize = 16 * 1024;
+
+/// The number of records in the sample used to detect the dialect.
+const SAMPLE_RECORDS: usize = 100;
+
+/// How a CSV file is written, as detected from a sample of it.
+#[derive(Clone, Copy, Debug)]
+pub struct Dialect {
+    pub delimiter: CsvDelimiter,
+    pub quote: u8,
+    pub has_header: bool
+}
+
+impl Dialect {
+    /// Detects the dialect of a file from the bytes at its start. When the
+    /// sample doesn't hold the whole file, its last line is ignored as it may
+    /// be cut short. The quote character is only detected if not given.
+    pub fn detect(sample: &[u8], is_whole_file: bool, quote: Option<u8>) -> Dialect {
+        let sample = match is_whole_file {
+            true => sample,
+            false => match sample.iter().rposition(|byte| *byte == b'\n') {
+                Some(index) => &sample[..=index],
+                None => sample
+            }
+        };
+
+        let mut best: Option<(CsvDelimiter, u8, f64, usize)> = None;
+        let delimiters = [
+            CsvDelimiter::Comma,
+            CsvDelimiter::Tab,
+            CsvDelimiter::Semicolon,
+            CsvDelimiter::Other(b'|'),
+            CsvDelimiter::Other(b'^'),
+            CsvDelimiter::Other(0x1f),
+            CsvDelimiter::Space
+        ];
+        let quotes = match quote {
+            Some(quote_char) => vec![quote_char],
+            None => vec![b'"', b'\'']
+        };
+        for delimiter in delimiters {
+            // spaces are common inside values, so are only used as a last resort
+            if delimiter == CsvDelimiter::Space && best.is_some() {
+                break;
+            }
+            for quote in quotes.iter().copied() {
+                let (consistency, field_count) = score(sample, delimiter, quote);
+                if field_count < 2 {
+                    continue;
+                }
+                let is_better = match best {
+                    Some((_, _, best_consistency, best_field_count)) => {
+                        consistency > best_consistency
+                            || (consistency == best_consistency
+                                && field_count > best_field_count)
+                    },
+                    None => true
+                };
+                if is_better {
+                    best = Some((delimiter, quote, consistency, field_count));
+                }
+            }
+        }
+
+        let (delimiter, quote) = match best {
+            Some((delimiter, quote, _, _)) => (delimiter, quote),
+            None => (CsvDelimiter::Comma, quotes[0])
+        };
+        let records = read_records(sample, delimiter, quote);
+
+        Dialect {
+            delimiter,
+            quote,
+            has_header: detect_header(&records)
+        }
+    }
+}
+
+fn read_records(sample: &[u8], delimiter: CsvDelimiter, quote: u8) -> Vec<Vec<String>> {
+    csv::ReaderBuilder::new()
+        .delimiter(delimiter.as_u8())
+        .quote(quote)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(sample)
+        .records()
+        .take(SAMPLE_RECORDS)
+        .map_while(|record| record.ok())
+        .map(|record| record.iter().map(String::from).collect())
+        .collect()
+}
+
+/// Returns the fraction of records with the most common number of fields,
+/// and that number of fields.
+fn score(sample: &[u8], delimiter: CsvDelimiter, quote: u8) -> (f64, usize) {
+    let records = read_records(sample, delimiter, quote);
+    if records.is_empty() {
+        return (0.0, 0);
+    }
+    let mut counts: Vec<(usize, usize)> = Vec::new();
+    for record in records.iter() {
+        match counts.iter_mut().find(|(field_count, _)| *field_count == record.len()) {
+            Some((_, occurrences)) => *occurrences += 1,
+            None => counts.push((record.len(), 1))
+        }
+    }
+    let (field_count, occurrences) = counts.iter()
+        .max_by_key(|(field_count, occurrences)| (*occurrences, *field_count))
+        .copied()
+        .unwrap_or((0, 0));
+
+    (occurrences as f64 / records.len() as f64, field_count)
+}
+
+/// Guesses whether the first record holds column names, by checking for
+/// columns of numbers or dates whose first value is text.
+fn detect_header(records: &[Vec<String>]) -> bool {
+    let (first, rest) = match records.split_first() {
+        Some((first, rest)) if !rest.is_empty() => (first, rest),
+        _ => return false
+    };
+    if first.iter().any(|cell| cell.trim().is_empty()) {
+        return false;
+    }
+
+    let mut votes = 0;
+    for (col, name) in first.iter().enumerate() {
+        let values = rest.iter().map(|row| match row.get(col) {
+            Some(cell) => cell.as_str(),
+            None => ""
+        });
+        let column_type = ColumnType::detect(values);
+        if column_type == ColumnType::Text {
+            continue;
+        }
+        if ColumnType::detect(std::iter::once(name.as_str())) == column_type {
+            votes -= 1;
+        } else {
+            votes += 1;
+        }
+    }
+
+    votes > 0
+}
+
+/// The characters ending each line of a file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r"
+        }
+    }
+}
+
+/// The details of how a file was written that aren't needed to read it, 
+/// recorded so that saving it changes as little as possible. The rows which
+/// haven't changed are copied from the records they were read from.
+pub struct FileFormat {
+    /// The encoding the file was read with. Records copied from the file
+    /// unchanged are only written in this encoding.
+    pub encoding: &'static Encoding,
+    pub line_ending: LineEnding,
+    pub has_bom: bool,
+    pub has_trailing_newline: bool,
+    /// For each column, whether most of its values were quoted, even where 
+    /// quotes weren't needed.
+    quoted_cols: Vec<bool>,
+    /// The delimiter, quote and escape characters the file was written with.
+    /// Records are only copied from it while these are unchanged.
+    delimiter: u8,
+    quote: u8,
+    escape: Option<u8>
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        FileFormat {
+            encoding: UTF_8,
+            line_ending: LineEnding::Lf,
+            has_bom: false,
+            has_trailing_newline: true,
+            quoted_cols: Vec::new(),
+            delimiter: b',',
+            quote: b'"',
+            escape: None
+        }
+    }
+}
+
+impl FileFormat {
+    /// Records the format of the file contents. 
+    pub fn from_contents(contents: &[u8],
+                         delimiter: u8,
+                         quote: u8,
+                         escape: Option<u8>) -> FileFormat {
+        let has_bom = contents.starts_with(UTF8_BOM);
+        let body = match has_bom {
+            true => &contents[UTF8_BOM.len()..],
+            false => contents
+        };
+        let mut format = FileFormat {
+            has_bom,
+            has_trailing_newline: body.is_empty() 
+                || body.ends_with(b"\n") 
+                || body.ends_with(b"\r"),
+            delimiter,
+            quote,
+            escape,
+            ..FileFormat::default()
+        };
+
+        let records = split_records(body, delimiter, quote, escape);
+        if let Some(record) = records.iter().find(|record| record.line_ending.is_some()) {
+            format.line_ending = record.line_ending.unwrap_or(LineEnding::Lf);
+        }
+
+        let mut quoted_counts: Vec<(usize, usize)> = Vec::new();
+        for record in records.iter() {
+            for (col, is_quoted) in record.quoted_fields.iter().enumerate() {
+                if quoted_counts.len() <= col {
+                    quoted_counts.resize(col + 1, (0, 0));
+                }
+                match is_quoted {
+                    true => quoted_counts[col].0 += 1,
+                    false => quoted_counts[col].1 += 1
+                }
+            }
+        }
+        format.quoted_cols = quoted_counts.iter()
+            .map(|(quoted, unquoted)| quoted > unquoted)
+            .collect();
+
+        format
+    }
+
+    /// Checks whether the file was written with the given characters.
+    pub fn is_written_with(&self, delimiter: u8, quote: u8, escape: Option<u8>) -> bool {
+        delimiter == self.delimiter && quote == self.quote && escape == self.escape
+    }
+
+    pub fn is_quoted_col(&self, col: usize) -> bool {
+        self.quoted_cols.get(col).copied().unwrap_or(false)
+    }
+}
+
+pub const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Detects the encoding of a file from the bytes at its start. Files with a
+/// BOM are read with the encoding it marks, files which are valid UTF-8 as
+/// UTF-8, and anything else as Windows-1252, which also reads Latin-1.
+pub fn detect_encoding(sample: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return encoding;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => UTF_8,
+        // the sample may end part way through a character
+        Err(err) if err.error_len().is_none() => UTF_8,
+        Err(_) => WINDOWS_1252
+    }
+}
+
+/// Decodes the contents as text. A BOM at the start is kept, as the UTF-8
+/// BOM, so that the file format records it. Bytes which aren't valid in
+/// the encoding are replaced.
+pub fn decode_contents<'a>(encoding: &'static Encoding, contents: &'a [u8]) -> Cow<'a, str> {
+    encoding.decode_without_bom_handling(contents).0
+}
+
+/// Encodes the text to be written in the encoding. Fails if the text has
+/// characters the encoding has no way of writing.
+pub fn encode_text<'a>(encoding: &'static Encoding, text: &'a str) -> io::Result<Cow<'a, [u8]>> {
+    // encoding_rs only decodes UTF-16, so it is encoded here
+    if encoding == UTF_16LE {
+        return Ok(Cow::Owned(text.encode_utf16().flat_map(u16::to_le_bytes).collect()));
+    }
+    if encoding == UTF_16BE {
+        return Ok(Cow::Owned(text.encode_utf16().flat_map(u16::to_be_bytes).collect()));
+    }
+    let (bytes, _, has_unmappable) = encoding.encode(text);
+    if has_unmappable {
+        return Err(io::Error::other(format!(
+                    "the data has characters which can't be written in {}, save it as UTF-8 with :set encoding=utf-8",
+                    encoding.name())));
+    }
+    Ok(bytes)
+}
+
+/// Converts positions in the text decoded from a file into positions in the
+/// file. Text decoded from encodings which write ASCII as it is was read
+/// from the file's own bytes, so only other encodings need converting, which
+/// is done from the last position converted, so positions are best
+/// converted in order.
+pub struct FilePositions<'a> {
+    encoding: &'static Encoding,
+    text: &'a [u8],
+    text_pos: usize,
+    file_pos: u64
+}
+
+impl<'a> FilePositions<'a> {
+    pub fn new(encoding: &'static Encoding, text: &'a [u8]) -> FilePositions<'a> {
+        FilePositions {
+            encoding,
+            text,
+            text_pos: 0,
+            file_pos: 0
+        }
+    }
+
+    pub fn get(&mut self, text_pos: usize) -> u64 {
+        if self.encoding.is_ascii_compatible() {
+            return text_pos as u64;
+        }
+        if text_pos < self.text_pos {
+            self.text_pos = 0;
+            self.file_pos = 0;
+        }
+        let text_pos = text_pos.min(self.text.len());
+        let skipped = &self.text[self.text_pos..text_pos];
+        self.file_pos += match std::str::from_utf8(skipped) {
+            Ok(skipped) => encode_text(self.encoding, skipped)
+                .map_or(skipped.len(), |bytes| bytes.len()),
+            Err(_) => skipped.len()
+        } as u64;
+        self.text_pos = text_pos;
+        self.file_pos
+    }
+}
+
+/// Returns the length of the contents up to the end of the last record  
+/// finished with a line ending. Anything after it is a record still being 
+/// written, or empty lines.
+pub fn find_records_end(contents: &[u8], 
+                        delimiter: u8, 
+                        quote: u8, 
+                        escape: Option<u8>) -> usize {
+    split_records(contents, delimiter, quote, escape).iter()
+        .rev()
+        .find_map(|record| record.line_ending
+                  .map(|line_ending| record.end + line_ending.as_str().len()))
+        .unwrap_or(0)
+}
+
+/// Checks whether the contents end inside a quoted value, as the last
+/// record of a file does when one of its quotes is never closed.
+pub fn ends_in_quotes(contents: &[u8],
+                      delimiter: u8,
+                      quote: u8,
+                      escape: Option<u8>) -> bool {
+    let mut is_field_start = true;
+    let mut in_quotes = false;
+    let mut pos = 0;
+
+    while pos < contents.len() {
+        let byte = contents[pos];
+        if in_quotes {
+            if Some(byte) == escape {
+                pos += 1;
+            } else if byte == quote {
+                // a doubled quote is a quote inside the value
+                match escape.is_none() && contents.get(pos + 1) == Some(&quote) {
+                    true => pos += 1,
+                    false => in_quotes = false
+                }
+            }
+        } else if is_field_start && byte == quote {
+            in_quotes = true;
+        }
+        is_field_start = !in_quotes 
+            && (byte == delimiter || byte == b'\n' || byte == b'\r');
+        pos += 1;
+    }
+
+    in_quotes
+}
+
+/// Where a record ends in the file, excluding its line ending.
+struct RawRecord {
+    end: usize,
+    line_ending: Option<LineEnding>,
+    quoted_fields: Vec<bool>
+}
+
+/// Splits the contents into records, skipping empty lines as the csv reader
+/// does, and noting which fields start with a quote.
+fn split_records(contents: &[u8], 
+                 delimiter: u8, 
+                 quote: u8, 
+                 escape: Option<u8>) -> Vec<RawRecord> {
+    let mut records = Vec::new();
+    let mut start = 0;
+    let mut quoted_fields = Vec::new();
+    let mut is_field_start = true;
+    let mut in_quotes = false;
+    let mut pos = 0;
+
+    while pos < contents.len() {
+        let byte = contents[pos];
+        if in_quotes {
+            if Some(byte) == escape {
+                pos += 1;
+            } else if byte == quote {
+                in_quotes = false;
+            }
+            pos += 1;
+            continue;
+        }
+        if is_field_start {
+            quoted_fields.push(byte == quote);
+            is_field_start = false;
+            if byte == quote {
+                in_quotes = true;
+                pos += 1;
+                continue;
+            }
+        }
+        if byte == quote && escape.is_none() && pos > start && contents[pos - 1] == quote {
+            // a doubled quote inside a quoted field
+            in_quotes = true;
+        } else if byte == delimiter {
+            is_field_start = true;
+        } else if byte == b'\n' || byte == b'\r' {
+            let line_ending = match (byte, contents.get(pos + 1)) {
+                (b'\r', Some(b'\n')) => LineEnding::CrLf,
+                (b'\r', _) => LineEnding::Cr,
+                _ => LineEnding::Lf
+            };
+            if pos > start {
+                records.push(RawRecord {
+                    end: pos,
+                    line_ending: Some(line_ending),
+                    quoted_fields: std::mem::take(&mut quoted_fields)
+                });
+            }
+            quoted_fields.clear();
+            pos += line_ending.as_str().len();
+            start = pos;
+            is_field_start = true;
+            continue;
+        }
+        pos += 1;
+    }
+    if pos > start {
+        records.push(RawRecord {
+            end: contents.len(),
+            line_ending: None,
+            quoted_fields
+        });
+    }
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(sample: &str) -> Dialect {
+        Dialect::detect(sample.as_bytes(), true, None)
+    }
+
+    #[test]
+    fn delimiter_is_detected() {
+        let delimiter = |sample| detect(sample).delimiter;
+        assert_eq!(delimiter("a;b;c\n1;2;3\n4;5;6\n"), CsvDelimiter::Semicolon);
+        assert_eq!(delimiter("a\tb\n1\t2\n3\t4\n"), CsvDelimiter::Tab);
+        assert_eq!(delimiter("a|b|c\n1|2|3\n"), CsvDelimiter::Other(b'|'));
+        assert_eq!(delimiter("a,b\n1,2\n"), CsvDelimiter::Comma);
+        assert_eq!(delimiter("a b\n1 2\n"), CsvDelimiter::Space);
+        // without any delimiter, the data is read as a single column of commas
+        assert_eq!(delimiter("a\nb\n"), CsvDelimiter::Comma);
+    }
+
+    #[test]
+    fn delimiters_inside_quotes_are_not_counted() {
+        let dialect = detect("\"a,b\";c\n\"d,e,f\";g\n\"h\";i\n");
+        assert_eq!(dialect.delimiter, CsvDelimiter::Semicolon);
+        assert_eq!(dialect.quote, b'"');
+        let dialect = detect("name,note\n1,\"x; y\"\n2,\"z; w; v\"\n");
+        assert_eq!(dialect.delimiter, CsvDelimiter::Comma);
+        assert!(dialect.has_header);
+    }
+
+    #[test]
+    fn cut_off_last_line_of_a_sample_is_ignored() {
+        let sample = b"a;b\n1;2\n3;4\n5,6,7,8,9";
+        assert_eq!(Dialect::detect(sample, false, None).delimiter, CsvDelimiter::Semicolon);
+        assert_eq!(Dialect::detect(sample, true, Some(b'"')).quote, b'"');
+    }
+
+    #[test]
+    fn header_is_detected_from_columns_of_numbers_and_dates() {
+        assert!(detect("name,age\nann,30\nbob,41\n").has_header);
+        assert!(detect("when;count\n2024-01-02;1\n2024-01-03;2\n").has_header);
+        assert!(!detect("ann,30\nbob,41\n").has_header);
+        assert!(!detect("name,town\nann,york\nbob,leeds\n").has_header);
+        // a header can't have an empty name
+        assert!(!detect("name,\nann,30\nbob,41\n").has_header);
+    }
+
+    #[test]
+    fn header_needs_rows_after_it() {
+        let records = |rows: &[&[&str]]| -> Vec<Vec<String>> {
+            rows.iter().map(|row| row.iter().map(|value| String::from(*value)).collect())
+                .collect()
+        };
+        assert!(!detect_header(&[]));
+        assert!(!detect_header(&records(&[&["name", "age"]])));
+        assert!(detect_header(&records(&[&["name", "age"], &["ann", "30"]])));
+        // short rows are treated as having empty values
+        assert!(detect_header(&records(&[&["name", "age"], &["ann"], &["bob", "41"]])));
+    }
+
+    #[test]
+    fn score_is_the_share_of_records_with_the_usual_field_count() {
+        assert_eq!(score(b"a,b\n1,2\n3\n", CsvDelimiter::Comma, b'"'), (2.0 / 3.0, 2));
+        assert_eq!(score(b"a,b\n1,2,3\n", CsvDelimiter::Comma, b'"'), (0.5, 3));
+        assert_eq!(score(b"", CsvDelimiter::Comma, b'"'), (0.0, 0));
+        assert_eq!(score(b"'a;b',c\n", CsvDelimiter::Semicolon, b'\''), (1.0, 1));
+    }
+
+    #[test]
+    fn records_are_split_at_line_endings_outside_quotes() {
+        let contents = b"a,\"b\nc\"\r\n\n\"d\"\"e\",f\rg";
+        let records = split_records(contents, b',', b'"', None);
+        let ends: Vec<(usize, Option<LineEnding>)> = records.iter()
+            .map(|record| (record.end, record.line_ending))
+            .collect();
+        assert_eq!(ends, [(7, Some(LineEnding::CrLf)), 
+                          (18, Some(LineEnding::Cr)), 
+                          (20, None)]);
+        assert_eq!(records[0].quoted_fields, [false, true]);
+        assert_eq!(records[1].quoted_fields, [true, false]);
+        assert_eq!(records[2].quoted_fields, [false]);
+    }
+
+    #[test]
+    fn escaped_quotes_stay_inside_the_value() {
+        let contents = b"\"a\\\"\n,b\"\nc";
+        let records = split_records(contents, b',', b'"', Some(b'\\'));
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].quoted_fields, [true]);
+        assert_eq!(split_records(contents, b',', b'"', None).len(), 3);
+    }
+
+    #[test]
+    fn encoding_is_detected_from_the_bom_or_the_bytes() {
+        assert_eq!(detect_encoding(b"\xff\xfea\x00"), UTF_16LE);
+        assert_eq!(detect_encoding(b"\xfe\xff\x00a"), UTF_16BE);
+        assert_eq!(detect_encoding(b"\xef\xbb\xbfa"), UTF_8);
+        assert_eq!(detect_encoding("a,é".as_bytes()), UTF_8);
+        // a sample may end part way through a character
+        assert_eq!(detect_encoding(&"a,é".as_bytes()[..3]), UTF_8);
+        assert_eq!(detect_encoding(b"caf\xe9,1"), WINDOWS_1252);
+    }
+
+    #[test]
+    fn utf_16_round_trips_with_its_bom() {
+        for (encoding, bom) in [(UTF_16LE, b"\xff\xfe"), (UTF_16BE, b"\xfe\xff")] {
+            let text = "\u{feff}name,é\r\n😀,\"a\nb\"\n";
+            let bytes = encode_text(encoding, text).unwrap();
+            assert!(bytes.starts_with(bom));
+            assert_eq!(bytes.len(), text.encode_utf16().count() * 2);
+            assert_eq!(detect_encoding(&bytes), encoding);
+            assert_eq!(decode_contents(encoding, &bytes), text);
+        }
+    }
+
+    #[test]
+    fn latin_1_round_trips_and_rejects_other_characters() {
+        let bytes = b"caf\xe9,\xa31\n";
+        let text = decode_contents(detect_encoding(bytes), bytes);
+        assert_eq!(text, "café,£1\n");
+        assert_eq!(encode_text(WINDOWS_1252, &text).unwrap(), &bytes[..]);
+        assert!(encode_text(WINDOWS_1252, "😀").is_err());
+        assert!(matches!(encode_text(UTF_8, "😀").unwrap(), Cow::Borrowed(_)));
+    }
+}
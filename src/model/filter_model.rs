@@ -0,0 +1,441 @@
+use std::cmp::Ordering;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::model::sort_model::compare_values;
+
+/// A parsed filter expression, such as `col3 > 100`, `status == "open"` or
+/// `name ~ /smith/i`. Comparisons can be combined with `and`, `or` and
+/// brackets.
+pub struct FilterExpression {
+    text: String,
+    root: FilterNode
+}
+
+enum FilterNode {
+    And(Box<FilterNode>, Box<FilterNode>),
+    Or(Box<FilterNode>, Box<FilterNode>),
+    Compare {
+        col: usize,
+        operator: Operator,
+        value: String
+    },
+    Matches {
+        col: usize,
+        regex: Regex,
+        negated: bool
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Matches,
+    NotMatches
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Regex(String, String),
+    Operator(Operator),
+    And,
+    Or,
+    OpenBracket,
+    CloseBracket
+}
+
+impl FilterExpression {
+    /// Parses the filter text. Columns are referred to as `colN`, or by name
+    /// when the data has a header row.
+    pub fn parse(text: &str, header: Option<&[String]>) -> Result<FilterExpression, String> {
+        let tokens = tokenize(text)?;
+        if tokens.is_empty() {
+            return Err(String::from("Filter is empty."));
+        }
+        let mut parser = Parser { tokens, pos: 0, header };
+        let root = parser.parse_or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(String::from("Unexpected text at end of filter."));
+        }
+
+        Ok(FilterExpression {
+            text: String::from(text),
+            root
+        })
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn matches(&self, row: &[String]) -> bool {
+        self.root.matches(row)
+    }
+}
+
+impl FilterNode {
+    fn matches(&self, row: &[String]) -> bool {
+        match self {
+            FilterNode::And(first, second) => first.matches(row) && second.matches(row),
+            FilterNode::Or(first, second) => first.matches(row) || second.matches(row),
+            FilterNode::Compare { col, operator, value } => {
+                let cell_value = match row.get(*col) {
+                    Some(cell) => cell.as_str(),
+                    None => ""
+                };
+                let ordering = compare_values(cell_value, value);
+                match operator {
+                    Operator::Equal => ordering == Ordering::Equal,
+                    Operator::NotEqual => ordering != Ordering::Equal,
+                    // empty cells have no order, so never pass a comparison
+                    _ if cell_value.trim().is_empty() => false,
+                    Operator::Greater => ordering == Ordering::Greater,
+                    Operator::GreaterEqual => ordering != Ordering::Less,
+                    Operator::Less => ordering == Ordering::Less,
+                    Operator::LessEqual => ordering != Ordering::Greater,
+                    Operator::Matches | Operator::NotMatches => false
+                }
+            },
+            FilterNode::Matches { col, regex, negated } => {
+                let cell_value = match row.get(*col) {
+                    Some(cell) => cell.as_str(),
+                    None => ""
+                };
+                regex.is_match(cell_value) != *negated
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    header: Option<&'a [String]>
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_or(&mut self) -> Result<FilterNode, String> {
+        let mut node = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            node = FilterNode::Or(Box::new(node), Box::new(self.parse_and()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_and(&mut self) -> Result<FilterNode, String> {
+        let mut node = self.parse_term()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            node = FilterNode::And(Box::new(node), Box::new(self.parse_term()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_term(&mut self) -> Result<FilterNode, String> {
+        if self.peek() == Some(&Token::OpenBracket) {
+            self.pos += 1;
+            let node = self.parse_or()?;
+            return match self.next() {
+                Some(Token::CloseBracket) => Ok(node),
+                _ => Err(String::from("Missing closing bracket."))
+            };
+        }
+
+        let col = match self.next() {
+            Some(Token::Word(name)) | Some(Token::Quoted(name)) => self.resolve_column(&name)?,
+            _ => return Err(String::from("Expected a column."))
+        };
+        let operator = match self.next() {
+            Some(Token::Operator(operator)) => operator,
+            _ => return Err(String::from("Expected an operator after the column."))
+        };
+        let value = match self.next() {
+            Some(token) => token,
+            None => return Err(String::from("Expected a value after the operator."))
+        };
+
+        match (operator, value) {
+            (Operator::Matches | Operator::NotMatches, value_token) => {
+                let (pattern, flags) = match value_token {
+                    Token::Regex(pattern, flags) => (pattern, flags),
+                    Token::Word(pattern) | Token::Quoted(pattern) => (pattern, String::new()),
+                    _ => return Err(String::from("Expected a pattern after ~."))
+                };
+                let regex = RegexBuilder::new(&pattern)
+                    .case_insensitive(flags.contains('i'))
+                    .build()
+                    .map_err(|_| format!("Invalid regex /{}/.", pattern))?;
+                Ok(FilterNode::Matches {
+                    col,
+                    regex,
+                    negated: operator == Operator::NotMatches
+                })
+            },
+            (_, Token::Word(value) | Token::Quoted(value)) => {
+                Ok(FilterNode::Compare { col, operator, value })
+            },
+            _ => Err(String::from("Expected a value after the operator."))
+        }
+    }
+
+    /// Finds the column referred to by name, either `colN` or the name of a
+    /// column in the header row.
+    fn resolve_column(&self, name: &str) -> Result<usize, String> {
+        if let Some(header_row) = self.header {
+            if let Some(col) = header_row.iter().position(|cell| cell == name) {
+                return Ok(col);
+            }
+            if let Some(col) = header_row.iter()
+                .position(|cell| cell.eq_ignore_ascii_case(name)) {
+                return Ok(col);
+            }
+        }
+        let lower_name = name.to_ascii_lowercase();
+        match lower_name.strip_prefix("col").map(|index| index.parse::<usize>()) {
+            Some(Ok(col)) => Ok(col),
+            _ => Err(format!("Unknown column {}.", name))
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        let next_c = chars.get(pos + 1).copied();
+        match c {
+            _ if c.is_whitespace() => {
+                pos += 1;
+            },
+            '(' => {
+                tokens.push(Token::OpenBracket);
+                pos += 1;
+            },
+            ')' => {
+                tokens.push(Token::CloseBracket);
+                pos += 1;
+            },
+            '"' | '\'' => {
+                let mut value = String::new();
+                pos += 1;
+                loop {
+                    match chars.get(pos) {
+                        Some('\\') if chars.get(pos + 1).is_some() => {
+                            value.push(chars[pos + 1]);
+                            pos += 2;
+                        },
+                        Some(quote) if *quote == c => {
+                            pos += 1;
+                            break;
+                        },
+                        Some(value_c) => {
+                            value.push(*value_c);
+                            pos += 1;
+                        },
+                        None => return Err(String::from("Missing closing quote."))
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            },
+            '/' => {
+                let mut pattern = String::new();
+                pos += 1;
+                loop {
+                    match chars.get(pos) {
+                        Some('\\') if chars.get(pos + 1) == Some(&'/') => {
+                            pattern.push('/');
+                            pos += 2;
+                        },
+                        Some('/') => {
+                            pos += 1;
+                            break;
+                        },
+                        Some(pattern_c) => {
+                            pattern.push(*pattern_c);
+                            pos += 1;
+                        },
+                        None => return Err(String::from("Missing closing / for regex."))
+                    }
+                }
+                let mut flags = String::new();
+                while let Some(flag) = chars.get(pos).filter(|flag| flag.is_ascii_alphabetic()) {
+                    flags.push(*flag);
+                    pos += 1;
+                }
+                tokens.push(Token::Regex(pattern, flags));
+            },
+            '=' | '!' | '<' | '>' | '~' => {
+                let (operator, length) = match (c, next_c) {
+                    ('=', Some('=')) => (Operator::Equal, 2),
+                    ('=', _) => (Operator::Equal, 1),
+                    ('!', Some('=')) => (Operator::NotEqual, 2),
+                    ('!', Some('~')) => (Operator::NotMatches, 2),
+                    ('<', Some('=')) => (Operator::LessEqual, 2),
+                    ('<', _) => (Operator::Less, 1),
+                    ('>', Some('=')) => (Operator::GreaterEqual, 2),
+                    ('>', _) => (Operator::Greater, 1),
+                    ('~', _) => (Operator::Matches, 1),
+                    _ => return Err(format!("Unknown operator {}.", c))
+                };
+                tokens.push(Token::Operator(operator));
+                pos += length;
+            },
+            '&' if next_c == Some('&') => {
+                tokens.push(Token::And);
+                pos += 2;
+            },
+            '|' if next_c == Some('|') => {
+                tokens.push(Token::Or);
+                pos += 2;
+            },
+            _ => {
+                let mut word = String::new();
+                while let Some(word_c) = chars.get(pos) {
+                    if word_c.is_whitespace() || "()\"'=!<>~&|".contains(*word_c) {
+                        break;
+                    }
+                    word.push(*word_c);
+                    pos += 1;
+                }
+                if word.is_empty() {
+                    return Err(format!("Unexpected character {}.", c));
+                }
+                tokens.push(match word.to_ascii_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    _ => Token::Word(word)
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| String::from(*value)).collect()
+    }
+
+    fn filter(text: &str) -> FilterExpression {
+        FilterExpression::parse(text, None).unwrap()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expression = filter("col0 = a or col0 = b and col1 = x");
+        assert!(expression.matches(&row(&["a", "y"])));
+        assert!(expression.matches(&row(&["b", "x"])));
+        assert!(!expression.matches(&row(&["b", "y"])));
+        let expression = filter("col0 = a && col1 = x || col1 = y");
+        assert!(expression.matches(&row(&["b", "y"])));
+        assert!(!expression.matches(&row(&["a", "z"])));
+    }
+
+    #[test]
+    fn brackets_group_comparisons() {
+        let expression = filter("(col0 = a or col0 = b) and col1 = x");
+        assert!(expression.matches(&row(&["b", "x"])));
+        assert!(!expression.matches(&row(&["a", "y"])));
+        let expression = filter("col1 = x and ((col0 = a))");
+        assert!(expression.matches(&row(&["a", "x"])));
+    }
+
+    #[test]
+    fn quoted_values_keep_spaces_and_operators() {
+        let expression = filter("col0 == \"a and b\" or col0 = 'x >= (y)'");
+        assert!(expression.matches(&row(&["a and b"])));
+        assert!(expression.matches(&row(&["x >= (y)"])));
+        assert!(!expression.matches(&row(&["a"])));
+        let expression = filter(r#"col0 = "say \"hi\"""#);
+        assert!(expression.matches(&row(&["say \"hi\""])));
+    }
+
+    #[test]
+    fn values_are_compared_as_numbers_or_text() {
+        assert!(filter("col0 > 9").matches(&row(&["10"])));
+        assert!(filter("col0 < b").matches(&row(&["A"])));
+        assert!(filter("col0 >= 2024-01-01").matches(&row(&["2024-02-01"])));
+        assert!(filter("col0 <= 5 and col0 != 4").matches(&row(&["5"])));
+    }
+
+    #[test]
+    fn regexes_match_with_flags() {
+        let expression = filter("col0 ~ /^sm.th$/i");
+        assert!(expression.matches(&row(&["SMITH"])));
+        assert!(!expression.matches(&row(&["smithers"])));
+        assert!(!filter("col0 ~ /^sm.th$/").matches(&row(&["SMITH"])));
+        assert!(filter("col0 !~ /a\\/b/").matches(&row(&["ab"])));
+        assert!(!filter("col0 !~ /a\\/b/").matches(&row(&["a/b"])));
+        assert!(filter("col0 ~ ab").matches(&row(&["cabin"])));
+    }
+
+    #[test]
+    fn columns_are_found_by_number_or_header_name() {
+        let header = row(&["Name", "name", "col0"]);
+        let parse = |text| FilterExpression::parse(text, Some(&header)).unwrap();
+        // an exact name is preferred over one differing in case
+        assert!(parse("name = b").matches(&row(&["a", "b", "c"])));
+        assert!(parse("NAME = a").matches(&row(&["a", "b", "c"])));
+        // header names are looked up before colN
+        assert!(parse("col0 = c").matches(&row(&["a", "b", "c"])));
+        assert!(parse("COL1 = b").matches(&row(&["a", "b", "c"])));
+        assert!(parse("\"Name\" = a").matches(&row(&["a", "b", "c"])));
+        assert!(filter("col1 = b").matches(&row(&["a", "b"])));
+    }
+
+    #[test]
+    fn empty_and_missing_cells_only_equal_empty_values() {
+        assert!(filter("col1 = ''").matches(&row(&["a", ""])));
+        assert!(filter("col1 = ''").matches(&row(&["a"])));
+        assert!(filter("col1 != a").matches(&row(&["a"])));
+        for operator in [">", ">=", "<", "<="] {
+            let expression = filter(&format!("col1 {} 0", operator));
+            assert!(!expression.matches(&row(&["a", ""])), "{} matched", operator);
+            assert!(!expression.matches(&row(&["a", " "])), "{} matched", operator);
+        }
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        let error = |text| FilterExpression::parse(text, None).err().unwrap();
+        assert_eq!(error(""), "Filter is empty.");
+        assert_eq!(error("(col0 = a"), "Missing closing bracket.");
+        assert_eq!(error("col0 = a)"), "Unexpected text at end of filter.");
+        assert_eq!(error("(col0 = a))"), "Unexpected text at end of filter.");
+        assert_eq!(error("name = a"), "Unknown column name.");
+        assert_eq!(error("colx = a"), "Unknown column colx.");
+        assert_eq!(error("col0 = 'a"), "Missing closing quote.");
+        assert_eq!(error("col0 ~ /a"), "Missing closing / for regex.");
+        assert_eq!(error("col0 ~ /(/"), "Invalid regex /(/.");
+        assert_eq!(error("col0 a"), "Expected an operator after the column.");
+        assert_eq!(error("col0 ="), "Expected a value after the operator.");
+        assert_eq!(error("col0 = a and"), "Expected a column.");
+        let header = row(&["id"]);
+        assert!(FilterExpression::parse("name = a", Some(&header)).is_err());
+    }
+}
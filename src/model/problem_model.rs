@@ -0,0 +1,104 @@
+use std::{
+    error::Error,
+    fmt,
+    io
+};
+
+/// What is wrong with a malformed record. Records with a different number
+/// of fields from the others aren't malformed, as they are read the same
+/// way, so are marked as ragged rows instead.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProblemKind {
+    /// The record has bytes which aren't valid in the encoding of the file,
+    /// which are read as the replacement character.
+    InvalidBytes,
+    /// A quoted value in the record is never closed, so the rest of the file
+    /// was read into it.
+    UnclosedQuote
+}
+
+impl fmt::Display for ProblemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProblemKind::InvalidBytes => write!(f, "bytes which aren't valid in the encoding"),
+            ProblemKind::UnclosedQuote => write!(f, "a quote which is never closed")
+        }
+    }
+}
+
+/// A problem found in a record when loading a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadProblem {
+    /// The data row read from the record, kept up to date as rows are
+    /// inserted, removed and sorted.
+    pub row: usize,
+    /// The line of the file the record starts on, counting from 1.
+    pub line: u64,
+    /// Where the record starts in the file, in bytes.
+    pub byte: u64,
+    pub kind: ProblemKind
+}
+
+impl fmt::Display for LoadProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, byte {}: {}", self.line, self.byte, self.kind)
+    }
+}
+
+/// The error loading a file fails with when it has malformed records and
+/// isn't loaded leniently.
+#[derive(Debug)]
+pub struct MalformedFile {
+    pub problems: Vec<LoadProblem>
+}
+
+impl MalformedFile {
+    pub fn into_error(self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, self)
+    }
+
+    /// Returns the problems of the file, if loading it failed because it was
+    /// malformed.
+    pub fn from_error(err: &io::Error) -> Option<&MalformedFile> {
+        err.get_ref().and_then(|inner| inner.downcast_ref::<MalformedFile>())
+    }
+}
+
+impl fmt::Display for MalformedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.problems.first() {
+            Some(first) => write!(f, "{} malformed records, the first at {}",
+                                  self.problems.len(),
+                                  first),
+            None => write!(f, "malformed records")
+        }
+    }
+}
+
+impl Error for MalformedFile {}
+
+/// Returns where a record starts in the file and the line it starts on,
+/// given the position the reader gives for it and the byte found there. The
+/// reader ends a record at the "\r" of a "\r\n" line ending, so its "\n" is
+/// counted as the start of the next record.
+pub fn record_start(byte: u64, line: u64, first_byte: Option<u8>) -> (u64, u64) {
+    match first_byte {
+        Some(b'\n') => (byte + 1, line + 1),
+        _ => (byte, line)
+    }
+}
+
+/// A file which wasn't loaded because it has malformed records. Its problems
+/// are listed in the problem panel in place of those of the loaded file.
+pub struct RejectedFile {
+    pub filename: String,
+    pub problems: Vec<LoadProblem>
+}
+
+/// The rows which normalising the data pads with empty values or cuts short,
+/// so that every row has width values, listed before they are changed.
+pub struct NormalisePreview {
+    pub width: usize,
+    /// The data rows which are changed.
+    pub rows: Vec<usize>
+}
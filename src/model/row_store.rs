@@ -0,0 +1,1086 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    ops::{Deref, DerefMut},
+    path::Path,
+    rc::Rc,
+    sync::mpsc::{self, TryRecvError},
+    thread
+};
+
+use encoding_rs::Encoding;
+
+use crate::model::{
+    width_model::ColumnWidths,
+    problem_model::{LoadProblem, ProblemKind, record_start},
+    dialect_model::encode_text
+};
+
+/// The number of rows read from the file that are kept in memory.
+const ROW_CACHE_SIZE: usize = 10_000;
+
+/// The number of records the worker thread indexes before sending them on.
+const INDEX_BATCH_SIZE: usize = 10_000;
+
+#[cfg(test)]
+thread_local! {
+    /// The number of rows looked at on the thread, so that tests can check
+    /// that an operation doesn't go through every row.
+    static ROWS_LOOKED_AT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+pub fn rows_looked_at() -> usize {
+    ROWS_LOOKED_AT.with(std::cell::Cell::get)
+}
+
+fn look_at_row() {
+    #[cfg(test)]
+    ROWS_LOOKED_AT.with(|count| count.set(count.get() + 1));
+}
+
+/// The rows of the data. Rows are either held in memory, or left in the
+/// records they were read from until they are needed. The records of a large
+/// file are left in the file, with an index of where each starts built by a
+/// worker thread reading it, while a smaller file is read into memory whole.
+/// The rows read from records are kept in a cache of the most recently used
+/// ones. Changing a row moves it into memory, so the rows in memory are the
+/// edits made to the file. Columns inserted and removed are applied to the
+/// rows in memory straight away, and to the rows in records as they are read.
+#[derive(Default)]
+pub struct RowStore {
+    rows: Vec<Row>,
+    source: Option<RecordSource>,
+    /// The columns inserted and removed, in the order they were, which are
+    /// applied to the values of each record as it is read.
+    col_ops: Vec<ColumnOp>,
+    /// Kept up to date as rows change, so that trimming the data after an
+    /// edit doesn't need to look at every row.
+    shapes: RefCell<RowShapes>
+}
+
+enum Row {
+    Record(RecordInfo),
+    Values(Box<MemoryRow>)
+}
+
+/// A row held in memory, with the record it was read from, if any, so that
+/// the row is still written as it was read while its values are unchanged.
+struct MemoryRow {
+    values: Vec<String>,
+    record: Option<RecordInfo>,
+    /// The number of column operations which had been made when the row was
+    /// moved into memory, which were applied to it as it was read.
+    col_ops_applied: usize
+}
+
+impl Row {
+    fn from_values(values: Vec<String>, 
+                   record: Option<RecordInfo>, 
+                   col_ops_applied: usize) -> Row {
+        Row::Values(Box::new(MemoryRow { values, record, col_ops_applied }))
+    }
+}
+
+/// A column inserted into or removed from every row long enough to have it.
+#[derive(Clone, Copy)]
+enum ColumnOp {
+    Insert(usize),
+    Remove(usize)
+}
+
+/// Returns the length of a row of len values once the column operations have
+/// been applied to it.
+fn apply_col_ops_to_len(mut len: usize, col_ops: &[ColumnOp]) -> usize {
+    for op in col_ops {
+        match op {
+            ColumnOp::Insert(col) if *col < len => len += 1,
+            ColumnOp::Remove(col) if *col < len => len -= 1,
+            _ => {}
+        }
+    }
+    len
+}
+
+/// The record a removed row was read from, so that the row can be put back
+/// as it was.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RowOrigin {
+    record: RecordInfo,
+    /// The number of column operations applied to the row when it was moved
+    /// into memory, or None if it was still in its record.
+    col_ops_applied: Option<usize>
+}
+
+/// Where a record is in the source, and the number of fields in it, so that
+/// the row doesn't need to be read to find its length.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordInfo {
+    pub start: u64,
+    /// The length of the record, including its line ending.
+    pub len: u64,
+    pub field_count: u32
+}
+
+/// A row of the data, borrowed from memory or shared with the cache.
+pub enum RowRef<'a> {
+    Values(&'a [String]),
+    Record(Rc<Vec<String>>)
+}
+
+impl Deref for RowRef<'_> {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        match self {
+            RowRef::Values(values) => values,
+            RowRef::Record(values) => values
+        }
+    }
+}
+
+/// A row being changed, whose shape is counted again once the change is
+/// done.
+pub struct RowMut<'a> {
+    values: &'a mut Vec<String>,
+    shapes: &'a RefCell<RowShapes>
+}
+
+impl<'a> RowMut<'a> {
+    fn new(values: &'a mut Vec<String>, shapes: &'a RefCell<RowShapes>) -> RowMut<'a> {
+        shapes.borrow_mut().remove(values_shape(values));
+        RowMut { values, shapes }
+    }
+}
+
+impl Deref for RowMut<'_> {
+    type Target = Vec<String>;
+
+    fn deref(&self) -> &Vec<String> {
+        self.values
+    }
+}
+
+impl DerefMut for RowMut<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<String> {
+        self.values
+    }
+}
+
+impl Drop for RowMut<'_> {
+    fn drop(&mut self) {
+        self.shapes.borrow_mut().add(values_shape(self.values));
+    }
+}
+
+/// The number of rows of each length, the number of rows in memory of each
+/// extent, which is the number of values up to and including the last one
+/// that isn't empty, and the number of rows still in their records of each
+/// length. Rows in records are never trimmed, so their extents aren't needed.
+#[derive(Default)]
+struct RowShapes {
+    lengths: BTreeMap<usize, usize>,
+    extents: BTreeMap<usize, usize>,
+    record_lengths: BTreeMap<usize, usize>
+}
+
+/// The length of a row, and its extent if it is held in memory.
+type RowShape = (usize, Option<usize>);
+
+impl RowShapes {
+    fn add(&mut self, (len, extent): RowShape) {
+        *self.lengths.entry(len).or_insert(0) += 1;
+        match extent {
+            Some(extent) => *self.extents.entry(extent).or_insert(0) += 1,
+            None => *self.record_lengths.entry(len).or_insert(0) += 1
+        }
+    }
+
+    fn remove(&mut self, (len, extent): RowShape) {
+        let (counts, key) = match extent {
+            Some(extent) => (&mut self.extents, extent),
+            None => (&mut self.record_lengths, len)
+        };
+        for (counts, key) in [(&mut self.lengths, len), (counts, key)] {
+            if let Some(count) = counts.get_mut(&key) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn max_len(&self) -> usize {
+        self.lengths.keys().next_back().copied().unwrap_or(0)
+    }
+
+    fn max_extent(&self) -> usize {
+        self.extents.keys().next_back().copied().unwrap_or(0)
+    }
+
+    fn max_record_len(&self) -> usize {
+        self.record_lengths.keys().next_back().copied().unwrap_or(0)
+    }
+}
+
+fn row_shape(row: &Row, col_ops: &[ColumnOp]) -> RowShape {
+    match row {
+        Row::Values(row) => values_shape(&row.values),
+        Row::Record(info) => (apply_col_ops_to_len(info.field_count as usize, col_ops), None)
+    }
+}
+
+fn values_shape(values: &[String]) -> RowShape {
+    let extent = match values.iter().rposition(|value| !value.is_empty()) {
+        Some(col) => col + 1,
+        None => 0
+    };
+    (values.len(), Some(extent))
+}
+
+/// Where the records are read from.
+struct RecordSource {
+    bytes: RecordBytes,
+    reader_builder: csv::ReaderBuilder,
+    /// The encoding the values of the records are decoded from, which must
+    /// keep the bytes of ASCII as they are, for the records to be split.
+    encoding: &'static Encoding,
+    cache: RefCell<RowCache>,
+    /// Receives the records from the worker thread indexing the file, None
+    /// once the whole file has been indexed or indexing was stopped.
+    indexer: Option<RecordIndexer>
+}
+
+enum RecordBytes {
+    /// A large file, which records are read from as they are needed.
+    File(RefCell<fs::File>),
+    /// The contents of a file which has been read whole.
+    Memory(Vec<u8>)
+}
+
+/// Receives the records indexed by the worker thread.
+struct RecordIndexer {
+    receiver: mpsc::Receiver<IndexedRecords>,
+    /// How far through the file the records received so far reach.
+    position: u64,
+    file_len: u64
+}
+
+/// A batch of records indexed by the worker thread.
+struct IndexedRecords {
+    records: Vec<RecordInfo>,
+    /// The widths of the values in the batch.
+    col_widths: ColumnWidths,
+    /// The problems found in the records of the batch.
+    problems: Vec<LoadProblem>,
+    position: u64,
+    /// Set on the last batch, with the error indexing stopped at, if any.
+    result: Option<io::Result<()>>
+}
+
+/// The rows most recently read from the file, keyed by where their records
+/// start.
+#[derive(Default)]
+struct RowCache {
+    rows: HashMap<u64, (Rc<Vec<String>>, u64)>,
+    last_use: u64
+}
+
+impl RowCache {
+    fn get(&mut self, start: u64) -> Option<Rc<Vec<String>>> {
+        self.last_use += 1;
+        let last_use = self.last_use;
+        self.rows.get_mut(&start).map(|(row, used)| {
+            *used = last_use;
+            Rc::clone(row)
+        })
+    }
+
+    /// Adds the row, first dropping the least recently used quarter of the
+    /// rows if the cache is full.
+    fn insert(&mut self, start: u64, row: Rc<Vec<String>>) {
+        if self.rows.len() >= ROW_CACHE_SIZE {
+            let mut uses: Vec<u64> = self.rows.values().map(|(_, used)| *used).collect();
+            let (_, oldest_kept, _) = uses.select_nth_unstable(ROW_CACHE_SIZE / 4);
+            let oldest_kept = *oldest_kept;
+            self.rows.retain(|_, (_, used)| *used >= oldest_kept);
+        }
+        self.last_use += 1;
+        self.rows.insert(start, (row, self.last_use));
+    }
+}
+
+impl RowStore {
+    /// Keeps the contents of a file which has been read whole, with the
+    /// records found in it, to read the rows from as they are needed. The
+    /// values of the records are decoded from the encoding.
+    pub fn from_records(contents: Vec<u8>,
+                        records: Vec<RecordInfo>,
+                        reader_builder: csv::ReaderBuilder,
+                        encoding: &'static Encoding) -> RowStore {
+        let mut shapes = RowShapes::default();
+        let rows: Vec<Row> = records.into_iter().map(Row::Record).collect();
+        for row in rows.iter() {
+            shapes.add(row_shape(row, &[]));
+        }
+        RowStore {
+            rows,
+            col_ops: Vec::new(),
+            source: Some(RecordSource {
+                bytes: RecordBytes::Memory(contents),
+                reader_builder,
+                encoding,
+                cache: RefCell::new(RowCache::default()),
+                indexer: None
+            }),
+            shapes: RefCell::new(shapes)
+        }
+    }
+
+    /// Opens the file to read its records lazily, starting at the offset.
+    /// The records are indexed by a worker thread, and are added to the rows
+    /// as they are received. ends_in_quotes checks the text of the last
+    /// record for a quote which is never closed.
+    pub fn open(path: &Path,
+                reader_builder: csv::ReaderBuilder,
+                offset: u64,
+                encoding: &'static Encoding,
+                ends_in_quotes: impl Fn(&[u8]) -> bool + Send + 'static) -> io::Result<RowStore> {
+        let file = fs::File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut index_file = fs::File::open(path)?;
+        index_file.seek(SeekFrom::Start(offset))?;
+        let reader = reader_builder.from_reader(index_file.take(file_len.saturating_sub(offset)));
+        let peek_file = fs::File::open(path)?;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || index_records(reader,
+                                            offset,
+                                            encoding,
+                                            peek_file,
+                                            ends_in_quotes,
+                                            sender));
+        Ok(RowStore {
+            rows: Vec::new(),
+            col_ops: Vec::new(),
+            source: Some(RecordSource {
+                bytes: RecordBytes::File(RefCell::new(file)),
+                reader_builder,
+                encoding,
+                cache: RefCell::new(RowCache::default()),
+                indexer: Some(RecordIndexer {
+                    receiver,
+                    position: offset,
+                    file_len
+                })
+            }),
+            shapes: RefCell::default()
+        })
+    }
+
+    /// Adds the records indexed since the last call, without waiting for
+    /// more, adding the widths of their values to col_widths and the
+    /// problems found in them to problems. Returns true once the whole file
+    /// has been indexed. Indexing stops at the first record that can't be
+    /// read.
+    pub fn receive_records(&mut self, 
+                           col_widths: &mut ColumnWidths,
+                           problems: &mut Vec<LoadProblem>) -> io::Result<bool> {
+        self.receive(false, col_widths, problems)
+    }
+
+    /// Waits for the rest of the file to be indexed.
+    pub fn receive_all_records(&mut self, 
+                               col_widths: &mut ColumnWidths,
+                               problems: &mut Vec<LoadProblem>) -> io::Result<()> {
+        self.receive(true, col_widths, problems).map(|_| ())
+    }
+
+    fn receive(&mut self, 
+               wait: bool, 
+               col_widths: &mut ColumnWidths,
+               problems: &mut Vec<LoadProblem>) -> io::Result<bool> {
+        let source = match &mut self.source {
+            Some(source) => source,
+            None => return Ok(true)
+        };
+        let indexer = match &mut source.indexer {
+            Some(indexer) => indexer,
+            None => return Ok(true)
+        };
+        loop {
+            let batch = match wait {
+                true => indexer.receiver.recv().map_err(|_| TryRecvError::Disconnected),
+                false => indexer.receiver.try_recv()
+            };
+            let batch = match batch {
+                Ok(batch) => batch,
+                Err(TryRecvError::Empty) => return Ok(false),
+                Err(TryRecvError::Disconnected) => {
+                    source.indexer = None;
+                    return Err(io::Error::other("the file stopped being read"));
+                }
+            };
+            let shapes = self.shapes.get_mut();
+            for info in batch.records {
+                let row = Row::Record(info);
+                shapes.add(row_shape(&row, &self.col_ops));
+                self.rows.push(row);
+            }
+            col_widths.merge(batch.col_widths);
+            problems.extend(batch.problems);
+            indexer.position = batch.position;
+            if let Some(result) = batch.result {
+                source.indexer = None;
+                return result.map(|_| true);
+            }
+        }
+    }
+
+    /// Stops indexing the file, keeping the records indexed so far.
+    pub fn cancel_indexing(&mut self) {
+        if let Some(source) = &mut self.source {
+            // the worker stops once it finds nothing is receiving its records
+            source.indexer = None;
+        }
+    }
+
+    pub fn is_indexing(&self) -> bool {
+        self.source.as_ref().is_some_and(|source| source.indexer.is_some())
+    }
+
+    /// Returns how far through the file indexing has got, and the length of
+    /// the file, while it is being indexed.
+    pub fn get_index_progress(&self) -> Option<(u64, u64)> {
+        let indexer = self.source.as_ref()?.indexer.as_ref()?;
+        Some((indexer.position, indexer.file_len))
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<RowRef<'_>> {
+        look_at_row();
+        match self.rows.get(index)? {
+            Row::Values(row) => Some(RowRef::Values(&row.values)),
+            Row::Record(info) => Some(RowRef::Record(self.read_record(info)))
+        }
+    }
+
+    pub fn first(&self) -> Option<RowRef<'_>> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<RowRef<'_>> {
+        self.get(self.rows.len().checked_sub(1)?)
+    }
+
+    /// Returns the number of values in the row, without reading it.
+    pub fn row_len(&self, index: usize) -> usize {
+        match self.rows.get(index) {
+            Some(Row::Values(row)) => row.values.len(),
+            Some(Row::Record(info)) => apply_col_ops_to_len(info.field_count as usize, 
+                                                            &self.col_ops),
+            None => 0
+        }
+    }
+
+    /// Returns the length of the longest row.
+    pub fn max_row_len(&self) -> usize {
+        self.shapes.borrow().max_len()
+    }
+
+    /// Returns the most common length of the rows which aren't empty,
+    /// preferring the longer of two lengths which are as common.
+    pub fn modal_row_len(&self) -> usize {
+        self.shapes.borrow().lengths.iter()
+            .filter(|(len, _)| **len > 0)
+            .max_by_key(|(len, count)| (**count, **len))
+            .map(|(len, _)| *len)
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of rows with len values.
+    pub fn count_rows_of_len(&self, len: usize) -> usize {
+        self.shapes.borrow().lengths.get(&len).copied().unwrap_or(0)
+    }
+
+    /// Returns the row to be changed, moving it into memory.
+    pub fn get_mut(&mut self, index: usize) -> Option<RowMut<'_>> {
+        look_at_row();
+        self.move_into_memory(index);
+        match self.rows.get_mut(index) {
+            Some(Row::Values(row)) => Some(RowMut::new(&mut row.values, &self.shapes)),
+            _ => None
+        }
+    }
+
+    fn move_into_memory(&mut self, index: usize) {
+        if let Some(Row::Record(info)) = self.rows.get(index) {
+            let info = *info;
+            let row = Row::from_values(self.read_record(&info).as_ref().clone(), 
+                                       Some(info), 
+                                       self.col_ops.len());
+            let shapes = self.shapes.get_mut();
+            shapes.remove(row_shape(&self.rows[index], &self.col_ops));
+            shapes.add(row_shape(&row, &self.col_ops));
+            self.rows[index] = row;
+        }
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = RowRef<'_>> {
+        (0..self.rows.len()).filter_map(|index| self.get(index))
+    }
+
+    pub fn push(&mut self, values: Vec<String>) {
+        self.shapes.get_mut().add(values_shape(&values));
+        self.rows.push(Row::from_values(values, None, self.col_ops.len()));
+    }
+
+    /// Inserts a row, which was removed from the origin, if given, so that it
+    /// is put back as it was, and is written as it was read while its values
+    /// are unchanged.
+    pub fn insert(&mut self, index: usize, values: Vec<String>, origin: Option<RowOrigin>) {
+        let row = match origin {
+            Some(RowOrigin { record, col_ops_applied: None }) => Row::Record(record),
+            Some(RowOrigin { record, col_ops_applied: Some(col_ops_applied) }) => {
+                Row::from_values(values, Some(record), col_ops_applied)
+            },
+            None => Row::from_values(values, None, self.col_ops.len())
+        };
+        self.shapes.get_mut().add(row_shape(&row, &self.col_ops));
+        self.rows.insert(index, row);
+    }
+
+    /// Removes the row, returning its values and where it was read from, if
+    /// it was.
+    pub fn remove(&mut self, index: usize) -> (Vec<String>, Option<RowOrigin>) {
+        let row = self.rows.remove(index);
+        self.shapes.get_mut().remove(row_shape(&row, &self.col_ops));
+        match row {
+            Row::Values(row) => {
+                let origin = row.record.map(|record| RowOrigin {
+                    record,
+                    col_ops_applied: Some(row.col_ops_applied)
+                });
+                (row.values, origin)
+            },
+            Row::Record(record) => {
+                let origin = RowOrigin { record, col_ops_applied: None };
+                (self.read_record(&record).as_ref().clone(), Some(origin))
+            }
+        }
+    }
+
+    /// Removes the last row without reading it.
+    fn drop_last(&mut self) {
+        if let Some(row) = self.rows.pop() {
+            self.shapes.get_mut().remove(row_shape(&row, &self.col_ops));
+        }
+    }
+
+    /// Inserts an empty value at col into every row long enough to have one
+    /// there. The rows in records are left in them, and have the value
+    /// inserted as they are read.
+    pub fn insert_col(&mut self, col: usize) {
+        for row in self.memory_rows_mut() {
+            if col < row.values.len() {
+                row.values.insert(col, String::new());
+            }
+        }
+        self.set_col_ops(|col_ops| col_ops.push(ColumnOp::Insert(col)));
+    }
+
+    /// Reverts the last column operation, which inserted col.
+    pub fn revert_insert_col(&mut self, col: usize) {
+        let col_ops_made = self.col_ops.len().saturating_sub(1);
+        for row in self.memory_rows_mut() {
+            if col < row.values.len() {
+                row.values.remove(col);
+            }
+            row.col_ops_applied = row.col_ops_applied.min(col_ops_made);
+        }
+        self.set_col_ops(|col_ops| { col_ops.pop(); });
+    }
+
+    /// Removes the value at col from every row long enough to have one there.
+    /// The rows in records are left in them, and have the value removed as
+    /// they are read. Returns the values removed from the rows in memory,
+    /// with their rows.
+    pub fn remove_col(&mut self, col: usize) -> Vec<(usize, String)> {
+        let mut removed = Vec::new();
+        for (index, row) in self.rows.iter_mut().enumerate() {
+            if let Row::Values(row) = row {
+                if col < row.values.len() {
+                    removed.push((index, row.values.remove(col)));
+                }
+            }
+        }
+        self.set_col_ops(|col_ops| col_ops.push(ColumnOp::Remove(col)));
+        removed
+    }
+
+    /// Reverts the last column operation, which removed col, putting back the
+    /// values removed from the rows in memory. The values of rows which were
+    /// in records when the column was removed, and have since been moved into
+    /// memory, are read from their records again.
+    pub fn revert_remove_col(&mut self, col: usize, removed: &[(usize, String)]) {
+        self.set_col_ops(|col_ops| { col_ops.pop(); });
+        let col_ops_made = self.col_ops.len();
+        let mut removed = removed.iter().peekable();
+        for index in 0..self.rows.len() {
+            let value = match removed.next_if(|(row, _)| *row == index) {
+                Some((_, value)) => Some(value.clone()),
+                None => match &self.rows[index] {
+                    Row::Values(row) if row.col_ops_applied > col_ops_made => {
+                        row.record.and_then(|info| self.read_record(&info).get(col).cloned())
+                    },
+                    _ => None
+                }
+            };
+            if let Row::Values(row) = &mut self.rows[index] {
+                if let Some(value) = value {
+                    row.values.insert(col, value);
+                }
+                row.col_ops_applied = row.col_ops_applied.min(col_ops_made);
+            }
+        }
+        self.recount_shapes();
+    }
+
+    fn memory_rows_mut(&mut self) -> impl Iterator<Item = &mut MemoryRow> {
+        self.rows.iter_mut().filter_map(|row| match row {
+            Row::Values(row) => Some(row.as_mut()),
+            Row::Record(_) => None
+        })
+    }
+
+    /// Changes the column operations, dropping the rows read with the old
+    /// ones from the cache, and counting the shapes of the rows again.
+    fn set_col_ops(&mut self, change: impl FnOnce(&mut Vec<ColumnOp>)) {
+        change(&mut self.col_ops);
+        if let Some(source) = &self.source {
+            *source.cache.borrow_mut() = RowCache::default();
+        }
+        self.recount_shapes();
+    }
+
+    fn recount_shapes(&mut self) {
+        let mut shapes = RowShapes::default();
+        for row in self.rows.iter() {
+            shapes.add(row_shape(row, &self.col_ops));
+        }
+        self.shapes = RefCell::new(shapes);
+    }
+
+    pub fn pop(&mut self) -> Option<Vec<String>> {
+        let last = self.rows.len().checked_sub(1)?;
+        Some(self.remove(last).0)
+    }
+
+    /// Adds or removes rows at the end so that there are row_count rows,
+    /// adding empty rows.
+    pub fn resize(&mut self, row_count: usize) {
+        while self.rows.len() < row_count {
+            self.push(Vec::new());
+        }
+        while self.rows.len() > row_count {
+            self.drop_last();
+        }
+    }
+
+    /// Reorders the rows from first_row onwards, so that the row at position
+    /// i is the one which was at order[i].
+    pub fn apply_order(&mut self, first_row: usize, order: &[usize]) {
+        let mut rows: Vec<Option<Row>> = self.rows.drain(first_row..).map(Some).collect();
+        for old_pos in order {
+            let row = rows[*old_pos].take()
+                .unwrap_or_else(|| Row::from_values(Vec::new(), None, 0));
+            self.rows.push(row);
+        }
+    }
+
+    /// Reverts apply_order, moving each row back to where it was.
+    pub fn revert_order(&mut self, first_row: usize, order: &[usize]) {
+        let mut sorted_rows: Vec<Option<Row>> = self.rows.drain(first_row..).map(Some).collect();
+        let mut rows: Vec<Row> = (0..order.len())
+            .map(|_| Row::from_values(Vec::new(), None, 0))
+            .collect();
+        for (new_pos, old_pos) in order.iter().enumerate() {
+            if let Some(row) = sorted_rows[new_pos].take() {
+                rows[*old_pos] = row;
+            }
+        }
+        self.rows.append(&mut rows);
+    }
+
+    /// Removes the empty rows in memory after the last row with a value, and
+    /// the empty values after the last column with a value from the rows in
+    /// memory, keeping at least one row and column. Rows still in their
+    /// records are left as they were read, along with the columns they have.
+    /// Returns the position and previous length of each row that was
+    /// shortened or removed.
+    ///
+    /// Only the removed rows are looked at, unless the last column with a
+    /// value has moved left, so trimming after an edit is cheap.
+    pub fn trim(&mut self) -> Vec<(usize, usize)> {
+        let mut changed_rows = Vec::new();
+        // rows can't be removed from the end while more are being indexed
+        while let Some(Row::Values(row)) = self.rows.last().filter(|_| !self.is_indexing()) {
+            if self.rows.len() == 1 || values_shape(&row.values).1 > Some(0) {
+                break;
+            }
+            changed_rows.push((self.rows.len() - 1, row.values.len()));
+            self.drop_last();
+        }
+
+        let shapes = self.shapes.get_mut();
+        let col_count = shapes.max_extent().max(shapes.max_record_len()).max(1);
+        // only rows in memory can be longer than col_count
+        if shapes.max_len() <= col_count {
+            return changed_rows;
+        }
+        for (index, row) in self.rows.iter_mut().enumerate() {
+            look_at_row();
+            let row = match row {
+                Row::Values(row) if row.values.len() > col_count => row,
+                _ => continue
+            };
+            changed_rows.push((index, row.values.len()));
+            shapes.remove(values_shape(&row.values));
+            row.values.truncate(col_count);
+            shapes.add(values_shape(&row.values));
+        }
+        changed_rows
+    }
+
+    /// Returns the text of the row's record, without its line ending, in the
+    /// encoding, if the row has the values it was read with.
+    pub fn get_raw_record(&self, index: usize, encoding: &'static Encoding) -> Option<Vec<u8>> {
+        let info = match self.rows.get(index)? {
+            Row::Record(info) if self.col_ops.is_empty() => info,
+            Row::Record(info) if self.read_record(info)[..] == self.read_values(info)[..] => info,
+            Row::Values(row) => match &row.record {
+                Some(info) if self.read_values(info) == row.values => info,
+                _ => return None
+            },
+            Row::Record(_) => return None
+        };
+        let raw = self.read_raw_record(info).ok()?;
+        let source_encoding = self.source.as_ref()?.encoding;
+        if source_encoding == encoding {
+            return Some(raw);
+        }
+        let text = source_encoding.decode_without_bom_handling(&raw).0;
+        encode_text(encoding, &text).ok().map(|bytes| bytes.into_owned())
+    }
+
+    /// Returns where the row's record starts in the file, if the row is still
+    /// in the file.
+    pub fn get_record_start(&self, index: usize) -> Option<u64> {
+        match self.rows.get(index)? {
+            Row::Record(info) => Some(info.start),
+            Row::Values(_) => None
+        }
+    }
+
+    /// Returns the row read from the record, with the column operations
+    /// applied to it.
+    fn read_record(&self, info: &RecordInfo) -> Rc<Vec<String>> {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return Rc::new(Vec::new())
+        };
+        if let Some(row) = source.cache.borrow_mut().get(info.start) {
+            return row;
+        }
+        let mut values = self.read_values(info);
+        for op in &self.col_ops {
+            match *op {
+                ColumnOp::Insert(col) if col < values.len() => values.insert(col, String::new()),
+                ColumnOp::Remove(col) if col < values.len() => { values.remove(col); },
+                _ => {}
+            }
+        }
+        let row = Rc::new(values);
+        source.cache.borrow_mut().insert(info.start, Rc::clone(&row));
+        row
+    }
+
+    /// Returns the values of the record as they were read.
+    fn read_values(&self, info: &RecordInfo) -> Vec<String> {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return Vec::new()
+        };
+        // a record which can no longer be read, because the file has been
+        // changed, is shown as an empty row
+        match self.read_raw_record(info) {
+            Ok(raw) => source.reader_builder.from_reader(raw.as_slice())
+                .byte_records()
+                .next()
+                .and_then(|record| record.ok())
+                .map(|record| record.iter()
+                     .map(|field| source.encoding.decode_without_bom_handling(field).0.into_owned())
+                     .collect())
+                .unwrap_or_default(),
+            Err(_) => Vec::new()
+        }
+    }
+
+    fn read_raw_record(&self, info: &RecordInfo) -> io::Result<Vec<u8>> {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return Err(io::Error::other("rows aren't read from a file"))
+        };
+        let mut raw = match &source.bytes {
+            RecordBytes::File(file) => {
+                let mut file = file.borrow_mut();
+                file.seek(SeekFrom::Start(info.start))?;
+                let mut raw = vec![0; info.len as usize];
+                file.read_exact(&mut raw)?;
+                raw
+            },
+            RecordBytes::Memory(contents) => {
+                let start = info.start as usize;
+                match contents.get(start..start + info.len as usize) {
+                    Some(raw) => raw.to_vec(),
+                    None => return Err(io::ErrorKind::UnexpectedEof.into())
+                }
+            }
+        };
+        // records can start with the end of the previous line ending, and
+        // fields starting or ending with a line break are quoted, so only
+        // line endings are removed
+        let is_line_ending = |byte: &u8| *byte == b'\n' || *byte == b'\r';
+        while raw.last().is_some_and(is_line_ending) {
+            raw.pop();
+        }
+        let start = raw.iter().take_while(|byte| is_line_ending(byte)).count();
+        raw.drain(..start);
+        Ok(raw)
+    }
+}
+
+/// Indexes the records read by the reader, sending them on in batches until
+/// the end of the file, the first record that can't be read, or the
+/// receiver being dropped. The reader started offset bytes into the file,
+/// and the values are decoded from the encoding to count their widths. The
+/// problems found in the records are sent with them, with where they start
+/// found by reading peek_file.
+fn index_records(mut reader: csv::Reader<io::Take<fs::File>>,
+                 offset: u64,
+                 encoding: &'static Encoding,
+                 mut peek_file: fs::File,
+                 ends_in_quotes: impl Fn(&[u8]) -> bool,
+                 sender: mpsc::Sender<IndexedRecords>) {
+    let mut record = csv::ByteRecord::new();
+    let mut batch = Vec::new();
+    let mut col_widths = ColumnWidths::default();
+    let mut problems = Vec::new();
+    let mut row = 0;
+    // the row, line and start of the last record read
+    let mut last_record = None;
+    loop {
+        let result = match reader.read_byte_record(&mut record) {
+            Ok(true) => {
+                let (start, line) = match record.position() {
+                    Some(position) => (position.byte(), position.line()),
+                    None => (reader.position().byte(), reader.position().line())
+                };
+                let end = reader.position().byte();
+                let mut has_invalid_bytes = false;
+                for (col, field) in record.iter().enumerate() {
+                    let (value, had_errors) = encoding.decode_without_bom_handling(field);
+                    col_widths.add_value(col, &value);
+                    has_invalid_bytes |= had_errors;
+                }
+                if has_invalid_bytes {
+                    let (byte, line) = record_start(offset + start,
+                                                    line,
+                                                    peek_byte(&mut peek_file, offset + start));
+                    problems.push(LoadProblem {
+                        row,
+                        line,
+                        byte,
+                        kind: ProblemKind::InvalidBytes
+                    });
+                }
+                last_record = Some((row, line, offset + start));
+                row += 1;
+                batch.push(RecordInfo {
+                    start: offset + start,
+                    len: end - start,
+                    field_count: record.len() as u32
+                });
+                if batch.len() < INDEX_BATCH_SIZE {
+                    continue;
+                }
+                None
+            },
+            Ok(false) => {
+                if let Some((row, line, start)) = last_record {
+                    if last_record_ends_in_quotes(&mut peek_file, start, &ends_in_quotes) {
+                        let (byte, line) = record_start(start,
+                                                        line,
+                                                        peek_byte(&mut peek_file, start));
+                        problems.push(LoadProblem {
+                            row,
+                            line,
+                            byte,
+                            kind: ProblemKind::UnclosedQuote
+                        });
+                    }
+                }
+                Some(Ok(()))
+            },
+            Err(err) => Some(Err(err.into()))
+        };
+        let is_last = result.is_some();
+        let sent = sender.send(IndexedRecords {
+            records: std::mem::take(&mut batch),
+            col_widths: std::mem::take(&mut col_widths),
+            problems: std::mem::take(&mut problems),
+            position: offset + reader.position().byte(),
+            result
+        });
+        if is_last || sent.is_err() {
+            return;
+        }
+    }
+}
+
+/// Returns the byte at position in the file, if it can be read.
+fn peek_byte(file: &mut fs::File, position: u64) -> Option<u8> {
+    let mut byte = [0];
+    file.seek(SeekFrom::Start(position)).ok()?;
+    file.read_exact(&mut byte).ok()?;
+    Some(byte[0])
+}
+
+/// Checks whether the last record of the file, which starts at start, runs
+/// to the end of the file inside a quoted value.
+fn last_record_ends_in_quotes(file: &mut fs::File,
+                              start: u64,
+                              ends_in_quotes: impl Fn(&[u8]) -> bool) -> bool {
+    let mut raw = Vec::new();
+    file.seek(SeekFrom::Start(start)).is_ok()
+        && file.read_to_end(&mut raw).is_ok()
+        && ends_in_quotes(&raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::UTF_8;
+
+    use super::*;
+
+    /// Keeps the lines, each a record, in memory as a file read whole.
+    fn store_of_lines(lines: &[&str]) -> RowStore {
+        let mut contents = Vec::new();
+        let mut records = Vec::new();
+        for line in lines {
+            records.push(RecordInfo {
+                start: contents.len() as u64,
+                len: line.len() as u64 + 1,
+                field_count: line.split(',').count() as u32
+            });
+            contents.extend_from_slice(line.as_bytes());
+            contents.push(b'\n');
+        }
+        let mut reader_builder = csv::ReaderBuilder::new();
+        reader_builder.has_headers(false).flexible(true);
+        RowStore::from_records(contents, records, reader_builder, UTF_8)
+    }
+
+    fn rows(store: &RowStore) -> Vec<Vec<String>> {
+        store.iter().map(|row| row.to_vec()).collect()
+    }
+
+    fn is_in_record(store: &RowStore, index: usize) -> bool {
+        matches!(store.rows[index], Row::Record(_))
+    }
+
+    #[test]
+    fn column_operations_leave_rows_in_their_records() {
+        let mut store = store_of_lines(&["a,b,c", "d", "e,f"]);
+        store.get_mut(2).unwrap()[1] = String::from("g");
+        store.insert_col(1);
+        store.remove_col(0);
+        assert_eq!(rows(&store), [vec!["", "b", "c"], vec![], vec!["", "g"]]);
+        assert!(is_in_record(&store, 0) && is_in_record(&store, 1));
+        assert_eq!((store.row_len(0), store.row_len(1)), (3, 0));
+        assert_eq!((store.max_row_len(), store.count_rows_of_len(2)), (3, 1));
+    }
+
+    #[test]
+    fn reverting_a_removed_column_reads_it_from_the_records() {
+        let mut store = store_of_lines(&["a,b,c", "d,e", "f"]);
+        let removed = store.remove_col(1);
+        assert!(removed.is_empty());
+        // moved into memory after the column was removed
+        store.get_mut(1).unwrap();
+        store.revert_remove_col(1, &removed);
+        assert_eq!(rows(&store), [vec!["a", "b", "c"], vec!["d", "e"], vec!["f"]]);
+        assert_eq!(store.get_raw_record(1, UTF_8), Some(b"d,e".to_vec()));
+        assert_eq!(store.max_row_len(), 3);
+    }
+
+    #[test]
+    fn reverting_a_removed_column_puts_back_values_in_memory() {
+        let mut store = store_of_lines(&["a,b", "c,d"]);
+        store.get_mut(0).unwrap()[1] = String::from("x");
+        let removed = store.remove_col(1);
+        assert_eq!(removed, [(0, String::from("x"))]);
+        store.revert_remove_col(1, &removed);
+        assert_eq!(rows(&store), [vec!["a", "x"], vec!["c", "d"]]);
+        store.insert_col(0);
+        store.revert_insert_col(0);
+        assert_eq!(rows(&store), [vec!["a", "x"], vec!["c", "d"]]);
+    }
+
+    #[test]
+    fn raw_records_are_only_copied_while_their_columns_are_unchanged() {
+        let mut store = store_of_lines(&["a,b", "c"]);
+        store.insert_col(1);
+        assert_eq!(store.get_raw_record(0, UTF_8), None);
+        assert_eq!(store.get_raw_record(1, UTF_8), Some(b"c".to_vec()));
+        store.revert_insert_col(1);
+        assert_eq!(store.get_raw_record(0, UTF_8), Some(b"a,b".to_vec()));
+    }
+
+    #[test]
+    fn removed_rows_are_put_back_in_their_records() {
+        let mut store = store_of_lines(&["a,b", "c,d"]);
+        let (values, origin) = store.remove(1);
+        assert_eq!(values, ["c", "d"]);
+        store.insert(1, values, origin);
+        assert!(is_in_record(&store, 1));
+        assert_eq!(store.get_raw_record(1, UTF_8), Some(b"c,d".to_vec()));
+    }
+
+    #[test]
+    fn trimming_only_goes_through_the_rows_when_the_last_column_empties() {
+        let mut store = RowStore::default();
+        for row in [["a", "b", "c"], ["d", "e", ""], ["f", "", ""]] {
+            store.push(row.map(String::from).to_vec());
+        }
+        store.get_mut(1).unwrap()[0] = String::from("x");
+        let looked_at = rows_looked_at();
+        assert!(store.trim().is_empty());
+        assert_eq!(rows_looked_at(), looked_at);
+
+        store.get_mut(0).unwrap()[2] = String::new();
+        let looked_at = rows_looked_at();
+        assert_eq!(store.trim(), [(0, 3), (1, 3), (2, 3)]);
+        assert_eq!(rows_looked_at(), looked_at + 3);
+        assert_eq!(rows(&store), [vec!["a", "b"], vec!["x", "e"], vec!["f", ""]]);
+    }
+}
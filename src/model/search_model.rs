@@ -0,0 +1,119 @@
+use std::borrow::Cow;
+
+use regex::{Regex, RegexBuilder, Error as RegexError, NoExpand};
+
+use crate::model::utils_model::Position;
+
+/// Options controlling how a search query is matched against cell values.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchOptions {
+    pub case_insensitive: bool,
+    pub use_regex: bool
+}
+
+/// The cells a replace is applied to. Positions are grid positions, so do 
+/// not include the header row.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplaceScope {
+    Table,
+    Column(usize),
+    /// The rectangle between two opposite corners, inclusive.
+    Range(Position, Position)
+}
+
+impl ReplaceScope {
+    pub fn contains(&self, pos: &Position) -> bool {
+        match self {
+            ReplaceScope::Table => true,
+            ReplaceScope::Column(col) => pos.col == *col,
+            ReplaceScope::Range(first, second) => {
+                pos.row >= first.row.min(second.row) 
+                    && pos.row <= first.row.max(second.row)
+                    && pos.col >= first.col.min(second.col)
+                    && pos.col <= first.col.max(second.col)
+            }
+        }
+    }
+}
+
+/// A compiled search pattern, matching either the literal query or a regular
+/// expression, optionally ignoring case.
+pub struct SearchPattern {
+    query: String,
+    regex: Regex,
+    use_regex: bool
+}
+
+impl SearchPattern {
+    pub fn new(query: &str, 
+               options: &SearchOptions) -> Result<SearchPattern, RegexError> {
+        let expression = match options.use_regex {
+            true => String::from(query),
+            false => regex::escape(query)
+        };
+        let regex = RegexBuilder::new(&expression)
+            .case_insensitive(options.case_insensitive)
+            .build()?;
+
+        Ok(SearchPattern {
+            query: String::from(query),
+            regex,
+            use_regex: options.use_regex
+        })
+    }
+
+    pub fn get_query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn is_match(&self, cell_value: &str) -> bool {
+        !cell_value.is_empty() && self.regex.is_match(cell_value)
+    }
+
+    /// Replaces every match in the cell value. For regex patterns, `$1` or 
+    /// `${name}` in the replacement refer to capture groups, otherwise the
+    /// replacement is used as is.
+    pub fn replace_all<'a>(&self, cell_value: &'a str, replacement: &str) -> Cow<'a, str> {
+        if !self.is_match(cell_value) {
+            return Cow::Borrowed(cell_value);
+        }
+        match self.use_regex {
+            true => self.regex.replace_all(cell_value, replacement),
+            false => self.regex.replace_all(cell_value, NoExpand(replacement))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regex_replacements_expand_capture_groups() {
+        let options = SearchOptions { use_regex: true, ..Default::default() };
+        let pattern = SearchPattern::new(r"(\w+)@(?P<host>\w+)", &options).unwrap();
+        assert_eq!(pattern.replace_all("ann@example", "$1 at ${host}"), "ann at example");
+        assert_eq!(pattern.replace_all("a@b, c@d", "$2"), "b, d");
+    }
+
+    #[test]
+    fn literal_replacements_are_not_expanded() {
+        let pattern = SearchPattern::new("(a)", &SearchOptions::default()).unwrap();
+        assert_eq!(pattern.replace_all("x(a)y", "$1"), "x$1y");
+        assert_eq!(pattern.replace_all("xay", "$1"), "xay");
+    }
+
+    #[test]
+    fn scopes_contain_their_cells() {
+        let pos = |row, col| Position { row, col };
+        assert!(ReplaceScope::Table.contains(&pos(100, 100)));
+        assert!(ReplaceScope::Column(2).contains(&pos(7, 2)));
+        assert!(!ReplaceScope::Column(2).contains(&pos(7, 3)));
+        // the corners of a range can be given either way round
+        let range = ReplaceScope::Range(pos(3, 1), pos(1, 2));
+        assert!(range.contains(&pos(1, 1)) && range.contains(&pos(3, 2)));
+        assert!(!range.contains(&pos(0, 1)) && !range.contains(&pos(2, 3)));
+        let cell = ReplaceScope::Range(pos(1, 1), pos(1, 1));
+        assert!(cell.contains(&pos(1, 1)) && !cell.contains(&pos(1, 2)));
+    }
+}
@@ -0,0 +1,252 @@
+use std::cmp::Ordering;
+
+/// The kind of values held by a column, deciding how its cells are compared
+/// when sorting.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnType {
+    Numeric,
+    Date,
+    Text
+}
+
+impl ColumnType {
+    /// Picks the column type that fits every non empty value. Columns without
+    /// any values are treated as text.
+    pub fn detect<'a>(values: impl Iterator<Item = &'a str> + Clone) -> ColumnType {
+        let mut non_empty = values.filter(|value| !value.trim().is_empty()).peekable();
+        if non_empty.peek().is_none() {
+            return ColumnType::Text;
+        }
+        if non_empty.clone().all(|value| parse_number(value).is_some()) {
+            ColumnType::Numeric
+        } else if non_empty.all(|value| parse_date(value).is_some()) {
+            ColumnType::Date
+        } else {
+            ColumnType::Text
+        }
+    }
+}
+
+/// Compares two cell values as the given column type. Empty cells are always
+/// ordered after non empty ones, regardless of the direction of the sort.
+pub fn compare_cells(first: &str,
+                     second: &str,
+                     column_type: ColumnType,
+                     descending: bool) -> Ordering {
+    match (first.trim().is_empty(), second.trim().is_empty()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Greater,
+        (false, true) => return Ordering::Less,
+        (false, false) => {}
+    }
+    let ordering = match column_type {
+        ColumnType::Numeric => {
+            match (parse_number(first), parse_number(second)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                _ => compare_natural(first, second)
+            }
+        },
+        ColumnType::Date => {
+            match (parse_date(first), parse_date(second)) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => compare_natural(first, second)
+            }
+        },
+        ColumnType::Text => compare_natural(first, second)
+    };
+    match descending {
+        true => ordering.reverse(),
+        false => ordering
+    }
+}
+
+/// Compares two values on their own, as numbers if both are numbers, as 
+/// dates if both are dates, and otherwise as text.
+pub fn compare_values(first: &str, second: &str) -> Ordering {
+    if let (Some(a), Some(b)) = (parse_number(first), parse_number(second)) {
+        return a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+    }
+    if let (Some(a), Some(b)) = (parse_date(first), parse_date(second)) {
+        return a.cmp(&b);
+    }
+    compare_natural(first, second)
+}
+
+/// Parses a number, which must have at least one digit, so that words such
+/// as `inf` or `NaN` that parse as floats are treated as text.
+fn parse_number(value: &str) -> Option<f64> {
+    let value = value.trim();
+    if !value.bytes().any(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    value.parse::<f64>().ok()
+}
+
+/// Parses dates in the form `YYYY-MM-DD` or `YYYY/MM/DD`, optionally followed
+/// by a time of `HH:MM` or `HH:MM:SS` separated by a space or `T`. Returns the
+/// parts in order, so that the result can be compared directly.
+fn parse_date(value: &str) -> Option<(u32, u32, u32, u32, u32, u32)> {
+    let value = value.trim();
+    let (date, time) = match value.find([' ', 'T']) {
+        Some(index) => (&value[..index], Some(&value[index + 1..])),
+        None => (value, None)
+    };
+
+    let separator = if date.contains('-') { '-' } else { '/' };
+    let date_parts: Vec<&str> = date.split(separator).collect();
+    if date_parts.len() != 3 || date_parts[0].len() != 4 {
+        return None;
+    }
+    let year = parse_date_part(date_parts[0], 0, 9999)?;
+    let month = parse_date_part(date_parts[1], 1, 12)?;
+    let day = parse_date_part(date_parts[2], 1, 31)?;
+
+    let (hour, minute, second) = match time {
+        Some(time_value) => {
+            let time_parts: Vec<&str> = time_value.split(':').collect();
+            if time_parts.len() < 2 || time_parts.len() > 3 {
+                return None;
+            }
+            let hour = parse_date_part(time_parts[0], 0, 23)?;
+            let minute = parse_date_part(time_parts[1], 0, 59)?;
+            let second = match time_parts.get(2) {
+                Some(part) => parse_date_part(part, 0, 60)?,
+                None => 0
+            };
+            (hour, minute, second)
+        },
+        None => (0, 0, 0)
+    };
+
+    Some((year, month, day, hour, minute, second))
+}
+
+fn parse_date_part(part: &str, min: u32, max: u32) -> Option<u32> {
+    if part.is_empty() || part.len() > 4 || !part.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    part.parse::<u32>().ok().filter(|number| *number >= min && *number <= max)
+}
+
+/// Compares text so that runs of digits are ordered by their numeric value,
+/// and everything else is ordered ignoring case. For example `item2` is
+/// ordered before `Item10`.
+fn compare_natural(first: &str, second: &str) -> Ordering {
+    let mut first_chars = first.chars().peekable();
+    let mut second_chars = second.chars().peekable();
+
+    loop {
+        match (first_chars.peek(), second_chars.peek()) {
+            (None, None) => break,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) if a.is_ascii_digit() && b.is_ascii_digit() => {
+                let first_digits = take_digits(&mut first_chars);
+                let second_digits = take_digits(&mut second_chars);
+                let first_number = first_digits.trim_start_matches('0');
+                let second_number = second_digits.trim_start_matches('0');
+                let ordering = first_number.len().cmp(&second_number.len())
+                    .then_with(|| first_number.cmp(second_number));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            },
+            (Some(a), Some(b)) => {
+                let ordering = a.to_lowercase().cmp(b.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                first_chars.next();
+                second_chars.next();
+            }
+        }
+    }
+    // values equal apart from case or leading zeros are still given an order
+    first.cmp(second)
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(*c);
+        chars.next();
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_type_fits_every_value() {
+        let detect = |values: &[&str]| ColumnType::detect(values.iter().copied());
+        assert_eq!(detect(&["1", " -2.5 ", "1e3", ""]), ColumnType::Numeric);
+        assert_eq!(detect(&["2024-01-31", "2023/12/01 10:30", ""]), ColumnType::Date);
+        assert_eq!(detect(&["1", "2024-01-31"]), ColumnType::Text);
+        assert_eq!(detect(&["1", "two"]), ColumnType::Text);
+        assert_eq!(detect(&["", "  "]), ColumnType::Text);
+        assert_eq!(detect(&[]), ColumnType::Text);
+    }
+
+    #[test]
+    fn numbers_need_a_digit() {
+        assert_eq!(parse_number(" 12.5 "), Some(12.5));
+        assert_eq!(parse_number("-1e3"), Some(-1000.0));
+        assert_eq!(parse_number(".5"), Some(0.5));
+        for word in ["inf", "-inf", "Infinity", "NaN", "nan", "e", "-", "."] {
+            assert_eq!(parse_number(word), None, "{} is not a number", word);
+        }
+        let detect = |values: &[&str]| ColumnType::detect(values.iter().copied());
+        assert_eq!(detect(&["1", "inf"]), ColumnType::Text);
+        assert_eq!(detect(&["nan"]), ColumnType::Text);
+    }
+
+    #[test]
+    fn dates_are_parsed_in_each_form() {
+        assert_eq!(parse_date("2024-02-03"), Some((2024, 2, 3, 0, 0, 0)));
+        assert_eq!(parse_date("2024/02/03"), Some((2024, 2, 3, 0, 0, 0)));
+        assert_eq!(parse_date("2024-02-03 04:05"), Some((2024, 2, 3, 4, 5, 0)));
+        assert_eq!(parse_date("2024-02-03T04:05:06"), Some((2024, 2, 3, 4, 5, 6)));
+        for value in ["24-02-03", "2024-13-01", "2024-02-32", "2024-02", 
+                      "2024-02-03 25:00", "2024-02-03 04", "03/02/2024", "2024-0a-03"] {
+            assert_eq!(parse_date(value), None, "{} is not a date", value);
+        }
+    }
+
+    #[test]
+    fn natural_order_compares_runs_of_digits_as_numbers() {
+        assert_eq!(compare_natural("a2", "a10"), Ordering::Less);
+        assert_eq!(compare_natural("item2", "Item10"), Ordering::Less);
+        assert_eq!(compare_natural("b", "A"), Ordering::Greater);
+        // leading zeros only decide the order of otherwise equal values
+        assert_eq!(compare_natural("a02", "a3"), Ordering::Less);
+        assert_ne!(compare_natural("a02", "a2"), Ordering::Equal);
+        assert_eq!(compare_natural("a", "ab"), Ordering::Less);
+    }
+
+    #[test]
+    fn empty_cells_go_last_in_both_directions() {
+        for descending in [false, true] {
+            let mut values = ["", "2", " ", "10", "1"];
+            values.sort_by(|a, b| compare_cells(a, b, ColumnType::Numeric, descending));
+            let expected = match descending {
+                true => ["10", "2", "1"],
+                false => ["1", "2", "10"]
+            };
+            assert_eq!(values[..3], expected);
+            assert!(values[3..].iter().all(|value| value.trim().is_empty()));
+        }
+    }
+
+    #[test]
+    fn values_are_compared_as_numbers_then_dates_then_text() {
+        assert_eq!(compare_values("9", "10"), Ordering::Less);
+        assert_eq!(compare_values("2024-01-02", "2023-12-31"), Ordering::Greater);
+        assert_eq!(compare_values("x9", "x10"), Ordering::Less);
+        assert_eq!(compare_values("inf", "10"), Ordering::Greater);
+    }
+}
@@ -1,3 +1,9 @@
+use crate::model::search_model::{
+    SearchPattern,
+    SearchOptions,
+    ReplaceScope
+};
+
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum InputMode {
     Normal,
@@ -8,7 +14,17 @@ pub enum InputMode {
     Quiting,
     QuitSaving,
     Saved,
-    SavedFailed
+    SavedFailed,
+    Searching,
+    Filtering,
+    Command,
+    ConfirmOverwrite,
+    SelectingRange,
+    ReplacingFind,
+    ReplacingWith,
+    ReplaceConfirm,
+    ViewingProblems,
+    ConfirmNormalise
 }
 
 pub enum _InsertMode {
@@ -23,7 +39,7 @@ pub enum RunningMode {
     Help
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Position {
     pub row: usize,
     pub col: usize
@@ -35,3 +51,102 @@ pub struct Size {
     pub height: usize
 }
 
+
+/// How a row is marked beside its row number. A row can be marked both
+/// ways at once.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RowMark {
+    /// The row has a different number of values from the other rows.
+    pub is_ragged: bool,
+    /// A problem was found in the row when the file was loaded.
+    pub has_problem: bool
+}
+
+/// A list shown in a panel below the table. Only the items near the selected
+/// one are given, as the list can be very long.
+#[derive(Debug)]
+pub struct ListPanel {
+    pub title: String,
+    pub items: Vec<String>,
+    /// The position of the selected item in items.
+    pub selected: usize
+}
+
+/// How far through reading a file in the background loading has got.
+#[derive(Clone, Copy, Debug)]
+pub struct LoadProgress {
+    pub bytes_read: u64,
+    pub file_len: u64,
+    pub rows_read: usize
+}
+
+/// Everything a view needs to draw one frame, borrowed from the models for
+/// as long as the frame is drawn.
+#[derive(Clone, Copy)]
+pub struct ViewState<'a> {
+    /// The slice of the CSV data that fits on the page.
+    pub data_slice: &'a [Vec<String>],
+    /// The column names for the columns in data_slice, or None if the CSV
+    /// data has no header row.
+    pub header_slice: Option<&'a [String]>,
+    /// The size of data_slice.
+    pub grid_size: Size,
+    /// The size of the entire CSV data.
+    pub data_size: Size,
+    /// The number of rows matching the current filter, or None if no filter
+    /// is set.
+    pub filtered_row_count: Option<usize>,
+    /// The number of rows with a different number of values from most rows,
+    /// shown in the title of the table when there are any.
+    pub ragged_row_count: usize,
+    /// How wide each column of data_slice should be in order to fit the data.
+    pub column_widths: &'a [usize],
+    /// The row number shown beside each row of data_slice. These differ from
+    /// the position in the grid when a filter hides some of the rows.
+    pub row_numbers: &'a [usize],
+    /// Whether each row of data_slice had a problem when the file was loaded
+    /// or is ragged. These rows are marked beside their row number.
+    pub row_marks: &'a [RowMark],
+    /// Where the top left corner of data_slice appears in the overall CSV
+    /// data. Used to generate row and col numbers.
+    pub corner_pos: Position,
+    /// The position of the selected cell, relative to the current frame
+    /// rather than the overall CSV data.
+    pub relative_pos: Position,
+    /// The number of leading columns of data_slice which are frozen. These
+    /// are always the first columns of the CSV data, and are drawn before a
+    /// separator, with the scrolling columns starting at corner_pos after it.
+    pub frozen_cols: usize,
+    /// The current search, if any. Cells matching it are highlighted.
+    pub search_pattern: Option<&'a SearchPattern>,
+    /// The options used when searching, shown while typing a search.
+    pub search_options: &'a SearchOptions,
+    /// The range of cells currently selected, if any. Its positions are in
+    /// the CSV data, not relative to the frame.
+    pub selected_range: Option<ReplaceScope>,
+    /// The cells a replace applies to, shown while entering what to replace.
+    pub replace_scope: &'a ReplaceScope,
+    pub input_mode: &'a InputMode,
+    pub running_mode: &'a RunningMode,
+    /// The current value of the input field.
+    pub current_input: &'a str,
+    /// Where the cursor is in current_input, as the byte position of the
+    /// character it is on.
+    pub input_cursor: usize,
+    /// The display column of current_input shown at the left of the input
+    /// field, so that the cursor stays in view when the input is longer than
+    /// the field.
+    pub input_scroll: usize,
+    /// The name of the file being edited.
+    pub filename: &'a Option<String>,
+    /// Whether the current state of the file is saved to disk.
+    pub is_saved: bool,
+    /// A message to show in the header line, if any.
+    pub status_msg: &'a Option<String>,
+    /// How much of the file has been read while it is loaded in the
+    /// background, shown as a gauge beside the header line.
+    pub load_progress: Option<LoadProgress>,
+    /// A list shown below the table, such as the problems found when loading
+    /// a file, or the rows normalising the data would change.
+    pub list_panel: Option<&'a ListPanel>
+}
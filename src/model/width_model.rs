@@ -0,0 +1,197 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap
+};
+
+use unicode_width::UnicodeWidthStr;
+
+/// The number of values of each width in a column.
+pub type WidthCounts = BTreeMap<usize, usize>;
+
+/// The widths of the values in each column, counted by width, so that the
+/// widest value of a column is known without scanning the rows, and stays
+/// known as values are changed and removed. Empty values are not counted.
+#[derive(Default)]
+pub struct ColumnWidths {
+    cols: Vec<WidthCounts>
+}
+
+impl ColumnWidths {
+    /// Returns the width of the widest value in the column.
+    pub fn get(&self, col: usize) -> usize {
+        match self.cols.get(col).and_then(|widths| widths.keys().next_back()) {
+            Some(width) => *width,
+            None => 0
+        }
+    }
+
+    pub fn add_value(&mut self, col: usize, value: &str) {
+        let width = value_width(value);
+        if width == 0 {
+            return;
+        }
+        if self.cols.len() <= col {
+            self.cols.resize_with(col + 1, BTreeMap::new);
+        }
+        *self.cols[col].entry(width).or_insert(0) += 1;
+    }
+
+    pub fn remove_value(&mut self, col: usize, value: &str) {
+        let width = value_width(value);
+        if let Some(widths) = self.cols.get_mut(col) {
+            if let Some(count) = widths.get_mut(&width) {
+                *count -= 1;
+                if *count == 0 {
+                    widths.remove(&width);
+                }
+            }
+        }
+    }
+
+    pub fn add_row(&mut self, row: &[String]) {
+        for (col, value) in row.iter().enumerate() {
+            self.add_value(col, value);
+        }
+    }
+
+    pub fn remove_row(&mut self, row: &[String]) {
+        for (col, value) in row.iter().enumerate() {
+            self.remove_value(col, value);
+        }
+    }
+
+    /// Shifts the columns from col onwards one to the right, counting the
+    /// widths given for the column inserted.
+    pub fn insert_col(&mut self, col: usize, widths: WidthCounts) {
+        if self.cols.len() < col && !widths.is_empty() {
+            self.cols.resize_with(col, BTreeMap::new);
+        }
+        if col <= self.cols.len() {
+            self.cols.insert(col, widths);
+        }
+    }
+
+    /// Drops the column, shifting the columns after it one to the left.
+    /// Returns the widths counted for the column.
+    pub fn remove_col(&mut self, col: usize) -> WidthCounts {
+        match col < self.cols.len() {
+            true => self.cols.remove(col),
+            false => WidthCounts::new()
+        }
+    }
+
+    /// Adds the widths counted by other.
+    pub fn merge(&mut self, other: ColumnWidths) {
+        if self.cols.len() < other.cols.len() {
+            self.cols.resize_with(other.cols.len(), BTreeMap::new);
+        }
+        for (widths, other_widths) in self.cols.iter_mut().zip(other.cols) {
+            for (width, count) in other_widths {
+                *widths.entry(width).or_insert(0) += count;
+            }
+        }
+    }
+}
+
+/// Returns how many columns of the terminal the value takes up, which
+/// differs from its length in bytes for characters outside of ASCII, and for
+/// wide characters such as CJK text and emoji.
+fn value_width(value: &str) -> usize {
+    display_value(value).width()
+}
+
+/// Returns the value as it is shown on a single line, with each line break
+/// in it shown as a ↵, so that values with line breaks don't break up the
+/// grid.
+pub fn display_value(value: &str) -> Cow<'_, str> {
+    match value.contains(['\n', '\r']) {
+        true => Cow::Owned(value.replace("\r\n", "↵").replace(['\n', '\r'], "↵")),
+        false => Cow::Borrowed(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn widths_of(rows: &[&[&str]]) -> ColumnWidths {
+        let mut widths = ColumnWidths::default();
+        for row in rows {
+            widths.add_row(&row.iter().map(|value| value.to_string()).collect::<Vec<_>>());
+        }
+        widths
+    }
+
+    #[test]
+    fn widest_value_is_kept_as_values_change() {
+        let mut widths = widths_of(&[&["abc", "d"], &["abcde", ""], &["abcde"]]);
+        assert_eq!((widths.get(0), widths.get(1), widths.get(2)), (5, 1, 0));
+        widths.remove_value(0, "abcde");
+        assert_eq!(widths.get(0), 5);
+        widths.remove_value(0, "abcde");
+        assert_eq!(widths.get(0), 3);
+        widths.add_value(0, "ab");
+        widths.remove_value(0, "abc");
+        assert_eq!(widths.get(0), 2);
+        widths.remove_row(&[String::from("ab"), String::from("d")]);
+        assert_eq!((widths.get(0), widths.get(1)), (0, 0));
+    }
+
+    #[test]
+    fn empty_values_are_not_counted() {
+        let mut widths = widths_of(&[&["", "a"]]);
+        widths.remove_value(0, "");
+        widths.remove_value(1, "");
+        assert_eq!((widths.get(0), widths.get(1)), (0, 1));
+    }
+
+    #[test]
+    fn inserting_and_removing_columns_shifts_the_counts() {
+        let mut widths = widths_of(&[&["a", "bb", "ccc"]]);
+        widths.insert_col(1, WidthCounts::new());
+        assert_eq!((0..4).map(|col| widths.get(col)).collect::<Vec<_>>(), [1, 0, 2, 3]);
+        let removed = widths.remove_col(2);
+        assert_eq!(removed, WidthCounts::from([(2, 1)]));
+        assert_eq!((0..3).map(|col| widths.get(col)).collect::<Vec<_>>(), [1, 0, 3]);
+        widths.insert_col(2, removed);
+        assert_eq!((0..4).map(|col| widths.get(col)).collect::<Vec<_>>(), [1, 0, 2, 3]);
+        widths.insert_col(6, WidthCounts::from([(4, 1)]));
+        assert_eq!((widths.get(5), widths.get(6)), (0, 4));
+        assert!(widths.remove_col(9).is_empty());
+    }
+
+    #[test]
+    fn value_width_counts_terminal_columns() {
+        assert_eq!(value_width("abc"), 3);
+        assert_eq!(value_width("café"), 4);
+        assert_eq!(value_width("日本語"), 6);
+        assert_eq!(value_width("😀"), 2);
+        assert_eq!(value_width("e\u{301}"), 1);
+        assert_eq!(value_width("a\r\nb"), 3);
+    }
+
+    #[test]
+    fn wide_values_are_counted_by_width() {
+        let mut widths = widths_of(&[&["日本語", "abcd"], &["e\u{301}\u{301}", "😀"]]);
+        assert_eq!((widths.get(0), widths.get(1)), (6, 4));
+        widths.remove_value(0, "日本語");
+        widths.remove_value(1, "abcd");
+        assert_eq!((widths.get(0), widths.get(1)), (1, 2));
+    }
+
+    #[test]
+    fn display_value_shows_each_line_break_once() {
+        assert_eq!(display_value("a\r\nb\nc\rd"), "a↵b↵c↵d");
+        assert!(matches!(display_value("語"), Cow::Borrowed("語")));
+    }
+
+    #[test]
+    fn merging_adds_the_counts() {
+        let mut widths = widths_of(&[&["abc"]]);
+        widths.merge(widths_of(&[&["a", "bb"], &["abc"]]));
+        widths.remove_value(0, "abc");
+        assert_eq!((widths.get(0), widths.get(1)), (3, 2));
+        widths.remove_value(0, "abc");
+        assert_eq!(widths.get(0), 1);
+    }
+}
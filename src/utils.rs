@@ -1 +1,2 @@
 pub mod handle_args;
+pub mod save_file;
@@ -1,11 +1,16 @@
 use std::collections::HashMap;
 
+use encoding_rs::{Encoding, UTF_8};
+
 use crate::model::{
     csv_model::{
         CsvModel, 
-        CsvDelimiter},
+        CsvDelimiter,
+        CsvOptions,
+        parse_byte},
     app_state_model::AppStateModel, 
-    utils_model::RunningMode};
+    utils_model::{RunningMode, InputMode},
+    problem_model::{MalformedFile, RejectedFile}};
 
 pub fn handle_input_args(args: Vec<String>) 
     -> Result<(CsvModel, AppStateModel, RunningMode), &'static str> {
@@ -19,12 +24,23 @@ pub fn handle_input_args(args: Vec<String>)
      * -t or --tab
      * -sc or --semicolon
      * -s or --space
+     * --delimiter, --quote or --escape followed by a single ASCII byte
+     * --encoding followed by the name of an encoding
      * -d or --debug
+     * -H or --header
+     * -b or --backup
+     * -l or --lenient
+     * --follow
      */
-    let app_data: CsvModel;
+    let mut app_data: CsvModel;
     let mut filename: Option<&String> = None;
-    let mut delimiter: Option<&CsvDelimiter> = None;
+    let mut options = CsvOptions::default();
     let mut running_mode = RunningMode::Normal;
+    let mut has_header = false;
+    let mut make_backups = false;
+    let mut is_following = false;
+    let mut is_detected = false;
+    let mut rejected_file = None;
 
     println!("{:?}", args);
 
@@ -59,11 +75,11 @@ pub fn handle_input_args(args: Vec<String>)
             "-sc"|"--semicolon" |
             "-s"|"--space" => {
                 let delimiter_err_message = "Error - unable to determine chosen delimiter.";
-                delimiter = match args.get(index) {
+                options.delimiter = match args.get(index) {
                     Some(tag) => {
                         match long_delimiters.get(&tag[..]) {
-                            Some(short_tag) => delimiters.get(short_tag),
-                            None => delimiters.get(&tag[..])
+                            Some(short_tag) => delimiters.get(short_tag).copied(),
+                            None => delimiters.get(&tag[..]).copied()
                         }
                     },
                     None => {
@@ -72,13 +88,57 @@ pub fn handle_input_args(args: Vec<String>)
                 }
 
             },
-            "-d"|"--debug" => {
-                if running_mode != RunningMode::Help {
-                    running_mode = RunningMode::Debug;
-                }
+            "--delimiter" => {
+                options.delimiter = match args.get(index + 1)
+                    .and_then(|value| CsvDelimiter::parse(value)) {
+                    Some(delim) => Some(delim),
+                    None => {
+                        return Err("Error - delimiter must be a single ASCII byte, such as '|' or 0x1f.");
+                    }
+                };
+            },
+            "--quote" => {
+                options.quote = match args.get(index + 1).and_then(|value| parse_byte(value)) {
+                    Some(quote) => Some(quote),
+                    None => {
+                        return Err("Error - quote must be a single ASCII byte, such as \" or '.");
+                    }
+                };
+            },
+            "--escape" => {
+                options.escape = match args.get(index + 1).and_then(|value| parse_byte(value)) {
+                    Some(escape) => Some(escape),
+                    None => {
+                        return Err("Error - escape must be a single ASCII byte, such as \\.");
+                    }
+                };
+            },
+            "--encoding" => {
+                options.encoding = match args.get(index + 1)
+                    .and_then(|value| Encoding::for_label(value.as_bytes())) {
+                    Some(encoding) => Some(encoding),
+                    None => {
+                        return Err("Error - --encoding needs the name of an encoding, such as latin1, windows-1252 or utf-16.");
+                    }
+                };
+            },
+            "-d"|"--debug" if running_mode != RunningMode::Help => {
+                running_mode = RunningMode::Debug;
             },
             "-h"|"--help" => {
                 running_mode = RunningMode::Help;
+            },
+            "-H"|"--header" => {
+                has_header = true;
+            },
+            "-b"|"--backup" => {
+                make_backups = true;
+            },
+            "-l"|"--lenient" => {
+                options.lenient = true;
+            },
+            "--follow" => {
+                is_following = true;
             }
             _ => {}
         };
@@ -86,44 +146,89 @@ pub fn handle_input_args(args: Vec<String>)
 
     match filename {
         Some(fname) => {
-            match delimiter {
-                Some(delim) => {
-                    app_data = match CsvModel::load_file(fname, delim) {
-                        Ok(app) => app,
-                        Err(_) => {
-                            return Err("Error - Unable to load CSV with defined delimiter.");
-                        }
-                    };
-                },
-                None => {
-                   app_data = match CsvModel::load_file(fname, &CsvDelimiter::Comma) {
-                       Ok(app) => app,
-                       Err(_) => {
-                           return Err("Error - Unable to load CSV");
-                       }
-                   }
-
+            app_data = match CsvModel::load_file(fname, &options) {
+                Ok(app) => app,
+                Err(err) => match MalformedFile::from_error(&err) {
+                    // the editor opens without the file, listing its problems
+                    Some(malformed) => {
+                        rejected_file = Some(RejectedFile {
+                            filename: fname.clone(),
+                            problems: malformed.problems.clone()
+                        });
+                        CsvModel::default_with_options(&options)
+                    },
+                    None => {
+                        return Err(match options.delimiter {
+                            Some(_) => "Error - Unable to load CSV with defined delimiter.",
+                            None => "Error - Unable to load CSV"
+                        });
+                    }
                 }
-            }
+            };
+            // a detected encoding is reported even when the delimiter is given
+            is_detected = rejected_file.is_none() 
+                && (options.delimiter.is_none() 
+                    || (options.encoding.is_none() && app_data.get_encoding() != UTF_8));
         },
         None => {
-            match delimiter {
-                Some(delim) => {
-                    app_data = match CsvModel::default_with_delimiter(delim) {
-                        Ok(app) => app,
-                        Err(_) => {
-                            return Err("Error - Unable to create new CSV with defined delimiter");
-                        }
-                    }
-                },
-                None => {
-                    app_data = CsvModel::default();
-                }
+            if is_following {
+                return Err("Error - --follow needs a file to follow, given with -f.");
             }
+            app_data = CsvModel::default_with_options(&options);
         }
     }
 
-    let app_state = AppStateModel::from_running_mode(&running_mode);
+    if has_header {
+        app_data.set_has_header(true);
+    }
+    app_data.set_make_backups(make_backups);
+
+    let mut app_state = AppStateModel::from_running_mode(&running_mode);
+    app_state.set_following(is_following);
+    if is_detected {
+        app_state.set_status_msg(Some(format!(
+                    "Detected {}. Change with :set and reload with :e!",
+                    app_data.describe_dialect())));
+    }
+    if !app_data.get_problems().is_empty() {
+        app_state.set_status_msg(Some(format!(
+                    "{} malformed records are marked, press p to list them.",
+                    app_data.get_problems().len())));
+    }
+    if rejected_file.is_some() {
+        app_state.set_rejected_file(rejected_file);
+        app_state.set_input_mode(InputMode::ViewingProblems);
+    }
 
     Ok((app_data, app_state, running_mode))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| String::from(*value)).collect()
+    }
+
+    #[test]
+    fn follow_needs_a_file() {
+        let result = handle_input_args(args(&["csv-tui", "--follow", "-c"]));
+        assert_eq!(result.err(), Some("Error - --follow needs a file to follow, given with -f."));
+    }
+
+    #[test]
+    fn follow_is_set_for_a_file() {
+        let path = std::env::temp_dir()
+            .join(format!("csv_tui_{}_follow_args.csv", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        std::fs::write(&path, b"a,b\n").unwrap();
+        let (_, app_state, _) = handle_input_args(args(&["csv-tui", "--follow", "-f", &path]))
+            .unwrap();
+        assert!(app_state.is_following());
+        let (_, app_state, _) = handle_input_args(args(&["csv-tui", "-f", &path])).unwrap();
+        assert!(!app_state.is_following());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
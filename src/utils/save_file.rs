@@ -0,0 +1,422 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, OpenOptions},
+    hash::Hasher,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    time::SystemTime
+};
+
+/// The most symlinks followed when finding the file to save to, to stop
+/// loops of links.
+const MAX_SYMLINKS: usize = 40;
+
+/// Follows symlinks from the path to the file they point to, which may not
+/// exist yet. Saving replaces that file rather than the link.
+pub fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    let mut current = path.to_path_buf();
+    // the path reached after following the last link allowed is checked too
+    for _ in 0..=MAX_SYMLINKS {
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let target = fs::read_link(&current)?;
+                current = match target.is_absolute() {
+                    true => target,
+                    false => get_parent_dir(&current).join(target)
+                };
+            },
+            _ => return Ok(current)
+        }
+    }
+    Err(io::Error::other("too many levels of symbolic links"))
+}
+
+/// Returns the path of the backup made of a file, which has `.bak` added to
+/// its name.
+pub fn get_backup_path(path: &Path) -> PathBuf {
+    let mut backup_name = path.as_os_str().to_os_string();
+    backup_name.push(".bak");
+    PathBuf::from(backup_name)
+}
+
+/// Replaces the file with the contents written by write_contents, so that the
+/// file holds either its old or new contents if writing fails part way. The 
+/// contents are written to a temporary file in the same directory, which is
+/// synced to disk and then renamed over the file. The file's permissions are
+/// kept. Returns the stamp of the new file.
+pub fn write_atomically(path: &Path, 
+                        write_contents: impl FnOnce(&mut dyn Write) -> io::Result<()>) 
+    -> io::Result<FileStamp> {
+    let dir = get_parent_dir(path);
+    let (temp_path, mut temp_file) = create_temp_file(dir, path)?;
+
+    let result = fill_temp_file(&mut temp_file, path, write_contents);
+    // the file is closed before renaming, as open files can't be renamed on 
+    // every platform
+    drop(temp_file);
+    let (hasher, size) = match result.and_then(|hashed| {
+        fs::rename(&temp_path, path).map(|_| hashed)
+    }) {
+        Ok(hashed) => hashed,
+        Err(err) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err);
+        }
+    };
+
+    // the rename is only durable once the directory is synced, which isn't
+    // possible on every platform, so failures are ignored
+    if let Ok(dir_file) = fs::File::open(dir) {
+        let _ = dir_file.sync_all();
+    }
+    Ok(FileStamp {
+        path: path.to_path_buf(),
+        modified: fs::metadata(path).ok().and_then(|data| data.modified().ok()),
+        size,
+        hasher: Some(hasher)
+    })
+}
+
+/// Writes the contents to the temporary file and syncs it to disk, giving it
+/// the permissions of the file it will replace.
+/// Returns the hash and length of the contents.
+fn fill_temp_file(temp_file: &mut fs::File,
+                  path: &Path,
+                  write_contents: impl FnOnce(&mut dyn Write) -> io::Result<()>)
+    -> io::Result<(DefaultHasher, u64)> {
+    if let Ok(metadata) = fs::metadata(path) {
+        temp_file.set_permissions(metadata.permissions())?;
+    }
+    let mut writer = BufWriter::new(HashingWriter {
+        inner: &mut *temp_file,
+        hasher: DefaultHasher::new(),
+        size: 0
+    });
+    write_contents(&mut writer)?;
+    let hashed = writer.into_inner().map_err(|err| err.into_error())?;
+    hashed.inner.sync_all()?;
+    Ok((hashed.hasher, hashed.size))
+}
+
+/// Hashes the contents written through it, so that the stamp of a saved file
+/// can be made without reading it back.
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: DefaultHasher,
+    size: u64
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher = hash_contents(self.hasher.clone(), &buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn get_parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+    }
+}
+
+/// Creates a new, empty, hidden file in the directory, named after the file
+/// it will replace.
+fn create_temp_file(dir: &Path, path: &Path) -> io::Result<(PathBuf, fs::File)> {
+    let file_name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => String::from("csv-tui")
+    };
+    let mut attempt = 0;
+    loop {
+        let temp_path = dir.join(format!(".{}.{}.{}.tmp", file_name, process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                attempt += 1;
+            },
+            Err(err) => return Err(err)
+        }
+    }
+}
+
+/// Identifies the contents of a file at the time it was loaded or saved, so
+/// that changes made to it by something else can be detected.
+pub struct FileStamp {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    size: u64,
+    /// The hash of the contents, kept unfinished so that contents appended 
+    /// later can be added to it. None if the contents weren't read.
+    hasher: Option<DefaultHasher>
+}
+
+impl FileStamp {
+    /// Records the state of the file, which holds the contents.
+    pub fn new(path: &Path, contents: &[u8]) -> FileStamp {
+        let metadata = fs::metadata(path).ok();
+        FileStamp {
+            path: path.to_path_buf(),
+            modified: metadata.as_ref().and_then(|data| data.modified().ok()),
+            size: contents.len() as u64,
+            hasher: Some(hash_contents(DefaultHasher::new(), contents))
+        }
+    }
+
+    /// Records the state of the file without reading its contents, so that
+    /// any change to its modification time counts as a change.
+    pub fn from_metadata(path: &Path) -> io::Result<FileStamp> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp {
+            path: path.to_path_buf(),
+            modified: metadata.modified().ok(),
+            size: metadata.len(),
+            hasher: None
+        })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// Records that the contents were added to the end of the file.
+    pub fn append(&mut self, contents: &[u8]) {
+        self.modified = fs::metadata(&self.path).ok().and_then(|data| data.modified().ok());
+        self.size += contents.len() as u64;
+        self.hasher = self.hasher.take().map(|hasher| hash_contents(hasher, contents));
+    }
+
+    /// Checks whether the file has changed since it was recorded. Files 
+    /// which have been removed are not counted as changed, as there is 
+    /// nothing to lose by writing them again.
+    pub fn has_changed(&self) -> bool {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(data) => data,
+            Err(_) => return false
+        };
+        if metadata.len() == self.size && metadata.modified().ok() == self.modified {
+            return false;
+        }
+        if metadata.len() != self.size {
+            return true;
+        }
+        // the file may only have been touched, so its contents are compared
+        let hasher = match &self.hasher {
+            Some(hasher) => hasher,
+            None => return true
+        };
+        match fs::read(&self.path) {
+            Ok(contents) => {
+                hash_contents(DefaultHasher::new(), &contents).finish() != hasher.finish()
+            },
+            Err(_) => true
+        }
+    }
+}
+
+/// Adds the contents to the hash. The bytes are written without their length,
+/// so that hashing contents in parts gives the same hash as hashing them whole.
+fn hash_contents(mut hasher: DefaultHasher, contents: &[u8]) -> DefaultHasher {
+    hasher.write(contents);
+    hasher
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an empty directory for the test to write to, in the temporary
+    /// directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("csv_tui_{}_{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    fn dir_entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn write_bytes(path: &Path, contents: &'static [u8]) -> io::Result<FileStamp> {
+        write_atomically(path, |output| output.write_all(contents))
+    }
+
+    #[test]
+    fn file_is_replaced_without_leaving_temp_files() {
+        let dir = temp_dir("replace");
+        let path = dir.join("data.csv");
+        fs::write(&path, b"old").unwrap();
+        let stamp = write_bytes(&path, b"new,contents\n").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new,contents\n");
+        assert_eq!(stamp.get_size(), 13);
+        assert!(!stamp.has_changed());
+        assert_eq!(dir_entries(&dir), ["data.csv"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn temp_file_is_removed_when_writing_fails() {
+        let dir = temp_dir("fail");
+        let path = dir.join("data.csv");
+        fs::write(&path, b"old").unwrap();
+        let result = write_atomically(&path, |output| {
+            output.write_all(b"part of the")?;
+            Err(io::Error::other("stopped"))
+        });
+        assert_eq!(result.err().unwrap().to_string(), "stopped");
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        assert_eq!(dir_entries(&dir), ["data.csv"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions_are_kept() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("mode");
+        let path = dir.join("data.csv");
+        fs::write(&path, b"old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        write_bytes(&path, b"new").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_through_a_symlink_replaces_its_target() {
+        use std::os::unix::fs::symlink;
+
+        let dir = temp_dir("symlink");
+        fs::create_dir(dir.join("real")).unwrap();
+        let target = dir.join("real").join("data.csv");
+        fs::write(&target, b"old").unwrap();
+        // a relative link, via a second link
+        let link = dir.join("link.csv");
+        symlink("real/data.csv", dir.join("inner.csv")).unwrap();
+        symlink(dir.join("inner.csv"), &link).unwrap();
+        let path = resolve_symlinks(&link).unwrap();
+        assert_eq!(path, dir.join("real").join("data.csv"));
+        write_bytes(&path, b"new").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read(&link).unwrap(), b"new");
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert_eq!(dir_entries(&dir.join("real")), ["data.csv"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loops_are_stopped() {
+        use std::os::unix::fs::symlink;
+
+        let dir = temp_dir("loop");
+        symlink(dir.join("b.csv"), dir.join("a.csv")).unwrap();
+        symlink(dir.join("a.csv"), dir.join("b.csv")).unwrap();
+        assert!(resolve_symlinks(&dir.join("a.csv")).is_err());
+        // a chain of links is followed as long as it is no longer than the limit
+        for link in 0..MAX_SYMLINKS {
+            symlink(format!("{}.csv", link + 1), dir.join(format!("{}.csv", link))).unwrap();
+        }
+        let end = dir.join(format!("{}.csv", MAX_SYMLINKS));
+        assert_eq!(resolve_symlinks(&dir.join("0.csv")).unwrap(), end);
+        symlink("0.csv", dir.join("start.csv")).unwrap();
+        assert!(resolve_symlinks(&dir.join("start.csv")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Sets the modification time of the file to a time after any it could
+    /// have been given when written.
+    fn touch(path: &Path, seconds_later: u64) {
+        let time = SystemTime::now() + std::time::Duration::from_secs(seconds_later);
+        OpenOptions::new().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    #[test]
+    fn touching_a_file_without_changing_it_is_not_a_change() {
+        let dir = temp_dir("touch");
+        let path = dir.join("data.csv");
+        fs::write(&path, b"a,b\n").unwrap();
+        let stamp = FileStamp::new(&path, b"a,b\n");
+        assert!(!stamp.has_changed());
+        touch(&path, 10);
+        assert!(!stamp.has_changed());
+        // without the contents, a touched file can't be told from a changed one
+        let stamp = FileStamp::from_metadata(&path).unwrap();
+        touch(&path, 20);
+        assert!(stamp.has_changed());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn different_contents_are_a_change() {
+        let dir = temp_dir("change");
+        let path = dir.join("data.csv");
+        fs::write(&path, b"a,b\n").unwrap();
+        let stamp = FileStamp::new(&path, b"a,b\n");
+        // the same size, so only the contents tell the change apart
+        fs::write(&path, b"c,d\n").unwrap();
+        touch(&path, 10);
+        assert!(stamp.has_changed());
+        fs::write(&path, b"a,b\nc\n").unwrap();
+        assert!(stamp.has_changed());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn deleted_file_is_not_a_change() {
+        let dir = temp_dir("deleted");
+        let path = dir.join("data.csv");
+        fs::write(&path, b"a").unwrap();
+        let stamp = FileStamp::new(&path, b"a");
+        fs::remove_file(&path).unwrap();
+        assert!(!stamp.has_changed());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn appended_stamp_matches_the_whole_file() {
+        let dir = temp_dir("append");
+        let path = dir.join("data.csv");
+        fs::write(&path, b"a\n").unwrap();
+        let mut stamp = FileStamp::new(&path, b"a\n");
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"b\n").unwrap();
+        assert!(stamp.has_changed());
+        stamp.append(b"b\n");
+        assert_eq!(stamp.get_size(), 4);
+        assert!(!stamp.has_changed());
+        // the hash of the parts is compared with the hash of the whole file
+        touch(&path, 10);
+        assert!(!stamp.has_changed());
+        fs::write(&path, b"a\nc\n").unwrap();
+        touch(&path, 20);
+        assert!(stamp.has_changed());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_without_links_resolve_to_themselves() {
+        let path = Path::new("missing-dir").join("new.csv");
+        assert_eq!(resolve_symlinks(&path).unwrap(), path);
+        assert_eq!(get_backup_path(&path), Path::new("missing-dir").join("new.csv.bak"));
+    }
+}
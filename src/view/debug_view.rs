@@ -3,35 +3,62 @@ use tui::{
     Frame
 };
 
-use crate::model::utils_model::{
-    Position,
-    Size,
-    InputMode,
-    RunningMode
-};
+use crate::model::utils_model::ViewState;
 
-pub fn render_ui<B: Backend>(data_slice: Vec<Vec<String>>,
-                            grid_size: &Size,
-                            data_size: Size,
-                            column_widths: Vec<usize>,
-                            corner_pos: &Position,
-                            relative_pos: &Position,
-                            input_mode: &InputMode,
-                            running_mode: &RunningMode,
-                            current_input: &str,
-                            filename: &Option<String>,
-                            is_saved: bool,
-                            _f: &mut Frame<B>) {
+pub fn render_ui<B: Backend>(view: &ViewState, _f: &mut Frame<B>) {
+    let ViewState {
+        data_slice,
+        header_slice,
+        grid_size,
+        data_size,
+        filtered_row_count,
+        ragged_row_count,
+        column_widths,
+        row_numbers,
+        row_marks,
+        corner_pos,
+        relative_pos,
+        frozen_cols,
+        search_pattern,
+        search_options,
+        selected_range,
+        replace_scope,
+        input_mode,
+        running_mode,
+        current_input,
+        input_cursor,
+        input_scroll,
+        filename,
+        is_saved,
+        status_msg,
+        load_progress,
+        list_panel
+    } = *view;
     println!("{:?}", filename);
     println!("{:?}", is_saved);
     println!("{:?}", input_mode);
     println!("{:?}", running_mode);
     println!("{:?}", corner_pos);
     println!("{:?}", relative_pos);
+    println!("{:?}", frozen_cols);
+    println!("{:?}", search_pattern.map(|pattern| pattern.get_query()));
+    println!("{:?}", search_options);
+    println!("{:?}", selected_range);
+    println!("{:?}", replace_scope);
+    println!("{:?}", status_msg);
+    println!("{:?}", load_progress);
+    println!("{:?}", list_panel);
     println!("{:?}", column_widths);
+    println!("{:?}", row_numbers);
+    println!("{:?}", row_marks);
     println!("{:?}", current_input);
+    println!("{:?}", input_cursor);
+    println!("{:?}", input_scroll);
     println!("{:?}", data_size);
+    println!("{:?}", filtered_row_count);
+    println!("{:?}", ragged_row_count);
     println!("{:?}", grid_size);
     println!("{:?}", data_slice);
+    println!("{:?}", header_slice);
     todo!();
 }
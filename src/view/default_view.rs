@@ -6,11 +6,17 @@ use tui::{
         Block, 
         Borders, 
         Paragraph, 
-        Table}, 
+        Clear,
+        Gauge,
+        List,
+        ListItem,
+        ListState,
+        Table},    
     layout::{
         Layout, 
         Direction, 
-        Constraint}, 
+        Constraint,
+        Rect},  
     Frame, 
     text::{
         Span, 
@@ -20,60 +26,62 @@ use tui::{
         Style, 
         Modifier, 
         Color}};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::model::utils_model::{
-    Position,
-    Size,
-    InputMode,
-    RunningMode
+use crate::model::{
+    utils_model::{
+        Position,
+        InputMode,
+        RunningMode,
+        LoadProgress,
+        RowMark,
+        ListPanel,
+        ViewState
+    },
+    search_model::{
+        SearchOptions,
+        ReplaceScope
+    },
+    width_model::display_value
 };
 
 /// function renders the UI into the terminal frame provided. 
 ///
 /// # Arguments
 ///
-/// * `data_slice` - a 2D Vec of Strings which represent the slice of the CSV data that
-/// fits on the page.
+/// * `view` - a ViewState struct with the slice of the CSV data that fits on the page,
+///   where it is in the overall data, and the state of the application to show around it.
 ///
-/// * `grid_size` - a Size struct which is the size of the data Vec.
-///
-/// * `data_size` - a Size struct which is the saize of the entire CSV data.
-///
-/// * `column_widths` - a Vec of usize which describes how wide each column should
-/// be in order to fit the data. length should match the width defined in grid_size and 
-/// data.
-///
-/// * `corner_pos` - a Position struct which states where the top left corner of the 
-/// current data slice appears in the overall csv data. Used to generate row and col 
-/// numbers.
-///
-/// * `relative_pos` - a Position struct which states the position of the currently 
-/// selected cell. The position is relative to the current frame, not the overall position
-/// in the csv data.
-///
-/// * `input_mode` - an InputMode enum which states the current input mode that the application is
-/// in.
-///
-/// * `running_mode` - a RunningMode enum which states the current input mode that the application
-/// is in.
-///
-/// * `current_input` - a str which is the current value of the input field.
-///
-/// * `filename` - the name of the file being edited.
-///
-/// * `is_saved` - a boolean desribing if the current state of the file is saved to disk or not.
-pub fn render_ui<B: Backend>(data_slice: Vec<Vec<String>>,
-                            grid_size: &Size,
-                            data_size: Size,
-                            column_widths: Vec<usize>,
-                            corner_pos: &Position,
-                            relative_pos: &Position,
-                            input_mode: &InputMode,
-                            running_mode: &RunningMode,
-                            current_input: &str,
-                            filename: &Option<String>,
-                            is_saved: bool,
-                            f: &mut Frame<B>) {
+/// * `f` - the frame to render into.
+pub fn render_ui<B: Backend>(view: &ViewState, f: &mut Frame<B>) {
+    let ViewState {
+        data_slice,
+        header_slice,
+        grid_size,
+        data_size,
+        filtered_row_count,
+        ragged_row_count,
+        column_widths,
+        row_numbers,
+        row_marks,
+        corner_pos,
+        relative_pos,
+        frozen_cols,
+        search_pattern,
+        search_options,
+        selected_range,
+        replace_scope,
+        input_mode,
+        running_mode,
+        current_input,
+        input_cursor,
+        input_scroll,
+        filename,
+        is_saved,
+        status_msg,
+        load_progress,
+        list_panel
+    } = *view;
     /*
      * configure chunk structure, defining top level as info box, second
      * as input box, and third as a filler of the rest of the space, to 
@@ -92,14 +100,24 @@ pub fn render_ui<B: Backend>(data_slice: Vec<Vec<String>>,
             ].as_ref()) 
         .split(f.size()); 
 
-    let (msg, style) = generate_header_msg(input_mode, filename, is_saved);
+    let (msg, style) = generate_header_msg(input_mode, filename, is_saved, status_msg);
     let mut text = Text::from(Spans::from(msg));
     text.patch_style(style);
     let help_message = Paragraph::new(text);
-    f.render_widget(help_message, chunks[0]);
+    match load_progress {
+        Some(progress) => {
+            let header_chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Min(0), Constraint::Length(50)].as_ref())
+                .split(chunks[0]);
+            f.render_widget(help_message, header_chunks[0]);
+            f.render_widget(generate_load_gauge(progress), header_chunks[1]);
+        },
+        None => f.render_widget(help_message, chunks[0])
+    }
     
-    let input_title = generate_input_title(input_mode);
-    let input = Paragraph::new(current_input)
+    let input_title = generate_input_title(input_mode, search_options, replace_scope);
+    let input = Paragraph::new(scroll_input(&display_value(current_input), input_scroll))
         .block(Block::default().borders(Borders::ALL).title(input_title));
     f.render_widget(input, chunks[1]);
 
@@ -108,9 +126,14 @@ pub fn render_ui<B: Backend>(data_slice: Vec<Vec<String>>,
      */
 
     let col_width: usize = 5;
+    let separator = "│";
+    let separator_style = Style::default().fg(Color::DarkGray);
     let mut widths = Vec::new();
     widths.push(Constraint::Length(col_width as u16));
     for col in 0..grid_size.width {
+        if frozen_cols > 0 && col == frozen_cols {
+            widths.push(Constraint::Length(1));
+        }
         let width = match column_widths.get(col) {
             Some(w) => w,
             None => &col_width
@@ -122,24 +145,67 @@ pub fn render_ui<B: Backend>(data_slice: Vec<Vec<String>>,
     let mut first_row_vec = Vec::new();
     first_row_vec.push(Cell::from(""));
     for col in 0..grid_size.width {
-        let num = corner_pos.col + col;
-        first_row_vec.push(Cell::from(num.to_string()));
+        if frozen_cols > 0 && col == frozen_cols {
+            first_row_vec.push(Cell::from(Span::styled(separator, separator_style)));
+        }
+        let header_name = match &header_slice {
+            Some(header_row) => header_row.get(col),
+            None => None
+        };
+        match header_name {
+            Some(name) if !name.is_empty() => {
+                let style = Style::default().add_modifier(Modifier::BOLD);
+                first_row_vec.push(Cell::from(Span::styled(display_value(name).into_owned(), 
+                                                           style)));
+            },
+            _ => {
+                let num = if col < frozen_cols {
+                    col
+                } else {
+                    corner_pos.col + col - frozen_cols
+                };
+                first_row_vec.push(Cell::from(num.to_string()));
+            }
+        }
     }
     table_rows.push(Row::new(first_row_vec));
 
     let default_cell_value = "_____";
     for row in 0..grid_size.height {
         let mut row_vec = Vec::new();
-        let row_num = corner_pos.row + row;
-        row_vec.push(Cell::from(row_num.to_string()));
+        let row_num = match row_numbers.get(row) {
+            Some(num) => *num,
+            None => corner_pos.row + row
+        };
+        let mark = row_marks.get(row).copied().unwrap_or_default();
+        let style = match mark {
+            RowMark { has_problem: true, .. } => {
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+            },
+            RowMark { is_ragged: true, .. } => Style::default().fg(Color::Yellow),
+            _ => Style::default()
+        };
+        let row_label = format!("{}{}{}",
+                                if mark.has_problem { "!" } else { "" },
+                                if mark.is_ragged { "~" } else { "" },
+                                row_num);
+        row_vec.push(Cell::from(Span::styled(row_label, style)));
         for col in 0..grid_size.width {
+            if frozen_cols > 0 && col == frozen_cols {
+                row_vec.push(Cell::from(Span::styled(separator, separator_style)));
+            }
             let mut cell_has_value = false;
-            let mut cell_value = String::from(match data_slice.get(row) {
+            let mut is_search_match = false;
+            let mut cell_value = display_value(match data_slice.get(row) {
                 Some(data_row) => {
                     match data_row.get(col) {
                         Some(data_cell) => {
-                            if data_cell.len() > 0 {
+                            if !data_cell.is_empty() {
                                 cell_has_value = true;
+                                is_search_match = match search_pattern {
+                                    Some(pattern) => pattern.is_match(data_cell),
+                                    None => false
+                                };
                                 data_cell
                             } else {
                                 default_cell_value
@@ -149,21 +215,16 @@ pub fn render_ui<B: Backend>(data_slice: Vec<Vec<String>>,
                     }
                 },
                 None => default_cell_value
-            });
-            
+            }).into_owned();
+                        
             let max_col_width : usize = match column_widths.get(col) {
                 Some(length) => *length,
                 None => default_cell_value.len()
             };
-            if  cell_value.len() < max_col_width {
-                let diff = max_col_width - cell_value.len();
-                for _ in 0..diff {
-                    cell_value.push('_');
-                }
-            }
+            fit_to_width(&mut cell_value, max_col_width);
 
             match input_mode {
-                InputMode::Normal => {
+                InputMode::Normal | InputMode::SelectingRange => {
                     if relative_pos.row == row && relative_pos.col == col {
                         let style = Style::default()
                             .add_modifier(Modifier::RAPID_BLINK)
@@ -184,7 +245,7 @@ pub fn render_ui<B: Backend>(data_slice: Vec<Vec<String>>,
                         row_vec.push(cell);
                     }
                 }
-                InputMode::Editing => {
+                InputMode::Editing | InputMode::Searching | InputMode::Filtering => {
                     if relative_pos.row == row && relative_pos.col == col {
                         let style = Style::default().fg(Color::Yellow);
                         let cell = Cell::from(
@@ -207,7 +268,14 @@ pub fn render_ui<B: Backend>(data_slice: Vec<Vec<String>>,
                     InputMode::Saved | 
                     InputMode::SavedFailed |
                     InputMode::Quiting |
-                    InputMode::QuitSaving => {
+                    InputMode::QuitSaving |
+                    InputMode::Command |
+                    InputMode::ConfirmOverwrite |
+                    InputMode::ReplacingFind |
+                    InputMode::ReplacingWith |
+                    InputMode::ReplaceConfirm |
+                    InputMode::ViewingProblems |
+                    InputMode::ConfirmNormalise => {
                         let cell = Cell::from(cell_value);
                         row_vec.push(cell);
                 },
@@ -250,12 +318,41 @@ pub fn render_ui<B: Backend>(data_slice: Vec<Vec<String>>,
                     }
                 }
             }
+            let data_pos = Position {
+                row: corner_pos.row + row,
+                col: match col < frozen_cols {
+                    true => col,
+                    false => corner_pos.col + col - frozen_cols
+                }
+            };
+            let is_in_selected_range = match &selected_range {
+                Some(range) => range.contains(&data_pos),
+                None => false
+            };
+            if is_search_match || is_in_selected_range {
+                let background = match is_in_selected_range {
+                    true => Color::DarkGray,
+                    false => Color::Blue
+                };
+                if let Some(cell) = row_vec.pop() {
+                    row_vec.push(cell.style(Style::default().bg(background)));
+                }
+            }
         }
         table_rows.push(Row::new(row_vec));
     }
-    let current_size_string = format!("Rows - {}, Cols - {}", 
-                                        data_size.height,
-                                        data_size.width);
+    let mut current_size_string = match filtered_row_count {
+        Some(count) => format!("Rows - {} of {}, Cols - {}",
+                               count,
+                               data_size.height,
+                               data_size.width),
+        None => format!("Rows - {}, Cols - {}", 
+                        data_size.height,
+                        data_size.width)
+    };
+    if ragged_row_count > 0 {
+        current_size_string.push_str(&format!(", Ragged - {}", ragged_row_count));
+    }
     let table_name = match filename {
         Some(name) => String::from(name),
         None => String::from("Table"),
@@ -268,9 +365,30 @@ pub fn render_ui<B: Backend>(data_slice: Vec<Vec<String>>,
         .highlight_style(Style::default().add_modifier(Modifier::BOLD));
     let debug_str = format!("{:?}", data_slice);
     let debug_display = Paragraph::new(debug_str);
+    let mut value_editor_cursor = None;
     match running_mode {
         RunningMode::Normal => {
-            f.render_widget(table,chunks[2]);
+            match list_panel {
+                Some(panel) => {
+                    let panel_height = (panel.items.len() as u16 + 2).min(chunks[2].height / 2);
+                    let table_chunks = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([Constraint::Min(0), Constraint::Length(panel_height)].as_ref())
+                        .split(chunks[2]);
+                    f.render_widget(table, table_chunks[0]);
+                    let (list, mut list_state) = generate_panel_list(panel);
+                    f.render_stateful_widget(list, table_chunks[1], &mut list_state);
+                },
+                None => f.render_widget(table, chunks[2])
+            }
+            if *input_mode == InputMode::Editing && current_input.contains('\n') {
+                let (editor, area, cursor) = generate_value_editor(current_input,
+                                                                   input_cursor,
+                                                                   chunks[2]);
+                f.render_widget(Clear, area);
+                f.render_widget(editor, area);
+                value_editor_cursor = Some(cursor);
+            }
         },
         RunningMode::Debug => {
             f.render_widget(debug_display, chunks[2]);
@@ -287,23 +405,161 @@ pub fn render_ui<B: Backend>(data_slice: Vec<Vec<String>>,
             InputMode::SavedFailed |
             InputMode::Quiting |
             InputMode::SelectingCol |
-            InputMode::SelectingRow => {},
+            InputMode::SelectingRow |
+            InputMode::SelectingRange |
+            InputMode::ConfirmOverwrite |
+            InputMode::ReplaceConfirm |
+            InputMode::ViewingProblems |
+            InputMode::ConfirmNormalise => {},
 
-        InputMode::Editing | 
+        InputMode::Editing |  
             InputMode::Saving | 
-            InputMode::QuitSaving => {
-                f.set_cursor(
-                    chunks[1].x + current_input.len() as u16 + 1, 
-                    chunks[1].y + 1
-                )
+            InputMode::QuitSaving |
+            InputMode::Searching |
+            InputMode::Filtering |
+            InputMode::Command |
+            InputMode::ReplacingFind |
+            InputMode::ReplacingWith => {
+                let cursor_col = input_cursor_col(current_input, input_cursor, input_scroll);
+                match value_editor_cursor {
+                    Some((x, y)) => f.set_cursor(x, y),
+                    None => f.set_cursor(
+                        chunks[1].x + cursor_col as u16 + 1,  
+                        chunks[1].y + 1
+                    )
+                }
         }
     }
 }
 
-fn generate_header_msg(input_mode: &InputMode, 
+/// Lays out a value with line breaks, which is being edited, in a box over
+/// the table, as big as the value needs up to the size of the table. Lines
+/// and columns which don't fit are scrolled to keep the cursor in view.
+/// Returns the box, its area and where the cursor goes in it.
+fn generate_value_editor(value: &str,
+                         cursor: usize,
+                         table_area: Rect) -> (Paragraph<'static>, Rect, (u16, u16)) {
+    let lines: Vec<String> = value.split('\n')
+        .map(|line| display_value(line.strip_suffix('\r').unwrap_or(line)).into_owned())
+        .collect();
+    let cursor_line = value[..cursor].matches('\n').count();
+    let cursor_line_start = match value[..cursor].rfind('\n') {
+        Some(pos) => pos + 1,
+        None => 0
+    };
+    let cursor_col = display_value(&value[cursor_line_start..cursor]).width();
+    let longest_line = lines.iter().map(|line| line.width()).max().unwrap_or(0);
+
+    // the borders and the cursor after the longest line take three columns
+    let width = (longest_line + 3).max(40).min(table_area.width as usize) as u16;
+    let height = (lines.len() + 2).min(table_area.height as usize) as u16;
+    let area = Rect {
+        x: table_area.x + table_area.width.saturating_sub(width) / 2,
+        y: table_area.y + table_area.height.saturating_sub(height) / 2,
+        width,
+        height
+    };
+    // a table too small for the borders still shows the cursor's line and
+    // column, which the borders are drawn over
+    let first_line = (cursor_line + 1).saturating_sub(height.saturating_sub(2).max(1) as usize);
+    let first_col = (cursor_col + 1).saturating_sub(width.saturating_sub(2).max(1) as usize);
+    let text: Vec<Spans> = lines.iter().skip(first_line)
+        .map(|line| Spans::from(scroll_input(line, first_col)))
+        .collect();
+    let editor = Paragraph::new(text)
+        .block(Block::default().borders(Borders::ALL).title("Value - Alt-Enter for a new line"));
+    let cursor_pos = ((area.x + 1 + (cursor_col - first_col) as u16)
+                          .min(area.x + area.width.saturating_sub(1)),
+                      (area.y + 1 + (cursor_line - first_line) as u16)
+                          .min(area.y + area.height.saturating_sub(1)));
+    (editor, area, cursor_pos)
+}
+
+/// Returns the column of the input box the cursor is in, once the input has
+/// been scrolled by scroll columns.
+fn input_cursor_col(input: &str, cursor: usize, scroll: usize) -> usize {
+    display_value(&input[..cursor]).width().saturating_sub(scroll)
+}
+
+/// Drops the first scroll columns of the input, replacing what is left of a
+/// wide character cut in half with a space.
+fn scroll_input(input: &str, scroll: usize) -> String {
+    let mut col = 0;
+    let mut visible = String::new();
+    for c in input.chars() {
+        let char_width = c.width().unwrap_or(0);
+        if col >= scroll {
+            visible.push(c);
+        } else if col + char_width > scroll {
+            visible.push_str(&" ".repeat(col + char_width - scroll));
+        }
+        col += char_width;
+    }
+    visible
+}
+
+/// Pads the cell value with underscores, or cuts it short, so that it takes
+/// up exactly width columns of the terminal. A wide character which would
+/// only partly fit is replaced by padding.
+fn fit_to_width(cell_value: &mut String, width: usize) {
+    let mut used_width = 0;
+    let mut fitting_len = cell_value.len();
+    for (i, c) in cell_value.char_indices() {
+        let char_width = c.width().unwrap_or(0);
+        if used_width + char_width > width {
+            fitting_len = i;
+            break;
+        }
+        used_width += char_width;
+    }
+    cell_value.truncate(fitting_len);
+    for _ in used_width..width {
+        cell_value.push('_');
+    }
+}
+
+/// Creates the gauge showing how much of the file has been read, with the
+/// number of rows read so far.
+fn generate_load_gauge(progress: LoadProgress) -> Gauge<'static> {
+    let ratio = match progress.file_len {
+        0 => 1.0,
+        len => (progress.bytes_read as f64 / len as f64).min(1.0)
+    };
+    let label = format!("Loading {}% - {} rows - Esc to stop",
+                        (ratio * 100.0) as u32,
+                        progress.rows_read);
+    Gauge::default()
+        .gauge_style(Style::default().fg(Color::Yellow).bg(Color::DarkGray))
+        .ratio(ratio)
+        .label(label)
+}
+
+/// Lists the items of the panel, one per line, keeping the selected one in
+/// view.
+fn generate_panel_list(panel: &ListPanel) -> (List<'static>, ListState) {
+    let item_count = panel.items.len();
+    let items: Vec<ListItem> = panel.items.iter()
+        .map(|item| ListItem::new(item.clone()))
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(panel.title.clone()))
+        .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ");
+    let mut list_state = ListState::default();
+    if item_count > 0 {
+        list_state.select(Some(panel.selected.min(item_count - 1)));
+    }
+    (list, list_state)
+}
+
+fn generate_header_msg(input_mode: &InputMode,  
                        filename: &Option<String>, 
-                       is_saved: bool) -> (Vec<Span<'static>>, Style) {
-    let (msg, style) = match input_mode { 
+                       is_saved: bool,
+                       status_msg: &Option<String>) -> (Vec<Span<'static>>, Style) {
+    if let (InputMode::Normal, Some(status)) = (input_mode, status_msg) {
+        return (vec![Span::raw(status.clone())], Style::default());
+    }
+    let (mut msg, style) = match input_mode { 
         InputMode::Normal => ( 
             vec![ Span::raw("Press "), 
                 Span::styled("q", 
@@ -329,7 +585,16 @@ fn generate_header_msg(input_mode: &InputMode,
                 Span::raw(" to stop editing, "),
                 Span::styled("Enter", 
                              Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" to record the message"),
+                Span::raw(" to record the message, "),
+                Span::styled("Alt-Enter", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" for a new line, "),
+                Span::styled("Ctrl-W", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("/"),
+                Span::styled("Ctrl-U", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to delete the word/line before the cursor"),
             ],
             Style::default(),
         ),
@@ -432,14 +697,181 @@ fn generate_header_msg(input_mode: &InputMode,
                 Span::raw(" to cancel"),
             ],
             Style::default()
+        ),
+        InputMode::Searching => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("Enter", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to finish searching, "),
+                Span::styled("Esc",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to cancel, "),
+                Span::styled("Alt-c",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to toggle ignoring case, "),
+                Span::styled("Alt-r",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to toggle regex"),
+            ],
+            Style::default()
+        ),
+        InputMode::Filtering => (
+            vec![
+                Span::raw("Enter a filter such as "),
+                Span::styled("col3 > 100", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(", press "),
+                Span::styled("Enter", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to apply, empty to clear, "),
+                Span::styled("Esc",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to cancel"),
+            ],
+            Style::default()
+        ),
+        InputMode::ConfirmOverwrite => (
+            vec![
+                Span::raw("File changed on disk since it was loaded. Press "),
+                Span::styled("o", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to overwrite it, "),
+                Span::styled("r", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to reload it and discard edits, "),
+                Span::styled("c or Esc",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to cancel"),
+            ],
+            Style::default()
+        ),
+        InputMode::Command => (
+            vec![
+                Span::raw("Enter a command such as "),
+                Span::styled(":w", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(", "),
+                Span::styled(":q", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" or "),
+                Span::styled(":goto 10 2", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(", press "),
+                Span::styled("Enter", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to run, "),
+                Span::styled("Esc",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to cancel"),
+            ],
+            Style::default()
+        ),
+        InputMode::SelectingRange => (
+            vec![
+                Span::raw("Move to select a range, press "),
+                Span::styled("R", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to replace within it, "),
+                Span::styled("Esc",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to cancel"),
+            ],
+            Style::default()
+        ),
+        InputMode::ReplacingFind => (
+            vec![
+                Span::raw("Enter text to find, press "),
+                Span::styled("Enter", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to continue, "),
+                Span::styled("Tab",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to change scope, "),
+                Span::styled("Alt-c",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("/"),
+                Span::styled("Alt-r",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to toggle ignoring case/regex, "),
+                Span::styled("Esc",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to cancel"),
+            ],
+            Style::default()
+        ),
+        InputMode::ReplacingWith => (
+            vec![
+                Span::raw("Enter replacement, "),
+                Span::styled("$1", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" refers to regex groups. Press "),
+                Span::styled("Enter", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to continue, "),
+                Span::styled("Esc",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to cancel"),
+            ],
+            Style::default()
+        ),
+        InputMode::ReplaceConfirm => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("y", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to replace, "),
+                Span::styled("n or Esc",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to cancel"),
+            ],
+            Style::default()
+        ),
+        InputMode::ViewingProblems => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("Up/Down", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to choose a problem, "),
+                Span::styled("Enter", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to go to its row, "),
+                Span::styled("Esc",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to close"),
+            ],
+            Style::default()
+        ),
+        InputMode::ConfirmNormalise => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("y", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to normalise, "),
+                Span::styled("Up/Down", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to choose a row, "),
+                Span::styled("Enter", 
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to go to it, "),
+                Span::styled("n or Esc",
+                             Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to cancel"),
+            ],
+            Style::default()
         )
     };
+    if let Some(status) = status_msg {
+        msg.insert(0, Span::raw(status.clone()));
+    }
 
-    return (msg, style);
+    (msg, style)
 }
 
-fn generate_input_title(input_mode: &InputMode) -> &str {
-    match input_mode {
+fn generate_input_title(input_mode: &InputMode, 
+                        search_options: &SearchOptions,
+                        replace_scope: &ReplaceScope) -> String {
+    let title = match input_mode {
         InputMode::Normal => "Input - Normal",
         InputMode::SavedFailed => "Input - Saved Failed",
         InputMode::Quiting => "Input - Quiting",
@@ -448,6 +880,105 @@ fn generate_input_title(input_mode: &InputMode) -> &str {
         InputMode::Saved => "Input - Saved",
         InputMode::Saving => "Input - Saving",
         InputMode::SelectingRow => "Input - Row Selected",
-        InputMode::SelectingCol => "Input - Column Selected"
+        InputMode::SelectingCol => "Input - Column Selected",
+        InputMode::Searching => "Input - Searching",
+        InputMode::Filtering => "Input - Filtering",
+        InputMode::Command => "Input - Command",
+        InputMode::ConfirmOverwrite => "Input - Confirm Overwrite",
+        InputMode::SelectingRange => "Input - Selecting Range",
+        InputMode::ReplacingFind => "Input - Find",
+        InputMode::ReplacingWith => "Input - Replace With",
+        InputMode::ReplaceConfirm => "Input - Confirm Replace",
+        InputMode::ViewingProblems => "Input - Problems",
+        InputMode::ConfirmNormalise => "Input - Confirm Normalise"
+    };
+    let mut title = String::from(title);
+    if *input_mode == InputMode::ReplacingFind {
+        title.push_str(match replace_scope {
+            ReplaceScope::Table => " - Whole Table",
+            ReplaceScope::Column(_) => " - Current Column",
+            ReplaceScope::Range(_, _) => " - Selected Range"
+        });
+    }
+    if *input_mode == InputMode::Searching || *input_mode == InputMode::ReplacingFind {
+        if search_options.case_insensitive {
+            title.push_str(" - Ignoring Case");
+        }
+        if search_options.use_regex {
+            title.push_str(" - Regex");
+        }
+    }
+    title
+}
+
+#[cfg(test)]
+mod tests {
+    use tui::{backend::TestBackend, Terminal};
+
+    use super::*;
+
+    fn fitted(value: &str, width: usize) -> String {
+        let mut value = String::from(value);
+        fit_to_width(&mut value, width);
+        value
+    }
+
+    #[test]
+    fn wide_character_cut_at_the_edge_is_replaced_by_padding() {
+        assert_eq!(fitted("語語", 3), "語_");
+        assert_eq!(fitted("a😀b", 2), "a_");
+        assert_eq!(fitted("a😀b", 3), "a😀");
+        assert_eq!(fitted("😀", 1), "_");
+    }
+
+    #[test]
+    fn values_are_fitted_by_their_display_width() {
+        assert_eq!(fitted("ab", 4), "ab__");
+        assert_eq!(fitted("語", 4), "語__");
+        assert_eq!(fitted("e\u{301}x", 2), "e\u{301}x");
+        assert_eq!(fitted("e\u{301}xy", 2), "e\u{301}x");
+    }
+
+    #[test]
+    fn scrolling_through_a_wide_character_leaves_a_space() {
+        assert_eq!(scroll_input("語a", 1), " a");
+        assert_eq!(scroll_input("語a", 2), "a");
+        assert_eq!(scroll_input("é語", 1), "語");
+    }
+
+    #[test]
+    fn input_cursor_col_counts_display_columns() {
+        let input = "a語e\u{301}😀b";
+        let cols: Vec<usize> = input.char_indices()
+            .map(|(pos, _)| input_cursor_col(input, pos, 0))
+            .collect();
+        assert_eq!(cols, [0, 1, 3, 4, 4, 6]);
+        assert_eq!(input_cursor_col(input, input.len(), 0), 7);
+        assert_eq!(input_cursor_col(input, input.len(), 3), 4);
+        assert_eq!(input_cursor_col("a\nb", 3, 0), 3);
+    }
+
+    #[test]
+    fn value_editor_puts_the_cursor_after_wide_characters() {
+        let value = "ab\n語😀c";
+        let area = Rect { x: 0, y: 0, width: 80, height: 20 };
+        let (_, editor_area, cursor) = generate_value_editor(value, value.len() - 1, area);
+        assert_eq!(cursor, (editor_area.x + 1 + 4, editor_area.y + 2));
+    }
+
+    #[test]
+    fn value_editor_fits_a_table_too_small_for_its_borders() {
+        let value = "first\nsecond line\nthird";
+        for (width, height) in [(1, 1), (80, 1), (2, 20), (3, 3), (0, 0)] {
+            let table_area = Rect { x: 4, y: 2, width, height };
+            for cursor in [0, 8, value.len()] {
+                let (editor, area, (x, y)) = generate_value_editor(value, cursor, table_area);
+                assert_eq!(area.intersection(table_area), area);
+                assert!(x >= area.x && x <= area.x + area.width.saturating_sub(1));
+                assert!(y >= area.y && y <= area.y + area.height.saturating_sub(1));
+                let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+                terminal.draw(|f| f.render_widget(editor, area)).unwrap();
+            }
+        }
     }
 }
@@ -7,10 +7,47 @@ Options:
  -h, --help             Print this help message
  -f, --filename         Open file defined in next arg in editor
  -d, --debug            Run CSV editor in Debug mode
+ -H, --header           Treat the first row as column names
+ -b, --backup           Copy the file to a .bak file before first saving over it
+ --follow               Add records to the grid as they are written to the end
+                        of the file, keeping the cursor on the last row
+ -l, --lenient          Load a file with malformed records as far as it can be
+                        read, marking the rows with problems
  -c, --comma            Set the CSV delimiter to comma
  -t, --tab              Set the CSV delimiter to tab
  -sc, --semicolon       Set the CSV delimiter to semicolon
  -s, --space            Set the CSV delimiter to space
+ --delimiter            Set the CSV delimiter to the ASCII byte in next arg
+ --quote                Set the quote character to the ASCII byte in next arg
+ --escape               Set the character escaping quotes to the ASCII byte in
+                        next arg, instead of doubling quotes
+ --encoding             Read and save the file in the encoding named in next arg,
+                        such as latin1, windows-1252 or utf-16le
+
+Without a delimiter option, the delimiter, quote character and header row are
+detected from the start of the file. Without an encoding, files with a BOM are
+read with the encoding it marks, files which aren't valid UTF-8 as
+Windows-1252, and the file is saved in the encoding it was read with.
+
+A file with malformed records, such as a quote which is never closed, or bytes
+which aren't valid in its encoding, isn't loaded unless --lenient is given. The
+line and byte of each problem are listed instead. Records with a different
+number of fields from the others aren't malformed, and are loaded as they are.
+Rows with problems are marked with ! beside their row number.
+
+Rows with a different number of values from most rows are marked with ~ beside
+their row number, after the ! of a row which also has a problem. The :normalise
+command pads them with empty values or cuts them short to match, or to the
+number of values given with :normalise N, after listing the rows which change.
+Otherwise rows are saved with the values they have, so short rows stay short.
+
+Opening another file with :e <file> reads it with the delimiter, quote, escape,
+encoding and header row of the current file, and keeps the backup and lenient
+settings, so every value set with :set carries over instead of being detected.
+
+Line breaks in values are shown as ↵ in the grid. When editing a cell,
+Alt-Enter starts a new line, and values with more than one line are edited in
+a larger box, where Up and Down move between the lines.
 
 Examples:
  csv-tui                    Opens empty editor
@@ -18,6 +55,12 @@ Examples:
  csv-tui -f test.csv -sc    Tries to open test.csv using semicolon as delimiter
  csv-tui --tab              Opens empty editor, setting delimiter to tab
  csv-tui --debug            Opens empty editor in debug mode
+ csv-tui -f test.csv -H     Opens test.csv, showing the first row as column names
+ csv-tui -f test.psv --delimiter '|'    Opens test.psv using pipe as delimiter
+ csv-tui -f test.txt --delimiter 0x1f   Opens test.txt using the unit separator byte
+ csv-tui -f job.csv --follow            Opens job.csv, showing records as they are logged
+ csv-tui -f old.csv --encoding latin1   Opens old.csv, reading and saving it as Latin-1
+ csv-tui -f export.csv --lenient        Opens export.csv, even if some records are malformed
 ";
     println!("{}", help_text);
 }